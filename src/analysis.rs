@@ -5,9 +5,59 @@
 */
 
 use super::lut;
-use egg::{Analysis, DidMerge};
+use egg::{Analysis, DidMerge, Id, Symbol};
 #[cfg(feature = "cut_analysis")]
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A set of primary inputs, stored as a bitset over the indices interned by [LutAnalysis]
+#[cfg(feature = "cut_analysis")]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct InputSet(Vec<u64>);
+
+#[cfg(feature = "cut_analysis")]
+impl InputSet {
+    /// The set that only contains input `i`
+    pub fn singleton(i: usize) -> Self {
+        let mut words = vec![0; i / 64 + 1];
+        words[i / 64] = 1 << (i % 64);
+        Self(words)
+    }
+
+    /// Add the inputs of `other` to the set
+    pub fn union_with(&mut self, other: &Self) {
+        if other.0.len() > self.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (a, b) in self.0.iter_mut().zip(&other.0) {
+            *a |= b;
+        }
+    }
+
+    /// Returns true if the sets share an input
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.0.iter().zip(&other.0).any(|(a, b)| a & b != 0)
+    }
+
+    /// Returns the number of inputs in the set
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|w| w.count_ones() as usize).sum()
+    }
+
+    /// Returns true if the set is empty
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Returns the indices of the inputs in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().enumerate().flat_map(|(i, w)| {
+            (0..64)
+                .filter(move |b| (w >> b) & 1 == 1)
+                .map(move |b| i * 64 + b)
+        })
+    }
+}
 
 /// An e-class is typically a boolean signal.
 /// However, we store constants and input aliases for folding.
@@ -18,13 +68,20 @@ pub struct LutAnalysisData {
     program: Option<u64>,
     /// Is Some(bool) when the class is equivalent to a constant `true` or `false`
     const_val: Option<bool>,
-    /// Eventually, this should be a vector so we can store aliases
-    input: Option<String>,
+    /// The names of the inputs this class aliases
+    input: Vec<String>,
     /// The bus size of the node (if it is a bus)
     size: Option<usize>,
+    /// The smallest LUT found in this class after removing constant and invariant inputs
+    fold: Option<(u64, Vec<egg::Id>)>,
+    /// The id of the class this data was made for
+    id: Option<Id>,
     /// Dominating cut
     #[cfg(feature = "cut_analysis")]
-    cut: HashSet<String>,
+    cut: InputSet,
+    /// The function of the class over its cut (indexed by the input order of the cut, msb first), if the cut is small enough
+    #[cfg(feature = "cut_analysis")]
+    tt: Option<u64>,
 }
 
 impl LutAnalysisData {
@@ -38,22 +95,26 @@ impl LutAnalysisData {
         Self {
             program,
             const_val,
-            input,
+            input: input.into_iter().collect(),
             size,
+            fold: None,
+            id: None,
+            #[cfg(feature = "cut_analysis")]
+            cut: InputSet::default(),
             #[cfg(feature = "cut_analysis")]
-            cut: HashSet::new(),
+            tt: None,
         }
     }
 
     /// Add a cut to the class, removing the old one
     #[cfg(feature = "cut_analysis")]
-    pub fn with_cut(self, cut: HashSet<String>) -> Self {
+    pub fn with_cut(self, cut: InputSet) -> Self {
         Self { cut, ..self }
     }
 
     /// Get the cut
     #[cfg(feature = "cut_analysis")]
-    pub fn get_cut(&self) -> &HashSet<String> {
+    pub fn get_cut(&self) -> &InputSet {
         &self.cut
     }
 
@@ -64,17 +125,72 @@ impl LutAnalysisData {
         egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
         node: &lut::LutLang,
     ) -> Self {
-        let mut cut: HashSet<String> = HashSet::new();
+        let mut cut = InputSet::default();
         use egg::Language;
         for c in node.children() {
-            for e in &egraph[*c].data.cut {
-                cut.insert(e.clone());
-            }
+            cut.union_with(&egraph[*c].data.cut);
         }
 
         Self { cut, ..self }
     }
 
+    /// Get the truth table of the class along with the cut it is over (msb first), named by `analysis`.
+    /// Only classes of combinational logic with at most [LutAnalysis::get_cut_size] inputs have one.
+    #[cfg(feature = "cut_analysis")]
+    pub fn get_truth_table(&self, analysis: &LutAnalysis) -> Option<(u64, Vec<Symbol>)> {
+        self.tt.map(|tt| (tt, analysis.cut_inputs(&self.cut)))
+    }
+
+    /// Compute the truth table of `node` over the cut of the class from the truth tables of its children
+    #[cfg(feature = "cut_analysis")]
+    fn with_truth_table(
+        self,
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        node: &lut::LutLang,
+    ) -> Self {
+        if self.cut.len() > egraph.analysis.get_cut_size() {
+            return self;
+        }
+
+        let n = self.cut.len();
+        let child = |c: &Id| {
+            let d = &egraph[*c].data;
+            d.tt.map(|tt| expand_tt(tt, &d.cut, &self.cut))
+        };
+        let tt = match node {
+            lut::LutLang::Const(b) => Some(if *b { u64::MAX } else { 0 }),
+            lut::LutLang::Var(_) | lut::LutLang::Arg(_) => Some(2),
+            lut::LutLang::Not([a]) => child(a).map(|a| !a),
+            lut::LutLang::And([a, b]) => child(a).zip(child(b)).map(|(a, b)| a & b),
+            lut::LutLang::Nor([a, b]) => child(a).zip(child(b)).map(|(a, b)| !(a | b)),
+            lut::LutLang::Xor([a, b]) => child(a).zip(child(b)).map(|(a, b)| a ^ b),
+            lut::LutLang::Mux([s, a, b]) => match (child(s), child(a), child(b)) {
+                (Some(s), Some(a), Some(b)) => Some((s & a) | (!s & b)),
+                _ => None,
+            },
+            lut::LutLang::Lut(l) => {
+                let p = egraph[l[0]].data.program;
+                let operands: Option<Vec<u64>> = l[1..].iter().map(child).collect();
+                p.zip(operands).map(|(p, operands)| {
+                    let mut tt = 0;
+                    for i in 0..(1 << n) {
+                        let index = operands
+                            .iter()
+                            .fold(0, |index, o| (index << 1) | ((o >> i) & 1));
+                        tt |= ((p >> index) & 1) << i;
+                    }
+                    tt
+                })
+            }
+            _ => None,
+        };
+
+        Self {
+            tt: tt.map(|tt| tt & tt_mask(n)),
+            ..self
+        }
+    }
+
     /// Extract the LUT program in this class. If it is an input or gate, throw an error
     pub fn get_program(&self) -> Result<u64, String> {
         match self.program {
@@ -93,42 +209,229 @@ impl LutAnalysisData {
 
     /// Returns true if the class is an input
     pub fn is_an_input(&self) -> bool {
-        self.input.is_some()
+        !self.input.is_empty()
+    }
+
+    /// Returns the names of the inputs aliased by this class
+    pub fn get_inputs(&self) -> &[String] {
+        &self.input
+    }
+
+    /// Partially evaluate the LUT with operands `l` on its constant inputs and drop any trailing inputs it is invariant to.
+    /// The result is either a constant or a smaller LUT to be inserted by [LutAnalysis::modify].
+    fn fold_lut(egraph: &egg::EGraph<lut::LutLang, LutAnalysis>, l: &[egg::Id]) -> Self {
+        let mut d = Self::default();
+        let mut p = match egraph[l[0]].data.get_program() {
+            Ok(p) => p,
+            Err(_) => return d,
+        };
+        let mut operands = l[1..].to_vec();
+        let mut folded = false;
+
+        let mut pos = 0;
+        while pos < operands.len() {
+            match egraph[operands[pos]].data.const_val {
+                Some(v) => {
+                    p = lut::eval_lut_const_at(p, operands.len(), pos, v);
+                    operands.remove(pos);
+                    folded = true;
+                }
+                None => pos += 1,
+            }
+        }
+
+        while let Some(np) = lut::remove_lsb_var(p, operands.len()) {
+            p = np;
+            operands.pop();
+            folded = true;
+        }
+
+        let k = operands.len();
+        if k == 0 || p == 0 {
+            d.const_val = Some(k == 0 && p & 1 == 1);
+        } else if k == 1 && p == 3 {
+            d.const_val = Some(true);
+        } else if folded {
+            d.fold = Some((p, operands));
+        }
+        d
     }
 }
 
 /// The analysis struct allows for discovering when signals are equivalent to constants or leaf inputs.
-/// Additonally, the struct assists in folding constant inputs to smaller LUTs.
+/// Additonally, the struct folds constant inputs into smaller LUTs. Folding is incremental:
+/// a LUT is only re-evaluated when the data of one of its operand classes changes.
 #[derive(Default, Debug, Clone)]
-pub struct LutAnalysis;
+pub struct LutAnalysis {
+    /// The rule currently being applied
+    rule: Option<Symbol>,
+    /// The unsound merges found so far
+    unsound: Vec<Unsoundness>,
+    /// The most inputs a class can have to get a truth table
+    #[cfg(feature = "cut_analysis")]
+    cut_size: Option<usize>,
+    /// Insert the single LUT of a class with a small enough cut
+    #[cfg(feature = "cut_analysis")]
+    collapse: bool,
+    /// The names of the primary inputs, by their index in an [InputSet]
+    #[cfg(feature = "cut_analysis")]
+    inputs: Vec<Symbol>,
+    /// The index of each primary input in an [InputSet]
+    #[cfg(feature = "cut_analysis")]
+    input_ids: HashMap<Symbol, usize>,
+}
+
+impl LutAnalysis {
+    /// Only compute truth tables for classes with at most `k` inputs.
+    /// `k` is capped at [lut::LutLang::MAX_LUT_SIZE].
+    #[cfg(feature = "cut_analysis")]
+    pub fn with_cut_size(self, k: usize) -> Self {
+        Self {
+            cut_size: Some(k.min(lut::LutLang::MAX_LUT_SIZE)),
+            ..self
+        }
+    }
+
+    /// Collapse every class with at most `k` inputs into a single LUT
+    #[cfg(feature = "cut_analysis")]
+    pub fn with_cut_collapse(self, k: usize) -> Self {
+        Self {
+            collapse: true,
+            ..self.with_cut_size(k)
+        }
+    }
+
+    /// Returns the most inputs a class can have to get a truth table
+    #[cfg(feature = "cut_analysis")]
+    pub fn get_cut_size(&self) -> usize {
+        self.cut_size.unwrap_or(lut::LutLang::MAX_LUT_SIZE)
+    }
+
+    /// Returns the index of primary input `name` in an [InputSet]
+    #[cfg(feature = "cut_analysis")]
+    fn intern(&mut self, name: Symbol) -> usize {
+        *self.input_ids.entry(name).or_insert_with(|| {
+            self.inputs.push(name);
+            self.inputs.len() - 1
+        })
+    }
+
+    /// Returns the names of the inputs in `cut`, in the order of the variables of a truth table over it
+    #[cfg(feature = "cut_analysis")]
+    pub fn cut_inputs(&self, cut: &InputSet) -> Vec<Symbol> {
+        cut.iter().map(|i| self.inputs[i]).collect()
+    }
+
+    fn report(&mut self, reason: &str, to: &LutAnalysisData, from: &LutAnalysisData) {
+        self.unsound.push(Unsoundness {
+            reason: reason.to_string(),
+            classes: (to.id, from.id),
+            rule: self.rule.map(|r| r.to_string()),
+        });
+    }
+}
+
+impl LutAnalysis {
+    /// Build the single LUT equivalent to class `id` out of the inputs its function depends on
+    #[cfg(feature = "cut_analysis")]
+    fn collapse_cut(egraph: &mut egg::EGraph<lut::LutLang, Self>, id: Id) -> Option<Id> {
+        let (mut tt, mut vars) = egraph[id].data.get_truth_table(&egraph.analysis)?;
+
+        // Drop the inputs the function does not depend on
+        let mut pos = 0;
+        while pos < vars.len() {
+            let k = vars.len();
+            let lo = lut::eval_lut_const_at(tt, k, pos, false);
+            if lo == lut::eval_lut_const_at(tt, k, pos, true) {
+                tt = lo;
+                vars.remove(pos);
+            } else {
+                pos += 1;
+            }
+        }
+
+        if vars.is_empty() {
+            return Some(egraph.add(lut::LutLang::Const(tt & 1 == 1)));
+        }
+
+        let mut operands = Vec::with_capacity(vars.len() + 1);
+        for v in &vars {
+            let leaf = match egraph.lookup(lut::LutLang::Var(*v)) {
+                Some(leaf) => leaf,
+                None => {
+                    let index = v.as_str().strip_prefix("arg")?.parse().ok()?;
+                    let index = egraph.lookup(lut::LutLang::Program(index))?;
+                    egraph.lookup(lut::LutLang::Arg([index]))?
+                }
+            };
+            operands.push(leaf);
+        }
+
+        if vars.len() == 1 && tt == 2 {
+            return Some(operands[0]);
+        }
+
+        operands.insert(0, egraph.add(lut::LutLang::Program(tt)));
+        Some(egraph.add(lut::LutLang::Lut(operands.into())))
+    }
+}
+
 impl Analysis<lut::LutLang> for LutAnalysis {
     type Data = LutAnalysisData;
-    fn merge(&mut self, to: &mut Self::Data, from: Self::Data) -> DidMerge {
+    fn merge(&mut self, to: &mut Self::Data, mut from: Self::Data) -> DidMerge {
         if to.program != from.program {
-            panic!("Tried to merge two different programs");
+            self.report("Tried to merge two different programs", to, &from);
         }
         if to.size != from.size {
-            panic!("Tried to merge two conflicting bus sizes");
-        }
-        if !(to.const_val == from.const_val || to.const_val.is_none() || from.const_val.is_none()) {
-            // Later we will want to relax this condition for constant folding.
-            // For now, it is a good sanity check.
-            panic!("Cannot merge constant type with non-constant type.");
+            self.report("Tried to merge two conflicting bus sizes", to, &from);
         }
-        if !(to.input == from.input || to.input.is_none() || from.input.is_none()) {
-            // Later, we will want to relax this condition when we're okay with input aliasing.
-            panic!("Cannot merge input type with non-input type.");
+        if let (Some(a), Some(b)) = (to.const_val, from.const_val)
+            && a != b
+        {
+            self.report(
+                "Tried to merge constant true with constant false",
+                to,
+                &from,
+            );
         }
-        let mut merged = to.clone();
-        merged.const_val = from.const_val.or(to.const_val);
-        merged.input = from.input.clone().or(to.input.clone());
+        // The ids only identify classes in error messages
+        from.id = to.id;
 
         // Rewrite rules can create redundant logic, so we need to track the current cut.
         // If we took the intersection, we would not have that info. So we take the union.
+        let mut merged = to.clone();
+        merged.const_val = to.const_val.or(from.const_val);
         #[cfg(feature = "cut_analysis")]
-        from.cut.iter().for_each(|x| {
-            merged.cut.insert(x.clone());
-        });
+        {
+            merged.cut.union_with(&from.cut);
+            merged.tt = None;
+            // Truth tables treat aliased inputs as independent, so they can't be compared
+            let aliased = !to.input.is_empty() && !from.input.is_empty() && to.input != from.input;
+            if !aliased && merged.cut.len() <= self.get_cut_size() {
+                let expand =
+                    |d: &LutAnalysisData| d.tt.map(|tt| expand_tt(tt, &d.cut, &merged.cut));
+                merged.tt = match (expand(to), expand(&from)) {
+                    (Some(a), Some(b)) if a != b => {
+                        self.report("Tried to merge two different functions", to, &from);
+                        Some(a)
+                    }
+                    (a, b) => a.or(b),
+                };
+            }
+        }
+        // Primary inputs proven equal become aliases of one another
+        for i in &from.input {
+            if !merged.input.contains(i) {
+                merged.input.push(i.clone());
+            }
+        }
+        merged.input.sort();
+        // Keep the smallest fold, so that re-evaluating a LUT does not register as a change
+        merged.fold = match (&to.fold, &from.fold) {
+            (Some((_, t)), Some((_, f))) if f.len() < t.len() => from.fold.clone(),
+            (None, _) => from.fold.clone(),
+            _ => to.fold.clone(),
+        };
 
         let merged_to = merged != *to;
         *to = merged;
@@ -137,16 +440,16 @@ impl Analysis<lut::LutLang> for LutAnalysis {
     fn make(
         egraph: &mut egg::EGraph<lut::LutLang, Self>,
         enode: &lut::LutLang,
-        _id: egg::Id,
+        id: egg::Id,
     ) -> Self::Data {
-        match enode {
+        let d = match enode {
             lut::LutLang::Program(p) => LutAnalysisData::new(Some(*p), None, None, None),
             lut::LutLang::Const(c) => LutAnalysisData::new(None, Some(*c), None, None),
             lut::LutLang::Var(v) => {
                 let d = LutAnalysisData::new(None, None, Some(v.to_string()), None);
 
                 #[cfg(feature = "cut_analysis")]
-                let d = d.with_cut(HashSet::from([v.to_string()]));
+                let d = d.with_cut(InputSet::singleton(egraph.analysis.intern(*v)));
 
                 d
             }
@@ -159,11 +462,19 @@ impl Analysis<lut::LutLang> for LutAnalysis {
                 let d = LutAnalysisData::new(None, None, Some(name.clone()), None);
 
                 #[cfg(feature = "cut_analysis")]
-                let d = d.with_cut(HashSet::from([name]));
+                let d = d.with_cut(InputSet::singleton(egraph.analysis.intern(name.into())));
 
                 d
             }
             lut::LutLang::Bus(b) => LutAnalysisData::new(None, None, None, Some(b.len())),
+            lut::LutLang::Lut(l) => {
+                let d = LutAnalysisData::fold_lut(egraph, l);
+
+                #[cfg(feature = "cut_analysis")]
+                let d = d.merge_cut(egraph, enode);
+
+                d
+            }
             _ => {
                 let d = LutAnalysisData::default();
 
@@ -172,67 +483,110 @@ impl Analysis<lut::LutLang> for LutAnalysis {
 
                 d
             }
-        }
+        };
+
+        #[cfg(feature = "cut_analysis")]
+        let d = d.with_truth_table(egraph, enode);
+
+        LutAnalysisData { id: Some(id), ..d }
     }
-    #[cfg(feature = "egraph_fold")]
     fn modify(egraph: &mut egg::EGraph<lut::LutLang, Self>, id: egg::Id) {
-        let nodes = egraph[id].nodes.clone();
-        for node in nodes {
-            if let lut::LutLang::Lut(_) = node {
-                let operands = node.get_operand_classes(egraph).expect("Expected operands");
-                let msb_const = egraph[operands[0]].data.get_as_const();
-                let program = node
-                    .get_program_in_egraph(egraph)
-                    .expect("Expected program");
-                let k = operands.len();
-
-                // Refactor LUT invariant to input at lsb
-                if let Some(np) = lut::remove_lsb_var(program, k) {
-                    let mut c = operands.clone();
-                    let pi = egraph.add(lut::LutLang::Program(np));
-                    c.pop();
-                    c.insert(0, pi);
-                    let repl = egraph.add(lut::LutLang::Lut(c.into()));
-                    egraph.union(id, repl);
-                }
+        if let Some(b) = egraph[id].data.const_val {
+            let c = egraph.add(lut::LutLang::Const(b));
+            egraph.union(id, c);
+        }
 
-                // Evaluate constant input at the msb
-                if msb_const.is_ok() {
-                    if operands.len() > 1 {
-                        let mod_program = lut::eval_lut_const_input(
-                            &program,
-                            operands.len() - 1,
-                            msb_const.unwrap(),
-                        );
-                        let pi = egraph.add(lut::LutLang::Program(mod_program));
-                        let mut c = operands.clone();
-                        c[0] = pi;
-                        let repl = egraph.add(lut::LutLang::Lut(c.into()));
-                        egraph.union(id, repl);
-                    } else {
-                        let const_val = msb_const.unwrap();
-                        match program & 3 {
-                            0 => {
-                                let repl = egraph.add(lut::LutLang::Const(false));
-                                egraph.union(id, repl);
-                            }
-                            3 => {
-                                let repl = egraph.add(lut::LutLang::Const(true));
-                                egraph.union(id, repl);
-                            }
-                            2 => {
-                                let repl = egraph.add(lut::LutLang::Const(const_val));
-                                egraph.union(id, repl);
-                            }
-                            1 => {
-                                let repl = egraph.add(lut::LutLang::Const(!const_val));
-                                egraph.union(id, repl);
-                            }
-                            _ => unreachable!(),
-                        }
-                    }
-                }
-            }
+        if let Some((p, mut operands)) = egraph[id].data.fold.clone() {
+            let pi = egraph.add(lut::LutLang::Program(p));
+            operands.insert(0, pi);
+            let repl = egraph.add(lut::LutLang::Lut(operands.into()));
+            egraph.union(id, repl);
+        }
+
+        #[cfg(feature = "cut_analysis")]
+        if egraph.analysis.collapse
+            && let Some(repl) = Self::collapse_cut(egraph, id)
+        {
+            egraph.union(id, repl);
+        }
+    }
+}
+
+/// Returns the mask of the valid bits of an `n`-input truth table
+#[cfg(feature = "cut_analysis")]
+fn tt_mask(n: usize) -> u64 {
+    if n >= 6 {
+        u64::MAX
+    } else {
+        (1 << (1 << n)) - 1
+    }
+}
+
+/// Re-index the truth table `tt` over the inputs `from` to the superset of inputs `to`
+#[cfg(feature = "cut_analysis")]
+fn expand_tt(tt: u64, from: &InputSet, to: &InputSet) -> u64 {
+    if from == to {
+        return tt;
+    }
+
+    let to: Vec<usize> = to.iter().collect();
+    let n = to.len();
+    let m = from.len();
+    let pos: Vec<usize> = from.iter().map(|i| to.binary_search(&i).unwrap()).collect();
+    let mut r = 0;
+    for i in 0..(1 << n) {
+        let mut index = 0;
+        for (j, p) in pos.iter().enumerate() {
+            index |= ((i >> (n - 1 - p)) & 1) << (m - 1 - j);
         }
+        r |= ((tt >> index) & 1) << i;
+    }
+    r
+}
+
+/// An analysis that records unsound merges of e-classes instead of panicking
+pub trait SoundnessCheck {
+    /// Attribute the following merges to `rule`, or to rebuilding when `None`
+    fn blame(&mut self, _rule: Option<Symbol>) {}
+
+    /// Returns the unsound merges found so far
+    fn unsound_merges(&self) -> &[Unsoundness] {
+        &[]
+    }
+}
+
+impl SoundnessCheck for LutAnalysis {
+    fn blame(&mut self, rule: Option<Symbol>) {
+        self.rule = rule;
+    }
+
+    fn unsound_merges(&self) -> &[Unsoundness] {
+        &self.unsound
+    }
+}
+
+/// A merge of two e-classes with incompatible analysis data.
+/// This is usually caused by an unsound rewrite rule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsoundness {
+    /// Why the merge is unsound
+    pub reason: String,
+    /// The e-classes that were merged
+    pub classes: (Option<Id>, Option<Id>),
+    /// The rule that caused the union. It is `None` for unions found while rebuilding.
+    pub rule: Option<String>,
+}
+
+impl fmt::Display for Unsoundness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let class = |c: Option<Id>| c.map_or("?".to_string(), |c| c.to_string());
+        write!(
+            f,
+            "{} (e-classes {} and {}, rule {})",
+            self.reason,
+            class(self.classes.0),
+            class(self.classes.1),
+            self.rule.as_deref().unwrap_or("<rebuild>")
+        )
     }
 }
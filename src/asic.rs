@@ -4,11 +4,14 @@
 
 */
 
+use super::analysis::SoundnessCheck;
 use super::check::Check;
-use super::cost::GateCostFn;
+use super::cost::{DagCost, GateCostFn};
 use super::driver::Comparison;
-use super::driver::Report;
 use super::driver::{Canonical, CircuitLang, EquivCheck, Explanable, Extractable};
+use super::driver::{Report, RuleStats};
+use super::power::{PowerCostFn, SwitchingActivity};
+use super::timing::{TimingCostFn, TimingModel};
 use super::verilog::PrimitiveType;
 use egg::{
     Analysis, CostFunction, DidMerge, EGraph, Id, Language, RecExpr, Rewrite, Symbol,
@@ -76,6 +79,7 @@ impl CostFunction<CellLang> for DepthCostFn {
         let op_cost = match enode {
             CellLang::Const(_) => 0,
             CellLang::Var(_) => 0,
+            CellLang::Bus(_) => 0,
             CellLang::Cell(_, _) => 1,
             _ => i64::MAX,
         };
@@ -151,17 +155,44 @@ impl Extractable for CellLang {
         DepthCostFn
     }
 
-    fn cell_cost_with_reg_weight_fn(cut_size: usize, _w: u64) -> impl CostFunction<Self> {
+    fn cell_cost_with_reg_weight_fn(
+        cut_size: usize,
+        _w: u64,
+    ) -> impl CostFunction<Self, Cost: DagCost> {
         CellCountFn::new(cut_size)
     }
 
-    fn exact_area_cost_fn() -> impl CostFunction<Self> {
+    fn exact_area_cost_fn() -> impl CostFunction<Self, Cost: DagCost> {
         AreaFn
     }
 
-    fn filter_cost_fn(set: std::collections::HashSet<String>) -> impl CostFunction<Self> {
+    fn filter_cost_fn(
+        set: std::collections::HashSet<String>,
+    ) -> impl CostFunction<Self, Cost: DagCost> {
         GateCostFn::new(set)
     }
+
+    fn timing_cost_fn(cut_size: usize, model: TimingModel) -> impl CostFunction<Self, Cost = f32> {
+        TimingCostFn::new(model).with_cut_size(cut_size)
+    }
+
+    fn power_cost_fn(
+        cut_size: usize,
+        activity: SwitchingActivity<Self>,
+    ) -> impl CostFunction<Self, Cost = f32> {
+        PowerCostFn::new(AreaFn, cut_size, activity)
+    }
+
+    fn exceeds_cut_size(&self, cut_size: usize) -> bool {
+        matches!(self, CellLang::Cell(_, l) if l.len() > cut_size)
+    }
+
+    fn is_logic(&self) -> bool {
+        matches!(
+            self,
+            CellLang::Cell(_, _) | CellLang::And(_) | CellLang::Or(_) | CellLang::Inv(_)
+        )
+    }
 }
 
 impl Canonical for CellLang {
@@ -214,6 +245,10 @@ impl CircuitLang for CellLang {
         None
     }
 
+    fn lut(_ids: impl Iterator<Item = egg::Id>) -> Option<Self> {
+        None
+    }
+
     fn is_bus(&self) -> bool {
         matches!(self, Self::Bus(_))
     }
@@ -232,6 +267,16 @@ impl CircuitLang for CellLang {
             _ => None,
         }
     }
+
+    fn simulate(&self, c: &[u64]) -> Option<u64> {
+        match self {
+            Self::Const(b) => Some(if *b { u64::MAX } else { 0 }),
+            Self::And(_) => Some(c[0] & c[1]),
+            Self::Or(_) => Some(c[0] | c[1]),
+            Self::Inv(_) => Some(!c[0]),
+            _ => None,
+        }
+    }
 }
 
 /// An empty analysis for CellLang
@@ -247,6 +292,8 @@ impl Analysis<CellLang> for CellAnalysis {
     fn make(_egraph: &mut EGraph<CellLang, Self>, _enode: &CellLang, _id: egg::Id) -> Self::Data {}
 }
 
+impl SoundnessCheck for CellAnalysis {}
+
 #[derive(Debug, Serialize)]
 struct CircuitStats {
     /// AST size of the circuit
@@ -306,6 +353,21 @@ pub struct CellRpt {
     name: String,
     /// Comparison of the original and mapped circuit
     stats: Comparison<CircuitStats>,
+    /// How often every rule matched and grew the e-graph
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    rule_stats: BTreeMap<String, RuleStats>,
+    /// The relative optimality gap of an exact extraction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optimality_gap: Option<f64>,
+    /// The slack of every output before and after mapping
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slack: Option<Comparison<BTreeMap<String, f32>>>,
+    /// The seed of a random extraction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    /// The max fanout of any logic before and after a fanout-limited extraction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_fanout: Option<Comparison<usize>>,
 }
 
 impl CellRpt {
@@ -314,6 +376,11 @@ impl CellRpt {
         Self {
             name,
             stats: Comparison::new(before, after),
+            rule_stats: BTreeMap::new(),
+            optimality_gap: None,
+            slack: None,
+            seed: None,
+            max_fanout: None,
         }
     }
 }
@@ -341,6 +408,38 @@ impl Report<CellLang> for CellRpt {
             ..self
         }
     }
+
+    fn with_rule_stats(self, rule_stats: BTreeMap<String, RuleStats>) -> Self {
+        Self { rule_stats, ..self }
+    }
+
+    fn with_optimality_gap(self, gap: f64) -> Self {
+        Self {
+            optimality_gap: Some(gap),
+            ..self
+        }
+    }
+
+    fn with_slack(self, slack: Comparison<BTreeMap<String, f32>>) -> Self {
+        Self {
+            slack: Some(slack),
+            ..self
+        }
+    }
+
+    fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    fn with_max_fanout(self, fanout: Comparison<usize>) -> Self {
+        Self {
+            max_fanout: Some(fanout),
+            ..self
+        }
+    }
 }
 
 /// Returns true if the logic is fully mapped to cells
@@ -3,8 +3,11 @@ use clap::ValueEnum;
 use eqmap::{
     asic::{CellAnalysis, CellLang, CellRpt, expansion_rewrites, expr_is_mapped},
     driver::{SynthRequest, logger_init, process_expression},
+    fanout::FanoutLimit,
     netlist::{LogicMapper, PrimitiveCell},
+    power::ActivityModel,
     rewrite::RewriteManager,
+    timing::TimingModel,
     verilog::sv_parse_wrapper,
 };
 use log::{debug, info, warn};
@@ -33,6 +36,7 @@ enum PartitionMethod {
 /// ASIC Technology Mapping Optimization with E-Graphs
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
+#[command(group(clap::ArgGroup::new("stochastic").args(["random", "local_search"]).multiple(true)))]
 struct Args {
     /// Verilog file to read from (or use stdin)
     input: Option<PathBuf>,
@@ -48,6 +52,14 @@ struct Args {
     #[arg(long)]
     rules: Option<PathBuf>,
 
+    /// Comma separated list of rule categories (or rules) to enable
+    #[arg(long, value_delimiter = ',')]
+    enable: Vec<String>,
+
+    /// Comma separated list of rule categories (or rules) to disable
+    #[arg(long, value_delimiter = ',')]
+    disable: Vec<String>,
+
     /// If provided, output a condensed JSON file with the e-graph
     #[cfg(feature = "graph_dumps")]
     #[arg(long)]
@@ -79,13 +91,53 @@ struct Args {
     verbose: bool,
 
     /// Extract for minimum circuit depth
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["dag", "local_search"])]
     min_depth: bool,
 
     /// Extract randomly
     #[arg(long, default_value_t = false)]
     random: bool,
 
+    /// Seed the random extraction or local search to make it reproducible
+    #[arg(long, requires = "stochastic")]
+    seed: Option<u64>,
+
+    /// Keep the best of this many random extractions by the cost model
+    #[arg(long, requires = "random", default_value_t = 1)]
+    samples: usize,
+
+    /// Extract a DAG, counting cells shared by several fanouts once
+    #[arg(long, default_value_t = false, conflicts_with = "random")]
+    dag: bool,
+
+    /// Refine the extraction by simulated annealing for this many seconds, counting shared cells once
+    #[arg(long, conflicts_with_all = ["random", "dag"])]
+    local_search: Option<u64>,
+
+    /// Extract for the worst slack under the arrival, required, and delay times in this file
+    #[arg(long, conflicts_with_all = ["min_depth", "random", "dag", "local_search"])]
+    timing: Option<PathBuf>,
+
+    /// Extract the fewest cells such that the circuit depth is at most this bound
+    #[arg(long, conflicts_with_all = ["min_depth", "random", "timing", "dag", "local_search"])]
+    depth_bound: Option<usize>,
+
+    /// Extract for the least dynamic power, weighing every cell by its area times the toggle rate of its output
+    #[arg(long, default_value_t = false, conflicts_with_all = ["min_depth", "random", "timing", "depth_bound"])]
+    power: bool,
+
+    /// Probability that each input is one when estimating the switching activity
+    #[arg(long, requires = "power", default_value_t = 0.5)]
+    input_probability: f64,
+
+    /// Duplicate logic so that every cell or gate drives at most this many loads
+    #[arg(long)]
+    max_fanout: Option<usize>,
+
+    /// Only duplicate logic when it is cheaper than this penalty for every load over the max fanout
+    #[arg(long, requires = "max_fanout")]
+    fanout_penalty: Option<f64>,
+
     /// Max fan in size allowed for extracted Cells
     #[arg(short = 'k', long, default_value_t = 6)]
     k: usize,
@@ -175,6 +227,10 @@ fn main() -> std::io::Result<()> {
         rules.enable_category("expansion_rewrites");
     }
 
+    rules
+        .select(&args.enable, &args.disable)
+        .map_err(std::io::Error::other)?;
+
     debug!(
         "Running with {} rewrite rules. Hash: {}",
         rules.num_active(),
@@ -183,6 +239,25 @@ fn main() -> std::io::Result<()> {
 
     let rules = rules.active_rules();
 
+    info!("Compiling Verilog...");
+    let mut mapper = f
+        .get_analysis::<LogicMapper<CellLang, PrimitiveCell>>()
+        .map_err(std::io::Error::other)?;
+
+    match args.partition {
+        PartitionMethod::R2R => {
+            mapper.insert_all_r2r().map_err(std::io::Error::other)?;
+        }
+        PartitionMethod::ArcSet => {
+            mapper.insert_partitioned().map_err(std::io::Error::other)?;
+        }
+        PartitionMethod::DelayPaths => todo!("Implement delay-based partitioning"),
+    }
+
+    let mut mapping = mapper.mappings();
+    let mapping = mapping.pop().unwrap();
+    let expr = mapping.get_expr();
+
     let req = SynthRequest::default().with_rules(rules);
 
     let req = match (args.timeout, args.node_limit, args.iter_limit) {
@@ -219,14 +294,50 @@ fn main() -> std::io::Result<()> {
             .map_err(std::io::Error::other)?
     } else if args.min_depth {
         req.with_min_depth()
-    } else if args.random {
-        req.with_randomness()
+    } else if let Some(p) = &args.timing {
+        let model = TimingModel::parse(std::fs::File::open(p)?).map_err(std::io::Error::other)?;
+        let outputs = mapping
+            .root_nets()
+            .map(|n| n.get_identifier().to_string())
+            .collect();
+        req.with_timing(args.k, model.with_output_names(outputs))
+    } else if let Some(d) = args.depth_bound {
+        req.with_depth_bound(args.k, d)
+    } else if args.power {
+        let model = ActivityModel::new(args.input_probability).map_err(std::io::Error::other)?;
+        req.with_power(args.k, model)
     } else if args.area {
         req.with_area()
     } else {
         req.with_k(args.k)
     };
 
+    let req = if args.random {
+        req.with_random_samples(args.seed, args.samples)
+    } else {
+        req
+    };
+
+    let req = if args.dag {
+        req.with_dag_extraction()
+    } else {
+        req
+    };
+
+    let req = match args.local_search {
+        Some(t) => req.with_local_search(t, args.seed),
+        None => req,
+    };
+
+    let req = match (args.max_fanout, args.fanout_penalty) {
+        (Some(0), _) => {
+            return Err(std::io::Error::other("The max fanout must be at least 1"));
+        }
+        (Some(m), Some(p)) => req.with_fanout_limit(FanoutLimit::new(m).with_penalty(p)),
+        (Some(m), None) => req.with_fanout_limit(FanoutLimit::new(m)),
+        _ => req,
+    };
+
     #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
     let req = if let Some(solver) = &args.exact {
         let timeout = args.timeout.unwrap_or(600);
@@ -248,25 +359,6 @@ fn main() -> std::io::Result<()> {
         ));
     }
 
-    info!("Compiling Verilog...");
-    let mut mapper = f
-        .get_analysis::<LogicMapper<CellLang, PrimitiveCell>>()
-        .map_err(std::io::Error::other)?;
-
-    match args.partition {
-        PartitionMethod::R2R => {
-            mapper.insert_all_r2r().map_err(std::io::Error::other)?;
-        }
-        PartitionMethod::ArcSet => {
-            mapper.insert_partitioned().map_err(std::io::Error::other)?;
-        }
-        PartitionMethod::DelayPaths => todo!("Implement delay-based partitioning"),
-    }
-
-    let mut mapping = mapper.mappings();
-    let mapping = mapping.pop().unwrap();
-    let expr = mapping.get_expr();
-
     info!("Building e-graph...");
     let result = process_expression::<CellLang, _, CellRpt>(expr, req, true)?
         .with_name(f.get_name().as_str());
@@ -1,20 +1,26 @@
 use clap::Parser;
 use clap::ValueEnum;
-#[cfg(feature = "dyn_decomp")]
-use eqmap::rewrite::dyn_decompositions;
 use eqmap::{
-    driver::{SynthReport, SynthRequest, logger_init, process_expression},
+    analysis::LutAnalysis,
+    driver::{
+        Extractable, SynthReport, SynthRequest, logger_init, process_expression,
+        process_pareto_front,
+    },
+    fanout::FanoutLimit,
     lut::LutLang,
-    netlist::{LogicMapper, PrimitiveCell},
-    rewrite::{all_static_rules, register_retiming},
+    netlist::{LogicMapper, LogicMapping, PrimitiveCell},
+    power::ActivityModel,
+    rewrite::RewriteManager,
+    timing::TimingModel,
     verilog::sv_parse_wrapper,
 };
 use log::{debug, info, warn};
 use nl_compiler::from_vast_overrides;
-use safety_net::Identifier;
+use safety_net::{Identifier, Netlist};
 use std::{
     io::{Read, Write, stderr, stdin},
-    path::PathBuf,
+    path::{Path, PathBuf},
+    rc::Rc,
 };
 
 #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
@@ -36,6 +42,7 @@ enum PartitionMethod {
 /// EqMap: FPGA Technology Mapping w/ E-Graphs
 #[derive(Parser, Debug)]
 #[command(version, long_about = None)]
+#[command(group(clap::ArgGroup::new("stochastic").args(["random", "local_search"]).multiple(true)))]
 struct Args {
     /// Verilog file to read from (or use stdin)
     input: Option<PathBuf>,
@@ -47,6 +54,10 @@ struct Args {
     #[arg(long)]
     report: Option<PathBuf>,
 
+    /// If provided, use rules compiled from file instead of built-in rules
+    #[arg(long)]
+    rules: Option<PathBuf>,
+
     /// If provided, output a condensed JSON file with the e-graph
     #[cfg(feature = "graph_dumps")]
     #[arg(long)]
@@ -71,9 +82,14 @@ struct Args {
 
     /// Comma separated list of cell types to decompose into
     #[cfg(feature = "dyn_decomp")]
-    #[arg(long)]
+    #[arg(long, conflicts_with = "rules")]
     disassemble: Option<String>,
 
+    /// Collapse every e-class with at most k inputs into a single LUT
+    #[cfg(feature = "dyn_decomp")]
+    #[arg(long, default_value_t = false, conflicts_with = "disassemble")]
+    collapse_cuts: bool,
+
     /// Perform an exact extraction using ILP (much slower)
     #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
     #[arg(long, value_enum)]
@@ -83,18 +99,102 @@ struct Args {
     #[arg(long, value_enum, default_value_t = PartitionMethod::ArcSet)]
     partition: PartitionMethod,
 
+    /// Comma separated list of rule categories (or rules) to enable
+    #[arg(long, value_delimiter = ',')]
+    enable: Vec<String>,
+
+    /// Comma separated list of rule categories (or rules) to disable
+    #[arg(long, value_delimiter = ',')]
+    disable: Vec<String>,
+
     /// Print explanations (generates a proof and runs slower)
     #[arg(short = 'v', long, default_value_t = false)]
     verbose: bool,
 
     /// Extract for minimum circuit depth
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["dag", "local_search"])]
     min_depth: bool,
 
     /// Extract randomly
     #[arg(long, default_value_t = false)]
     random: bool,
 
+    /// Seed the random extraction or local search to make it reproducible
+    #[arg(long, requires = "stochastic")]
+    seed: Option<u64>,
+
+    /// Keep the best of this many random extractions by the cost model
+    #[arg(long, requires = "random", default_value_t = 1)]
+    samples: usize,
+
+    /// Extract a DAG, counting LUTs shared by several fanouts once
+    #[arg(long, default_value_t = false, conflicts_with = "random")]
+    dag: bool,
+
+    /// Refine the extraction by simulated annealing for this many seconds, counting shared LUTs once
+    #[arg(long, conflicts_with_all = ["random", "dag", "pareto"])]
+    local_search: Option<u64>,
+
+    /// Extract for the worst slack under the arrival, required, and delay times in this file
+    #[arg(long, conflicts_with_all = ["min_depth", "random", "dag", "local_search"])]
+    timing: Option<PathBuf>,
+
+    /// Extract the fewest LUTs such that the circuit depth is at most this bound
+    #[arg(long, conflicts_with_all = ["min_depth", "random", "timing", "dag", "local_search"])]
+    depth_bound: Option<usize>,
+
+    /// Extract for the least dynamic power, weighing every LUT by the toggle rate of its output
+    #[arg(long, default_value_t = false, conflicts_with_all = ["min_depth", "random", "timing", "depth_bound"])]
+    power: bool,
+
+    /// Probability that each input is one when estimating the switching activity
+    #[arg(long, requires = "power", default_value_t = 0.5)]
+    input_probability: f64,
+
+    /// Duplicate logic so that every LUT or gate drives at most this many loads
+    #[arg(long, conflicts_with = "pareto")]
+    max_fanout: Option<usize>,
+
+    /// Only duplicate logic when it is cheaper than this penalty for every load over the max fanout
+    #[arg(long, requires = "max_fanout")]
+    fanout_penalty: Option<f64>,
+
+    /// Extract every circuit on the LUT count/depth Pareto front and report all of them
+    #[arg(
+        long,
+        default_value_t = false,
+        conflicts_with_all = ["min_depth", "random", "timing", "depth_bound", "power", "dag"]
+    )]
+    pareto: bool,
+
+    /// Emit this point of the Pareto front, counting from the least depth (defaults to the fewest LUTs)
+    #[arg(long, requires = "pareto")]
+    pareto_point: Option<usize>,
+
+    /// Write every point of the Pareto front to a separate Verilog file in this directory
+    #[arg(long, requires = "pareto")]
+    pareto_dir: Option<PathBuf>,
+
+    /// Prune LUTs wider than k from the e-graph during exploration
+    #[arg(long, default_value_t = false)]
+    prune: bool,
+
+    /// Prune nodes costing more than this many times the cheapest node of their e-class during exploration
+    #[arg(long)]
+    prune_cost: Option<f64>,
+
+    /// Merge functionally equivalent e-classes with at most this many inputs during exploration
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "12")]
+    sweep: Option<usize>,
+
+    /// Seed the e-graph with LUTs covering this many of the best k-feasible cuts of every node
+    #[arg(long)]
+    cut_seeds: Option<usize>,
+
+    /// Simplify LUTs with the don't cares of a window this many levels deep before exploration
+    #[arg(long)]
+    dont_cares: Option<usize>,
+
     /// Max fan in size allowed for extracted LUTs
     #[arg(short = 'k', long, default_value_t = 6)]
     k: usize,
@@ -128,6 +228,51 @@ fn xilinx_overrides(id: &Identifier, cell: &PrimitiveCell) -> Option<PrimitiveCe
     }
 }
 
+/// A compiled netlist and the logic to map inside it
+type MappedNetlist = (
+    Rc<Netlist<PrimitiveCell>>,
+    LogicMapping<LutLang, PrimitiveCell>,
+);
+
+/// Compile the Verilog `ast` and extract the logic to map with `partition`
+fn map_logic(
+    ast: &sv_parser::SyntaxTree,
+    partition: &PartitionMethod,
+) -> std::io::Result<MappedNetlist> {
+    let f = from_vast_overrides(ast, xilinx_overrides).map_err(std::io::Error::other)?;
+
+    info!("Extracting logic...");
+    let mut mapper = f
+        .get_analysis::<LogicMapper<LutLang, PrimitiveCell>>()
+        .map_err(std::io::Error::other)?;
+
+    match partition {
+        PartitionMethod::R2R => {
+            mapper.insert_all_r2r().map_err(std::io::Error::other)?;
+        }
+        PartitionMethod::ArcSet => {
+            mapper.insert_partitioned().map_err(std::io::Error::other)?;
+        }
+        PartitionMethod::DelayPaths => todo!("Implement delay-based partitioning"),
+    }
+
+    let mut mapping = mapper.mappings();
+    let mapping = mapping.pop().unwrap();
+    Ok((f, mapping))
+}
+
+/// Write the netlist `f` to the file at `p`
+fn write_netlist(f: &Netlist<PrimitiveCell>, p: &Path) -> std::io::Result<()> {
+    let mut file = std::fs::File::create(p)?;
+    write!(
+        file,
+        "/* Generated by {} {} */\n\n{}",
+        env!("CARGO_PKG_NAME"),
+        env!("CARGO_PKG_VERSION"),
+        f
+    )
+}
+
 fn main() -> std::io::Result<()> {
     let args = Args::parse();
     logger_init(args.verbose);
@@ -160,7 +305,7 @@ fn main() -> std::io::Result<()> {
     let ast = sv_parse_wrapper(&buf, path).map_err(std::io::Error::other)?;
 
     info!("Compiling Verilog...");
-    let f = from_vast_overrides(&ast, xilinx_overrides).map_err(std::io::Error::other)?;
+    let (f, mapping) = map_logic(&ast, &args.partition)?;
 
     info!(
         "Module {} has {} outputs",
@@ -168,24 +313,54 @@ fn main() -> std::io::Result<()> {
         f.get_output_ports().len()
     );
 
-    let mut rules = all_static_rules(false);
-
     #[cfg(feature = "dyn_decomp")]
-    if args.disassemble.is_some() {
-        rules = all_static_rules(true);
+    let bidirectional = args.disassemble.is_some();
+    #[cfg(not(feature = "dyn_decomp"))]
+    let bidirectional = false;
+
+    let mut rules = if let Some(p) = &args.rules {
+        info!("Loading rewrite rules from {p:?}");
+        let mut rules = RewriteManager::<LutLang, LutAnalysis>::with_lut_appliers();
+        let file = std::fs::File::open(p)?;
+        rules.parse_rules(file).map_err(std::io::Error::other)?;
+        rules
+    } else {
+        RewriteManager::with_lut_rules(bidirectional, true, args.k)
+    };
+
+    let categories = rules.categories().cloned().collect::<Vec<_>>();
+    for cat in categories {
+        rules.enable_category(&cat);
     }
 
     #[cfg(feature = "dyn_decomp")]
-    if args.decomp || args.disassemble.is_some() {
-        rules.append(&mut dyn_decompositions(true));
+    if !args.decomp && args.disassemble.is_none() {
+        rules.disable_category("decomp");
     }
 
+    // Exact synthesis is expensive, so it must be enabled explicitly
+    #[cfg(feature = "dyn_decomp")]
+    rules.disable_category("exact");
+
+    // Collapsing reconvergence searches a window below every LUT, so it must be enabled explicitly
+    rules.disable_category("reconvergence");
+
     // Cannot retime broken up paths
-    if args.partition != PartitionMethod::R2R {
-        rules.append(&mut register_retiming());
+    if args.partition == PartitionMethod::R2R {
+        rules.disable_category("retime");
     }
 
-    debug!("Running with {} rewrite rules", rules.len());
+    rules
+        .select(&args.enable, &args.disable)
+        .map_err(std::io::Error::other)?;
+
+    debug!(
+        "Running with {} rewrite rules. Hash: {}",
+        rules.num_active(),
+        rules.rules_hash()
+    );
+    let rules = rules.active_rules();
+
     #[cfg(feature = "dyn_decomp")]
     debug!(
         "Dynamic Decomposition {}",
@@ -200,6 +375,8 @@ fn main() -> std::io::Result<()> {
         }
     );
 
+    let expr = mapping.get_expr();
+
     let req = SynthRequest::default().with_rules(rules);
 
     let req = match (args.timeout, args.node_limit, args.iter_limit) {
@@ -221,6 +398,40 @@ fn main() -> std::io::Result<()> {
         req
     };
 
+    let req = if args.prune {
+        req.with_k_pruning(args.k)
+    } else {
+        req
+    };
+
+    let req = match args.prune_cost {
+        Some(s) if s < 1.0 => {
+            return Err(std::io::Error::other(
+                "Cost pruning slack must be at least 1",
+            ));
+        }
+        Some(s) => req.with_cost_pruning(
+            LutLang::cell_cost_with_reg_weight_fn(args.k, args.reg_weight),
+            s,
+        ),
+        None => req,
+    };
+
+    let req = match args.sweep {
+        Some(n) => req.with_sweeping(n),
+        None => req,
+    };
+
+    let req = match args.cut_seeds {
+        Some(n) => req.with_cut_seeding(args.k, n),
+        None => req,
+    };
+
+    let req = match args.dont_cares {
+        Some(d) => req.with_dont_care_simplification(d),
+        None => req,
+    };
+
     let req = if args.no_canonicalize {
         req.without_canonicalization()
     } else {
@@ -243,12 +454,29 @@ fn main() -> std::io::Result<()> {
 
     let req = if args.min_depth {
         req.with_min_depth()
-    } else if args.random {
-        req.with_randomness()
+    } else if let Some(p) = &args.timing {
+        let model = TimingModel::parse(std::fs::File::open(p)?).map_err(std::io::Error::other)?;
+        let outputs = mapping
+            .root_nets()
+            .map(|n| n.get_identifier().to_string())
+            .collect();
+        req.with_timing(args.k, model.with_output_names(outputs))
+    } else if let Some(d) = args.depth_bound {
+        req.with_depth_bound(args.k, d)
+    } else if args.power {
+        let model = ActivityModel::new(args.input_probability).map_err(std::io::Error::other)?;
+        req.with_power(args.k, model)
     } else {
         req.with_klut_regw(args.k, args.reg_weight)
     };
 
+    #[cfg(feature = "dyn_decomp")]
+    let req = if args.collapse_cuts {
+        req.with_analysis(LutAnalysis::default().with_cut_collapse(args.k))
+    } else {
+        req
+    };
+
     #[cfg(feature = "dyn_decomp")]
     let req = match args.disassemble {
         Some(list) => req
@@ -258,6 +486,32 @@ fn main() -> std::io::Result<()> {
         None => req,
     };
 
+    let req = if args.random {
+        req.with_random_samples(args.seed, args.samples)
+    } else {
+        req
+    };
+
+    let req = if args.dag {
+        req.with_dag_extraction()
+    } else {
+        req
+    };
+
+    let req = match args.local_search {
+        Some(t) => req.with_local_search(t, args.seed),
+        None => req,
+    };
+
+    let req = match (args.max_fanout, args.fanout_penalty) {
+        (Some(0), _) => {
+            return Err(std::io::Error::other("The max fanout must be at least 1"));
+        }
+        (Some(m), Some(p)) => req.with_fanout_limit(FanoutLimit::new(m).with_penalty(p)),
+        (Some(m), None) => req.with_fanout_limit(FanoutLimit::new(m)),
+        _ => req,
+    };
+
     #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
     let req = if let Some(solver) = &args.exact {
         let timeout = args.timeout.unwrap_or(600);
@@ -278,48 +532,68 @@ fn main() -> std::io::Result<()> {
         ));
     }
 
-    info!("Extracting logic...");
-    let mut mapper = f
-        .get_analysis::<LogicMapper<LutLang, PrimitiveCell>>()
-        .map_err(std::io::Error::other)?;
-
-    match args.partition {
-        PartitionMethod::R2R => {
-            mapper.insert_all_r2r().map_err(std::io::Error::other)?;
-        }
-        PartitionMethod::ArcSet => {
-            mapper.insert_partitioned().map_err(std::io::Error::other)?;
+    info!("Building e-graph...");
+    let result = if args.pareto {
+        let front: Vec<_> = process_pareto_front::<_, _, SynthReport>(
+            expr,
+            req,
+            args.k,
+            args.reg_weight,
+            args.no_verify,
+        )?
+        .into_iter()
+        .map(|r| r.with_name(f.get_name().as_str()))
+        .collect();
+        info!("Pareto front has {} points", front.len());
+
+        if let Some(p) = args.report {
+            let rpts: Vec<&SynthReport> = front.iter().filter_map(|r| r.get_report()).collect();
+            let writer = std::fs::File::create(p)?;
+            serde_json::to_writer_pretty(writer, &rpts)?;
+            for result in &front {
+                result.print_report(&mut stderr().lock())?;
+            }
         }
-        PartitionMethod::DelayPaths => todo!("Implement delay-based partitioning"),
-    }
 
-    let mut mapping = mapper.mappings();
-    let mapping = mapping.pop().unwrap();
-    let expr = mapping.get_expr();
+        if let Some(dir) = &args.pareto_dir {
+            std::fs::create_dir_all(dir)?;
+            for (i, result) in front.iter().enumerate() {
+                let g = f.deep_clone();
+                let mapping = mapping
+                    .with_netlist(&g)
+                    .ok_or(std::io::Error::other("Mapped net is missing from the copy"))?
+                    .with_expr(result.get_expr().to_owned());
+                mapping.rewrite(&g).map_err(std::io::Error::other)?;
+                write_netlist(&g, &dir.join(format!("{}_{i}.v", f.get_name())))?;
+            }
+        }
 
-    info!("Building e-graph...");
-    let result = process_expression::<_, _, SynthReport>(expr, req, args.no_verify)?
-        .with_name(f.get_name().as_str());
+        let i = args.pareto_point.unwrap_or(front.len() - 1);
+        let n = front.len();
+        front
+            .into_iter()
+            .nth(i)
+            .ok_or(std::io::Error::other(format!(
+                "Pareto point {i} does not exist. The front has {n} points"
+            )))?
+    } else {
+        let result = process_expression::<_, _, SynthReport>(expr, req, args.no_verify)?
+            .with_name(f.get_name().as_str());
 
-    if let Some(p) = args.report {
-        let mut writer = std::fs::File::create(p)?;
-        result.write_report(&mut writer)?;
-        result.print_report(&mut stderr().lock())?;
-    }
+        if let Some(p) = args.report {
+            let mut writer = std::fs::File::create(p)?;
+            result.write_report(&mut writer)?;
+            result.print_report(&mut stderr().lock())?;
+        }
+        result
+    };
 
     info!("Writing output to Verilog...");
     let mapping = mapping.with_expr(result.get_expr().to_owned());
     mapping.rewrite(&f).map_err(std::io::Error::other)?;
 
     if let Some(p) = args.output {
-        let mut file = std::fs::File::create(p)?;
-        write!(
-            file,
-            "/* Generated by {} {} */\n\n{}",
-            env!("CARGO_PKG_NAME"),
-            env!("CARGO_PKG_VERSION"),
-            f
-        )?;
+        write_netlist(&f, &p)?;
         info!("Goodbye");
     } else {
         print!("{f}");
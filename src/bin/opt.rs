@@ -2,13 +2,17 @@ use clap::Parser;
 #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
 use clap::ValueEnum;
 use egg::*;
-#[cfg(feature = "dyn_decomp")]
-use eqmap::rewrite::dyn_decompositions;
 use eqmap::{
     analysis::LutAnalysis,
-    driver::{SynthReport, SynthRequest, logger_init, process_string_expression, simple_reader},
+    driver::{
+        Extractable, SynthReport, SynthRequest, logger_init, process_string_expression,
+        simple_reader,
+    },
+    fanout::FanoutLimit,
     lut::{self, LutLang},
-    rewrite::{all_static_rules, register_retiming},
+    power::ActivityModel,
+    rewrite::RewriteManager,
+    timing::TimingModel,
 };
 use log::{debug, warn};
 use std::path::PathBuf;
@@ -17,12 +21,13 @@ fn get_main_runner(
     s: &str,
 ) -> Result<SynthRequest<LutLang, LutAnalysis>, RecExprParseError<FromOpError>> {
     let expr: RecExpr<lut::LutLang> = s.parse()?;
-    let mut rules = all_static_rules(false);
-    rules.append(&mut register_retiming());
+    let mut rules = RewriteManager::with_lut_rules(false, false, 4);
+    let enable = ["structural", "constant", "permute", "fusion", "retime"].map(String::from);
+    rules.select(&enable, &[]).unwrap();
 
     Ok(SynthRequest::default()
         .with_expr(expr)
-        .with_rules(rules)
+        .with_rules(rules.active_rules())
         .with_k(4)
         .with_asserts()
         .without_progress_bar()
@@ -90,6 +95,11 @@ struct Args {
     #[arg(long)]
     disassemble: Option<String>,
 
+    /// Collapse every e-class with at most k inputs into a single LUT
+    #[cfg(feature = "dyn_decomp")]
+    #[arg(long, default_value_t = false, conflicts_with = "disassemble")]
+    collapse_cuts: bool,
+
     /// Perform an exact extraction using ILP (much slower)
     #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
     #[arg(long, value_enum)]
@@ -99,14 +109,79 @@ struct Args {
     #[arg(short = 'r', long, default_value_t = false)]
     no_retime: bool,
 
+    /// Comma separated list of rule categories (or rules) to enable
+    #[arg(long, value_delimiter = ',')]
+    enable: Vec<String>,
+
+    /// Comma separated list of rule categories (or rules) to disable
+    #[arg(long, value_delimiter = ',')]
+    disable: Vec<String>,
+
     /// Print explanations (this generates a proof and runs longer)
     #[arg(short = 'v', long, default_value_t = false)]
     verbose: bool,
 
     /// Extract for minimum circuit depth
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["dag", "local_search"])]
     min_depth: bool,
 
+    /// Extract a DAG, counting LUTs shared by several fanouts once
+    #[arg(long, default_value_t = false)]
+    dag: bool,
+
+    /// Refine the extraction by simulated annealing for this many seconds, counting shared LUTs once
+    #[arg(long, conflicts_with = "dag")]
+    local_search: Option<u64>,
+
+    /// Seed the local search to make it reproducible
+    #[arg(long, requires = "local_search")]
+    seed: Option<u64>,
+
+    /// Extract for the worst slack under the arrival, required, and delay times in this file.
+    /// Outputs are named by their index.
+    #[arg(long, conflicts_with_all = ["min_depth", "dag", "local_search"])]
+    timing: Option<PathBuf>,
+
+    /// Extract the fewest LUTs such that the circuit depth is at most this bound
+    #[arg(long, conflicts_with_all = ["min_depth", "timing", "dag", "local_search"])]
+    depth_bound: Option<usize>,
+
+    /// Extract for the least dynamic power, weighing every LUT by the toggle rate of its output
+    #[arg(long, default_value_t = false, conflicts_with_all = ["min_depth", "timing", "depth_bound"])]
+    power: bool,
+
+    /// Probability that each input is one when estimating the switching activity
+    #[arg(long, requires = "power", default_value_t = 0.5)]
+    input_probability: f64,
+
+    /// Duplicate logic so that every LUT or gate drives at most this many loads
+    #[arg(long)]
+    max_fanout: Option<usize>,
+
+    /// Only duplicate logic when it is cheaper than this penalty for every load over the max fanout
+    #[arg(long, requires = "max_fanout")]
+    fanout_penalty: Option<f64>,
+
+    /// Prune LUTs wider than k from the e-graph during exploration
+    #[arg(long, default_value_t = false)]
+    prune: bool,
+
+    /// Prune nodes costing more than this many times the cheapest node of their e-class during exploration
+    #[arg(long)]
+    prune_cost: Option<f64>,
+
+    /// Merge functionally equivalent e-classes with at most this many inputs during exploration
+    #[arg(long, num_args = 0..=1, require_equals = true, default_missing_value = "12")]
+    sweep: Option<usize>,
+
+    /// Seed the e-graph with LUTs covering this many of the best k-feasible cuts of every node
+    #[arg(long)]
+    cut_seeds: Option<usize>,
+
+    /// Simplify LUTs with the don't cares of a window this many levels deep before exploration
+    #[arg(long)]
+    dont_cares: Option<usize>,
+
     /// Max fan in size allowed for extracted LUTs
     #[arg(short = 'k', long, default_value_t = 4)]
     k: usize,
@@ -138,30 +213,51 @@ fn main() -> std::io::Result<()> {
 
     let buf = simple_reader(args.command, args.input)?;
 
-    let mut rules = all_static_rules(false);
-
     #[cfg(feature = "dyn_decomp")]
-    if args.disassemble.is_some() {
-        rules = all_static_rules(true);
+    let bidirectional = args.disassemble.is_some();
+    #[cfg(not(feature = "dyn_decomp"))]
+    let bidirectional = false;
+
+    let mut rules = RewriteManager::with_lut_rules(bidirectional, false, args.k);
+    let categories = rules.categories().cloned().collect::<Vec<_>>();
+    for cat in categories {
+        rules.enable_category(&cat);
     }
 
     #[cfg(feature = "dyn_decomp")]
-    if args.decomp || args.disassemble.is_some() {
-        rules.append(&mut dyn_decompositions(false));
+    if !args.decomp && args.disassemble.is_none() {
+        rules.disable_category("decomp");
     }
 
-    if !args.no_retime {
-        rules.append(&mut register_retiming());
+    // Exact synthesis is expensive, so it must be enabled explicitly
+    #[cfg(feature = "dyn_decomp")]
+    rules.disable_category("exact");
+
+    // Collapsing reconvergence searches a window below every LUT, so it must be enabled explicitly
+    rules.disable_category("reconvergence");
+
+    if args.no_retime {
+        rules.disable_category("retime");
     }
 
-    debug!("Running with {} rewrite rules", rules.len());
+    rules
+        .select(&args.enable, &args.disable)
+        .map_err(std::io::Error::other)?;
+
+    debug!(
+        "Running with {} rewrite rules. Hash: {}",
+        rules.num_active(),
+        rules.rules_hash()
+    );
     #[cfg(feature = "dyn_decomp")]
     debug!(
         "Dynamic Decomposition {}",
         if args.decomp { "ON" } else { "OFF" }
     );
 
-    let req = SynthRequest::default().with_rules(rules).with_k(args.k);
+    let req = SynthRequest::default()
+        .with_rules(rules.active_rules())
+        .with_k(args.k);
 
     let req = match (args.timeout, args.node_limit, args.iter_limit) {
         (None, None, None) => req.with_joint_limits(10, 24_000, 32),
@@ -182,6 +278,40 @@ fn main() -> std::io::Result<()> {
         req
     };
 
+    let req = if args.prune {
+        req.with_k_pruning(args.k)
+    } else {
+        req
+    };
+
+    let req = match args.prune_cost {
+        Some(s) if s < 1.0 => {
+            return Err(std::io::Error::other(
+                "Cost pruning slack must be at least 1",
+            ));
+        }
+        Some(s) => req.with_cost_pruning(
+            LutLang::cell_cost_with_reg_weight_fn(args.k, args.reg_weight),
+            s,
+        ),
+        None => req,
+    };
+
+    let req = match args.sweep {
+        Some(n) => req.with_sweeping(n),
+        None => req,
+    };
+
+    let req = match args.cut_seeds {
+        Some(n) => req.with_cut_seeding(args.k, n),
+        None => req,
+    };
+
+    let req = match args.dont_cares {
+        Some(d) => req.with_dont_care_simplification(d),
+        None => req,
+    };
+
     let req = if args.no_canonicalize {
         req.without_canonicalization()
     } else {
@@ -198,10 +328,25 @@ fn main() -> std::io::Result<()> {
 
     let req = if args.min_depth {
         req.with_min_depth()
+    } else if let Some(p) = &args.timing {
+        let model = TimingModel::parse(std::fs::File::open(p)?).map_err(std::io::Error::other)?;
+        req.with_timing(args.k, model)
+    } else if let Some(d) = args.depth_bound {
+        req.with_depth_bound(args.k, d)
+    } else if args.power {
+        let model = ActivityModel::new(args.input_probability).map_err(std::io::Error::other)?;
+        req.with_power(args.k, model)
     } else {
         req.with_klut_regw(args.k, args.reg_weight)
     };
 
+    #[cfg(feature = "dyn_decomp")]
+    let req = if args.collapse_cuts {
+        req.with_analysis(LutAnalysis::default().with_cut_collapse(args.k))
+    } else {
+        req
+    };
+
     #[cfg(feature = "dyn_decomp")]
     let req = match args.disassemble {
         Some(list) => req
@@ -211,6 +356,26 @@ fn main() -> std::io::Result<()> {
         None => req,
     };
 
+    let req = if args.dag {
+        req.with_dag_extraction()
+    } else {
+        req
+    };
+
+    let req = match args.local_search {
+        Some(t) => req.with_local_search(t, args.seed),
+        None => req,
+    };
+
+    let req = match (args.max_fanout, args.fanout_penalty) {
+        (Some(0), _) => {
+            return Err(std::io::Error::other("The max fanout must be at least 1"));
+        }
+        (Some(m), Some(p)) => req.with_fanout_limit(FanoutLimit::new(m).with_penalty(p)),
+        (Some(m), None) => req.with_fanout_limit(FanoutLimit::new(m)),
+        _ => req,
+    };
+
     #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
     let req = if let Some(solver) = &args.exact {
         let timeout = args.timeout.unwrap_or(600);
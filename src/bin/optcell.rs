@@ -5,7 +5,10 @@ use egg::{FromOpError, RecExpr, RecExprParseError};
 use eqmap::{
     asic::{CellAnalysis, CellLang, CellRpt, asic_rewrites},
     driver::{SynthRequest, logger_init, process_string_expression, simple_reader},
+    fanout::FanoutLimit,
+    power::ActivityModel,
     rewrite::RewriteManager,
+    timing::TimingModel,
     verilog::SVModule,
 };
 use log::{debug, info, warn};
@@ -65,6 +68,14 @@ struct Args {
     #[arg(long)]
     rules: Option<PathBuf>,
 
+    /// Comma separated list of rule categories (or rules) to enable
+    #[arg(long, value_delimiter = ',')]
+    enable: Vec<String>,
+
+    /// Comma separated list of rule categories (or rules) to disable
+    #[arg(long, value_delimiter = ',')]
+    disable: Vec<String>,
+
     /// Use a cost model that weighs the cells by exact area
     #[arg(short = 'a', long, default_value_t = false)]
     area: bool,
@@ -95,9 +106,46 @@ struct Args {
     verilog: bool,
 
     /// Extract for minimum circuit depth
-    #[arg(long, default_value_t = false)]
+    #[arg(long, default_value_t = false, conflicts_with_all = ["dag", "local_search"])]
     min_depth: bool,
 
+    /// Extract a DAG, counting cells shared by several fanouts once
+    #[arg(long, default_value_t = false)]
+    dag: bool,
+
+    /// Refine the extraction by simulated annealing for this many seconds, counting shared cells once
+    #[arg(long, conflicts_with = "dag")]
+    local_search: Option<u64>,
+
+    /// Seed the local search to make it reproducible
+    #[arg(long, requires = "local_search")]
+    seed: Option<u64>,
+
+    /// Extract for the worst slack under the arrival, required, and delay times in this file.
+    /// Outputs are named by their index.
+    #[arg(long, conflicts_with_all = ["min_depth", "dag", "local_search"])]
+    timing: Option<PathBuf>,
+
+    /// Extract the fewest cells such that the circuit depth is at most this bound
+    #[arg(long, conflicts_with_all = ["min_depth", "timing", "dag", "local_search"])]
+    depth_bound: Option<usize>,
+
+    /// Extract for the least dynamic power, weighing every cell by its area times the toggle rate of its output
+    #[arg(long, default_value_t = false, conflicts_with_all = ["min_depth", "timing", "depth_bound"])]
+    power: bool,
+
+    /// Probability that each input is one when estimating the switching activity
+    #[arg(long, requires = "power", default_value_t = 0.5)]
+    input_probability: f64,
+
+    /// Duplicate logic so that every cell or gate drives at most this many loads
+    #[arg(long)]
+    max_fanout: Option<usize>,
+
+    /// Only duplicate logic when it is cheaper than this penalty for every load over the max fanout
+    #[arg(long, requires = "max_fanout")]
+    fanout_penalty: Option<f64>,
+
     /// Max fan in size allowed for extracted Cells
     #[arg(short = 'k', long, default_value_t = 4)]
     k: usize,
@@ -157,6 +205,10 @@ fn main() -> std::io::Result<()> {
         }
     }
 
+    rules
+        .select(&args.enable, &args.disable)
+        .map_err(std::io::Error::other)?;
+
     debug!(
         "Running with {} rewrite rules. Hash: {}",
         rules.num_active(),
@@ -194,12 +246,40 @@ fn main() -> std::io::Result<()> {
         req.with_ast_size()
     } else if args.min_depth {
         req.with_min_depth()
+    } else if let Some(p) = &args.timing {
+        let model = TimingModel::parse(std::fs::File::open(p)?).map_err(std::io::Error::other)?;
+        req.with_timing(args.k, model)
+    } else if let Some(d) = args.depth_bound {
+        req.with_depth_bound(args.k, d)
+    } else if args.power {
+        let model = ActivityModel::new(args.input_probability).map_err(std::io::Error::other)?;
+        req.with_power(args.k, model)
     } else if args.area {
         req.with_area()
     } else {
         req.with_k(args.k)
     };
 
+    let req = if args.dag {
+        req.with_dag_extraction()
+    } else {
+        req
+    };
+
+    let req = match args.local_search {
+        Some(t) => req.with_local_search(t, args.seed),
+        None => req,
+    };
+
+    let req = match (args.max_fanout, args.fanout_penalty) {
+        (Some(0), _) => {
+            return Err(std::io::Error::other("The max fanout must be at least 1"));
+        }
+        (Some(m), Some(p)) => req.with_fanout_limit(FanoutLimit::new(m).with_penalty(p)),
+        (Some(m), None) => req.with_fanout_limit(FanoutLimit::new(m)),
+        _ => req,
+    };
+
     #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
     let req = if let Some(solver) = &args.exact {
         let timeout = args.timeout.unwrap_or(600);
@@ -0,0 +1,63 @@
+use std::{
+    io::{Read, stdin},
+    path::PathBuf,
+};
+
+use clap::Parser;
+use eqmap::{
+    driver::logger_init,
+    rulegen::{CellSpec, RuleSynthesizer},
+};
+use log::warn;
+/// Synthesize a CellLang rule file from the truth tables of a cell library
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+struct Args {
+    /// Path to the cell library. If not provided, reads from stdin
+    input: Option<PathBuf>,
+    /// Path to write the rule file to. If not provided, writes to stdout
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
+    /// Max number of operators in the term implementing a cell
+    #[arg(short = 'c', long, default_value_t = 6)]
+    cell_size: usize,
+    /// Number of variables in algebraic rules
+    #[arg(short = 'v', long, default_value_t = 3)]
+    term_vars: usize,
+    /// Max number of operators in the terms of algebraic rules
+    #[arg(short = 't', long, default_value_t = 3)]
+    term_size: usize,
+}
+
+fn main() -> std::io::Result<()> {
+    let args = Args::parse();
+    logger_init(false);
+    let mut buf = String::new();
+
+    match args.input {
+        Some(p) => std::fs::File::open(&p)?.read_to_string(&mut buf)?,
+        None => stdin().read_to_string(&mut buf)?,
+    };
+
+    let cells = CellSpec::parse_library(buf.as_bytes()).map_err(std::io::Error::other)?;
+    let (rules, unmatched) = RuleSynthesizer::new(cells)
+        .with_cell_size(args.cell_size)
+        .with_term_size(args.term_vars, args.term_size)
+        .synthesize()
+        .map_err(std::io::Error::other)?;
+
+    for cell in unmatched {
+        warn!(
+            "No term with at most {} operators implements {cell}",
+            args.cell_size
+        );
+    }
+
+    match args.output {
+        Some(p) => std::fs::write(p, rules),
+        None => {
+            print!("{rules}");
+            Ok(())
+        }
+    }
+}
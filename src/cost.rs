@@ -6,7 +6,7 @@
 use super::asic::CellLang;
 use super::lut::LutLang;
 use egg::{CostFunction, Id, Language};
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 
 /// A cost function that extracts LUTs with at most `k` fan-in.
 /// Gates have cost [u64::MAX] to prevent their extraction.
@@ -253,6 +253,8 @@ impl CostFunction<CellLang> for GateCostFn {
 /// A randomized extractor to use for program fuzzing.
 pub struct RandomExtract<L> {
     choice_func: fn(&[L]) -> usize,
+    /// A seeded generator that makes the choices instead of `choice_func`
+    seeded: Option<std::cell::RefCell<rand::rngs::StdRng>>,
 }
 
 impl<L> RandomExtract<L>
@@ -276,7 +278,7 @@ where
         }
 
         let choices = &egraph[id].nodes;
-        let choice = (self.choice_func)(choices);
+        let choice = self.choose(choices);
         let node = choices[choice]
             .clone()
             .map_children(|child| self.extract_term(egraph, child, expr, res));
@@ -290,12 +292,34 @@ where
         use rand::{RngExt, rng};
         RandomExtract {
             choice_func: |choices| rng().random_range(0..choices.len()),
+            seeded: None,
+        }
+    }
+
+    /// Create a randomized extractor that makes the same choices for the same `seed`
+    pub fn with_seed(seed: u64) -> Self {
+        use rand::SeedableRng;
+        RandomExtract {
+            seeded: Some(rand::rngs::StdRng::seed_from_u64(seed).into()),
+            ..Self::new()
         }
     }
 
     /// Use an arbitrary choice function to perform extraction with
     pub fn with_choice_func(choice_func: fn(&[L]) -> usize) -> Self {
-        RandomExtract { choice_func }
+        RandomExtract {
+            choice_func,
+            seeded: None,
+        }
+    }
+
+    /// Returns the index of a random choice among `choices`
+    pub fn choose(&self, choices: &[L]) -> usize {
+        use rand::RngExt;
+        match &self.seeded {
+            Some(rng) => rng.borrow_mut().random_range(0..choices.len()),
+            None => (self.choice_func)(choices),
+        }
     }
 
     /// Extract a random expression from the egraph using rand crate.
@@ -318,3 +342,687 @@ where
         Self::new()
     }
 }
+
+/// A cost that can be summed over the nodes of a DAG
+pub trait DagCost: Copy + PartialOrd + Default {
+    /// Add two costs, saturating at the max cost
+    fn saturating_add(self, other: Self) -> Self;
+
+    /// Returns the cost as a float, or [None] if it is the max cost of a node that can't be extracted
+    fn finite(self) -> Option<f64>;
+}
+
+impl DagCost for u64 {
+    fn saturating_add(self, other: Self) -> Self {
+        u64::saturating_add(self, other)
+    }
+
+    fn finite(self) -> Option<f64> {
+        (self != u64::MAX).then_some(self as f64)
+    }
+}
+
+impl DagCost for usize {
+    fn saturating_add(self, other: Self) -> Self {
+        usize::saturating_add(self, other)
+    }
+
+    fn finite(self) -> Option<f64> {
+        (self != usize::MAX).then_some(self as f64)
+    }
+}
+
+impl DagCost for i64 {
+    fn saturating_add(self, other: Self) -> Self {
+        i64::saturating_add(self, other)
+    }
+
+    fn finite(self) -> Option<f64> {
+        (self != i64::MAX).then_some(self as f64)
+    }
+}
+
+impl DagCost for f32 {
+    fn saturating_add(self, other: Self) -> Self {
+        self + other
+    }
+
+    fn finite(self) -> Option<f64> {
+        (self < f32::MAX).then_some(self as f64)
+    }
+}
+
+/// The e-classes needed by a choice of e-node and the cost each of them adds
+struct CostSet<T> {
+    costs: BTreeMap<Id, T>,
+    total: T,
+    choice: usize,
+}
+
+/// A greedy extractor that counts nodes shared by several parents once.
+/// Every e-class keeps the set of e-classes its best e-node needs, and its cost is the sum over that set.
+pub struct DagExtract<C> {
+    cost_fn: C,
+}
+
+impl<C> DagExtract<C> {
+    /// Create a DAG extractor using the node costs of `cost_fn`. The costs of children are ignored.
+    pub fn new(cost_fn: C) -> Self {
+        Self { cost_fn }
+    }
+
+    /// Returns the cost function, e.g. to fall back to tree extraction
+    pub fn into_cost_fn(self) -> C {
+        self.cost_fn
+    }
+
+    /// Extract the expression rooted at `id` with the least total cost.
+    /// Returns [None] if the choices made form a cycle.
+    pub fn extract<L, A>(&mut self, egraph: &egg::EGraph<L, A>, id: Id) -> Option<egg::RecExpr<L>>
+    where
+        L: Language,
+        A: egg::Analysis<L>,
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        let mut parents: HashMap<Id, Vec<(Id, usize)>> = HashMap::new();
+        let mut worklist: std::collections::VecDeque<(Id, usize)> = Default::default();
+        for class in egraph.classes() {
+            for (i, n) in class.nodes.iter().enumerate() {
+                if n.is_leaf() {
+                    worklist.push_back((class.id, i));
+                }
+                for c in n.children() {
+                    parents
+                        .entry(egraph.find(*c))
+                        .or_default()
+                        .push((class.id, i));
+                }
+            }
+        }
+
+        let mut sets: HashMap<Id, CostSet<C::Cost>> = HashMap::new();
+        let mut queued: HashSet<(Id, usize)> = worklist.iter().copied().collect();
+        while let Some((cid, i)) = worklist.pop_front() {
+            queued.remove(&(cid, i));
+            let node = &egraph[cid].nodes[i];
+            let children: Option<Vec<&CostSet<C::Cost>>> = node
+                .children()
+                .iter()
+                .map(|c| sets.get(&egraph.find(*c)))
+                .collect();
+            let Some(mut children) = children else {
+                continue;
+            };
+
+            // Start from the largest child set, since merging the others into it is cheaper
+            children.sort_by_key(|s| std::cmp::Reverse(s.costs.len()));
+            let mut costs = children
+                .first()
+                .map(|s| s.costs.clone())
+                .unwrap_or_default();
+            for s in children.iter().skip(1) {
+                for (k, v) in &s.costs {
+                    costs.entry(*k).or_insert(*v);
+                }
+            }
+            if costs.contains_key(&cid) {
+                continue;
+            }
+            costs.insert(cid, self.cost_fn.cost(node, |_| C::Cost::default()));
+            let total = costs
+                .values()
+                .fold(C::Cost::default(), |sum, c| sum.saturating_add(*c));
+
+            if sets.get(&cid).is_some_and(|s| s.total <= total) {
+                continue;
+            }
+            sets.insert(
+                cid,
+                CostSet {
+                    costs,
+                    total,
+                    choice: i,
+                },
+            );
+            for p in parents.get(&cid).into_iter().flatten() {
+                if queued.insert(*p) {
+                    worklist.push_back(*p);
+                }
+            }
+        }
+
+        let mut expr = egg::RecExpr::default();
+        let mut memo: HashMap<Id, Id> = HashMap::new();
+        Self::build(
+            egraph,
+            &sets,
+            egraph.find(id),
+            &mut expr,
+            &mut memo,
+            &mut HashSet::new(),
+        )?;
+        Some(expr)
+    }
+
+    /// Add the chosen e-node of `id` and its children to `expr`
+    fn build<L, A, T>(
+        egraph: &egg::EGraph<L, A>,
+        sets: &HashMap<Id, CostSet<T>>,
+        id: Id,
+        expr: &mut egg::RecExpr<L>,
+        memo: &mut HashMap<Id, Id>,
+        visiting: &mut HashSet<Id>,
+    ) -> Option<Id>
+    where
+        L: Language,
+        A: egg::Analysis<L>,
+    {
+        if let Some(e) = memo.get(&id) {
+            return Some(*e);
+        }
+        if !visiting.insert(id) {
+            return None;
+        }
+        let node = egraph[id].nodes[sets.get(&id)?.choice].clone();
+        let mut children = Vec::with_capacity(node.len());
+        for c in node.children() {
+            children.push(Self::build(
+                egraph,
+                sets,
+                egraph.find(*c),
+                expr,
+                memo,
+                visiting,
+            )?);
+        }
+        let mut children = children.into_iter();
+        let e = expr.add(node.map_children(|_| children.next().unwrap()));
+        visiting.remove(&id);
+        memo.insert(id, e);
+        Some(e)
+    }
+}
+
+/// The cost of every e-node in an e-graph, or [None] if it can't be extracted. The costs of children are ignored.
+pub(crate) struct NodeCosts<T> {
+    costs: HashMap<Id, Vec<Option<T>>>,
+}
+
+impl<T: Copy> NodeCosts<T> {
+    /// Tabulate the cost `f` of every e-node in `egraph`
+    pub(crate) fn new<L, A>(egraph: &egg::EGraph<L, A>, mut f: impl FnMut(&L) -> Option<T>) -> Self
+    where
+        L: Language,
+        A: egg::Analysis<L>,
+    {
+        let costs = egraph
+            .classes()
+            .map(|class| (class.id, class.nodes.iter().map(&mut f).collect()))
+            .collect();
+        Self { costs }
+    }
+
+    /// Never extract the e-nodes that satisfy `f`
+    pub(crate) fn forbid<L, A>(&mut self, egraph: &egg::EGraph<L, A>, f: impl Fn(&L) -> bool)
+    where
+        L: Language,
+        A: egg::Analysis<L>,
+    {
+        for class in egraph.classes() {
+            let costs = self.costs.get_mut(&class.id).unwrap();
+            for (c, n) in costs.iter_mut().zip(&class.nodes) {
+                if f(n) {
+                    *c = None;
+                }
+            }
+        }
+    }
+
+    /// Returns the least cost of every e-class as a tree and the e-node that achieves it, like a greedy extractor.
+    /// The cost of an e-node is its `value` plus its children folded by `combine`, like a sum for area or a max
+    /// for depth.
+    pub(crate) fn greedy<L, A>(
+        &self,
+        egraph: &egg::EGraph<L, A>,
+        value: impl Fn(&T) -> f64,
+        combine: impl Fn(f64, f64) -> f64,
+    ) -> HashMap<Id, (f64, usize)>
+    where
+        L: Language,
+        A: egg::Analysis<L>,
+    {
+        let mut best: HashMap<Id, (f64, usize)> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for class in egraph.classes() {
+                for (i, (n, c)) in class.nodes.iter().zip(&self.costs[&class.id]).enumerate() {
+                    let Some(c) = c else {
+                        continue;
+                    };
+                    let children: Option<f64> = n.children().iter().try_fold(0.0, |acc, c| {
+                        best.get(&egraph.find(*c)).map(|(b, _)| combine(acc, *b))
+                    });
+                    let Some(cost) = children.map(|children| children + value(c)) else {
+                        continue;
+                    };
+                    if best.get(&class.id).is_none_or(|(old, _)| cost < *old) {
+                        best.insert(class.id, (cost, i));
+                        changed = true;
+                    }
+                }
+            }
+        }
+        best
+    }
+}
+
+impl NodeCosts<f64> {
+    /// Tabulate the node costs of `cost_fn`
+    pub(crate) fn from_cost_fn<L, A, C>(egraph: &egg::EGraph<L, A>, cost_fn: &mut C) -> Self
+    where
+        L: Language,
+        A: egg::Analysis<L>,
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        Self::new(egraph, |n| cost_fn.cost(n, |_| C::Cost::default()).finite())
+    }
+}
+
+impl<T> std::ops::Index<Id> for NodeCosts<T> {
+    type Output = [Option<T>];
+
+    fn index(&self, id: Id) -> &Self::Output {
+        &self.costs[&id]
+    }
+}
+
+/// The least area of an e-class at some depth and the e-node that achieves it
+type AreaChoice = Option<(f64, usize)>;
+
+/// An expression on the area/depth Pareto front
+pub struct ParetoPoint<L> {
+    expr: egg::RecExpr<L>,
+    area: f64,
+    depth: usize,
+}
+
+impl<L> ParetoPoint<L> {
+    /// Returns the extracted expression
+    pub fn get_expr(&self) -> &egg::RecExpr<L> {
+        &self.expr
+    }
+
+    /// Returns the area of the expression, counting every shared node once
+    pub fn get_area(&self) -> f64 {
+        self.area
+    }
+
+    /// Returns the depth of the expression
+    pub fn get_depth(&self) -> usize {
+        self.depth
+    }
+
+    /// Consumes the point and returns the expression
+    pub fn into_expr(self) -> egg::RecExpr<L> {
+        self.expr
+    }
+}
+
+/// An extractor that minimizes area subject to a bound on depth.
+/// Every e-class keeps the least area it can be built with at each depth up to the bound.
+pub struct DepthBoundExtract<'a, L: Language, A: egg::Analysis<L>> {
+    egraph: &'a egg::EGraph<L, A>,
+    /// The area and depth of every e-node
+    costs: NodeCosts<(f64, usize)>,
+}
+
+impl<'a, L, A> DepthBoundExtract<'a, L, A>
+where
+    L: Language,
+    A: egg::Analysis<L>,
+{
+    /// Create an extractor that weighs every e-node by its own area under `area_fn` and its own depth under `depth_fn`
+    pub fn new<C, D>(egraph: &'a egg::EGraph<L, A>, mut area_fn: C, mut depth_fn: D) -> Self
+    where
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+        D: CostFunction<L, Cost = i64>,
+    {
+        let costs = NodeCosts::new(egraph, |n| {
+            let area = area_fn.cost(n, |_| C::Cost::default()).finite()?;
+            let depth = depth_fn.cost(n, |_| 0).finite()?;
+            Some((area, depth.max(0.0) as usize))
+        });
+        Self { egraph, costs }
+    }
+
+    /// Never extract the e-nodes that satisfy `f`, at any depth
+    pub fn forbid(mut self, f: impl Fn(&L) -> bool) -> Self {
+        self.costs.forbid(self.egraph, f);
+        self
+    }
+
+    /// Returns the least depth of the expression rooted at `id`, or [None] if nothing can be extracted
+    pub fn min_depth(&self, id: Id) -> Option<usize> {
+        let depths = self.costs.greedy(self.egraph, |(_, d)| *d as f64, f64::max);
+        depths.get(&self.egraph.find(id)).map(|(d, _)| *d as usize)
+    }
+
+    /// Fill the table of least areas for every e-class at every depth up to `bound`
+    fn tabulate(&self, bound: usize) -> HashMap<Id, Vec<AreaChoice>> {
+        let mut table: HashMap<Id, Vec<AreaChoice>> = self
+            .egraph
+            .classes()
+            .map(|class| (class.id, vec![None; bound + 1]))
+            .collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for class in self.egraph.classes() {
+                for (i, (n, c)) in class.nodes.iter().zip(&self.costs[class.id]).enumerate() {
+                    let Some((area, d)) = c else {
+                        continue;
+                    };
+                    for depth in *d..=bound {
+                        let children: Option<f64> =
+                            n.children().iter().try_fold(*area, |sum, c| {
+                                table[&self.egraph.find(*c)][depth - d].map(|(a, _)| sum + a)
+                            });
+                        let Some(total) = children else {
+                            continue;
+                        };
+                        let entry = &mut table.get_mut(&class.id).unwrap()[depth];
+                        if entry.is_none_or(|(old, _)| total < old) {
+                            *entry = Some((total, i));
+                            changed = true;
+                        }
+                    }
+                }
+            }
+        }
+        table
+    }
+
+    /// Extract the expression rooted at `id` with the least area and a depth of at most `bound`.
+    /// Returns [None] if no expression meets the bound.
+    pub fn extract(&self, id: Id, bound: usize) -> Option<egg::RecExpr<L>> {
+        self.extract_from(&self.tabulate(bound), id, bound)
+            .map(ParetoPoint::into_expr)
+    }
+
+    /// Extract the expression rooted at `id` with a depth of at most `depth` from a filled `table`.
+    /// The area of the point counts every shared node once.
+    fn extract_from(
+        &self,
+        table: &HashMap<Id, Vec<AreaChoice>>,
+        id: Id,
+        depth: usize,
+    ) -> Option<ParetoPoint<L>> {
+        let mut dag = DagBuilder::default();
+        self.build(table, self.egraph.find(id), depth, &mut dag)?;
+        let depth = dag.costs.last().map_or(0, |(_, d)| *d);
+        let area = dag.costs.iter().map(|(a, _)| a).sum();
+        Some(ParetoPoint {
+            expr: dag.expr,
+            area,
+            depth,
+        })
+    }
+
+    /// Returns the least area of the expression rooted at `id` regardless of depth
+    fn min_area(&self, id: Id) -> Option<f64> {
+        let areas = self.costs.greedy(self.egraph, |(a, _)| *a, |x, y| x + y);
+        areas.get(&self.egraph.find(id)).map(|(a, _)| *a)
+    }
+
+    /// Extract the expressions rooted at `id` that no other expression beats in both area and depth.
+    /// The table is filled with the area of trees, so every depth bound is extracted and the points are compared by
+    /// the area of their DAG, where shared nodes count once.
+    /// The points are sorted from least depth to least area.
+    pub fn pareto(&self, id: Id) -> Vec<ParetoPoint<L>> {
+        let id = self.egraph.find(id);
+        let (Some(lo), Some(best)) = (self.min_depth(id), self.min_area(id)) else {
+            return vec![];
+        };
+
+        // Grow the bound until it admits the expression with the least area
+        let mut bound = lo.max(1);
+        let table = loop {
+            let table = self.tabulate(bound);
+            if table[&id][bound].is_some_and(|(a, _)| a - best <= f64::EPSILON * best.abs()) {
+                break table;
+            }
+            bound *= 2;
+        };
+
+        let mut points: Vec<ParetoPoint<L>> = (lo..=bound)
+            .filter_map(|depth| self.extract_from(&table, id, depth))
+            .collect();
+        points.sort_by(|a, b| a.depth.cmp(&b.depth).then(a.area.total_cmp(&b.area)));
+
+        let mut front: Vec<ParetoPoint<L>> = Vec::new();
+        for p in points {
+            if front.last().is_none_or(|q| p.area < q.area) {
+                front.push(p);
+            }
+        }
+        front
+    }
+
+    /// Add the chosen e-node of `id` at `depth` and its children to `dag`
+    fn build(
+        &self,
+        table: &HashMap<Id, Vec<AreaChoice>>,
+        id: Id,
+        depth: usize,
+        dag: &mut DagBuilder<L>,
+    ) -> Option<Id> {
+        if let Some(e) = dag.memo.get(&(id, depth)) {
+            return Some(*e);
+        }
+        if !dag.visiting.insert((id, depth)) {
+            return None;
+        }
+        let (_, i) = table[&id][depth]?;
+        let node = self.egraph[id].nodes[i].clone();
+        let (area, d) = self.costs[id][i]?;
+        let mut children = Vec::with_capacity(node.len());
+        for c in node.children() {
+            children.push(self.build(table, self.egraph.find(*c), depth - d, dag)?);
+        }
+        let below = children
+            .iter()
+            .map(|c| dag.costs[usize::from(*c)].1)
+            .max()
+            .unwrap_or(0);
+        let mut children = children.into_iter();
+        let node = node.map_children(|_| children.next().unwrap());
+        let e = match dag.nodes.get(&node) {
+            Some(e) => *e,
+            None => {
+                let e = dag.expr.add(node.clone());
+                dag.nodes.insert(node, e);
+                dag.costs.push((area, below + d));
+                e
+            }
+        };
+        dag.visiting.remove(&(id, depth));
+        dag.memo.insert((id, depth), e);
+        Some(e)
+    }
+}
+
+/// An expression built by [DepthBoundExtract], where identical nodes are shared
+struct DagBuilder<L: Language> {
+    expr: egg::RecExpr<L>,
+    /// The area of every node in `expr` and the depth of the expression it roots
+    costs: Vec<(f64, usize)>,
+    nodes: HashMap<L, Id>,
+    memo: HashMap<(Id, usize), Id>,
+    visiting: HashSet<(Id, usize)>,
+}
+
+impl<L: Language> Default for DagBuilder<L> {
+    fn default() -> Self {
+        Self {
+            expr: egg::RecExpr::default(),
+            costs: Vec::new(),
+            nodes: HashMap::new(),
+            memo: HashMap::new(),
+            visiting: HashSet::new(),
+        }
+    }
+}
+
+/// A local search that refines a greedy extraction by simulated annealing over the e-node chosen for every e-class.
+/// The cost of a set of choices is the sum of the node costs over the DAG they build, so shared nodes count once.
+pub struct LocalSearchExtract<'a, L: Language, A: egg::Analysis<L>> {
+    egraph: &'a egg::EGraph<L, A>,
+    costs: NodeCosts<f64>,
+    moves: RandomExtract<L>,
+    /// Picks the e-class to perturb and accepts worse moves
+    rng: std::cell::RefCell<rand::rngs::StdRng>,
+}
+
+impl<'a, L, A> LocalSearchExtract<'a, L, A>
+where
+    L: Language,
+    A: egg::Analysis<L>,
+{
+    /// The factor the temperature is cooled by after every move
+    const COOLING: f64 = 0.999;
+
+    /// Create a local search that charges every chosen e-node its own cost under `cost_fn`
+    pub fn new<C>(egraph: &'a egg::EGraph<L, A>, cost_fn: &mut C) -> Self
+    where
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        use rand::{RngExt, SeedableRng, rng};
+        Self {
+            egraph,
+            costs: NodeCosts::from_cost_fn(egraph, cost_fn),
+            moves: RandomExtract::new(),
+            rng: rand::rngs::StdRng::seed_from_u64(rng().random()).into(),
+        }
+    }
+
+    /// Make the same moves for the same `seed`. The number of moves still depends on the time budget.
+    pub fn with_seed(self, seed: u64) -> Self {
+        use rand::{RngExt, SeedableRng};
+        let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+        Self {
+            moves: RandomExtract::with_seed(rng.random()),
+            rng: rng.into(),
+            ..self
+        }
+    }
+
+    /// Never choose the e-nodes that satisfy `f`, neither in the greedy start nor as a move
+    pub fn forbid(mut self, f: impl Fn(&L) -> bool) -> Self {
+        self.costs.forbid(self.egraph, f);
+        self
+    }
+
+    /// Perturb the choices with the random choices of `moves`
+    pub fn with_moves(self, moves: RandomExtract<L>) -> Self {
+        Self { moves, ..self }
+    }
+
+    /// Returns the choices of a greedy extraction that minimizes the cost of the tree
+    fn greedy(&self) -> HashMap<Id, usize> {
+        let best = self.costs.greedy(self.egraph, |c| *c, |x, y| x + y);
+        best.into_iter().map(|(id, (_, i))| (id, i)).collect()
+    }
+
+    /// Returns the cost of the DAG built by `choices` from `id` and the e-classes in it.
+    /// Returns [None] if the choices form a cycle or need an e-node that can't be extracted.
+    fn evaluate(&self, choices: &HashMap<Id, usize>, id: Id) -> Option<(f64, Vec<Id>)> {
+        let mut order = Vec::new();
+        let mut done = HashSet::new();
+        let mut stack = vec![(id, false)];
+        let mut visiting = HashSet::new();
+        let mut cost = 0.0;
+        while let Some((id, expanded)) = stack.pop() {
+            if expanded {
+                visiting.remove(&id);
+                done.insert(id);
+                order.push(id);
+                continue;
+            }
+            if done.contains(&id) {
+                continue;
+            }
+            if !visiting.insert(id) {
+                return None;
+            }
+            let i = *choices.get(&id)?;
+            cost += self.costs[id][i]?;
+            stack.push((id, true));
+            for c in self.egraph[id].nodes[i].children() {
+                let c = self.egraph.find(*c);
+                if visiting.contains(&c) {
+                    return None;
+                }
+                stack.push((c, false));
+            }
+        }
+        Some((cost, order))
+    }
+
+    /// Extract the expression rooted at `id` by refining a greedy extraction for at most `budget`.
+    /// Returns [None] if nothing can be extracted.
+    pub fn extract(&self, id: Id, budget: std::time::Duration) -> Option<egg::RecExpr<L>> {
+        use rand::RngExt;
+        let mut rng = self.rng.borrow_mut();
+        let start = std::time::Instant::now();
+        let id = self.egraph.find(id);
+        let mut choices = self.greedy();
+        let (mut cost, mut classes) = self.evaluate(&choices, id)?;
+        let mut best = (cost, choices.clone());
+
+        // Start hot enough to swap the average e-node for a slightly worse one
+        let mut temperature = cost / classes.len() as f64;
+        while start.elapsed() < budget {
+            let class = classes[rng.random_range(0..classes.len())];
+            let nodes = &self.egraph[class].nodes;
+            let choice = self.moves.choose(nodes);
+            let old = choices.insert(class, choice).unwrap();
+            if choice == old || self.costs[class][choice].is_none() {
+                choices.insert(class, old);
+                continue;
+            }
+
+            let accepted = match self.evaluate(&choices, id) {
+                Some((c, cs))
+                    if c <= cost || rng.random::<f64>() < ((cost - c) / temperature).exp() =>
+                {
+                    cost = c;
+                    classes = cs;
+                    true
+                }
+                _ => false,
+            };
+            if !accepted {
+                choices.insert(class, old);
+            } else if cost < best.0 {
+                best = (cost, choices.clone());
+            }
+            temperature *= Self::COOLING;
+        }
+
+        let mut expr = egg::RecExpr::default();
+        let mut memo: HashMap<Id, Id> = HashMap::new();
+        let (_, order) = self.evaluate(&best.1, id)?;
+        for c in order {
+            let node = self.egraph[c].nodes[best.1[&c]]
+                .clone()
+                .map_children(|child| memo[&self.egraph.find(child)]);
+            memo.insert(c, expr.add(node));
+        }
+        Some(expr)
+    }
+}
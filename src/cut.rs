@@ -0,0 +1,221 @@
+/*!
+
+  Priority cut enumeration, used to seed the e-graph with k-feasible LUTs.
+
+*/
+use super::driver::CircuitLang;
+use super::lut::VAR_PATTERNS;
+use egg::{Analysis, EGraph, Id, RecExpr};
+use std::collections::HashSet;
+
+/// A cut of a node: a set of leaves that separates it from the primary inputs
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Cut {
+    /// The sorted leaves of the cut
+    leaves: Vec<Id>,
+    /// The max depth of the leaves
+    arrival: usize,
+}
+
+impl Cut {
+    /// The cut of a node that only contains itself
+    fn trivial(id: Id, arrival: usize) -> Self {
+        Self {
+            leaves: vec![id],
+            arrival,
+        }
+    }
+
+    /// Merge two cuts, if the result has at most `k` leaves
+    fn merge(&self, other: &Self, k: usize) -> Option<Self> {
+        let mut leaves = self.leaves.clone();
+        leaves.extend(&other.leaves);
+        leaves.sort();
+        leaves.dedup();
+        if leaves.len() > k {
+            None
+        } else {
+            Some(Self {
+                leaves,
+                arrival: self.arrival.max(other.arrival),
+            })
+        }
+    }
+
+    /// Returns the leaves of the cut in ascending order
+    pub fn leaves(&self) -> &[Id] {
+        &self.leaves
+    }
+
+    /// Returns the depth of the node when the cut is implemented as a LUT
+    pub fn get_depth(&self) -> usize {
+        self.arrival + 1
+    }
+
+    /// The priority of a cut, lower is better
+    fn priority(&self) -> (usize, usize) {
+        (self.arrival, self.leaves.len())
+    }
+}
+
+/// The best `n` cuts with at most `k` leaves of every node in an expression
+pub struct CutEnumeration<'a, L: CircuitLang> {
+    expr: &'a RecExpr<L>,
+    /// The non-trivial cuts of each node, best first
+    cuts: Vec<Vec<Cut>>,
+    /// The depth of each node given its best cut
+    depth: Vec<usize>,
+}
+
+impl<'a, L: CircuitLang> CutEnumeration<'a, L> {
+    /// Enumerate the `n` best cuts with at most `k` leaves of every node in `expr`.
+    /// Cuts are ranked by depth, then by size.
+    pub fn new(expr: &'a RecExpr<L>, k: usize, n: usize) -> Self {
+        let nodes = expr.as_ref();
+        let mut cuts: Vec<Vec<Cut>> = Vec::with_capacity(nodes.len());
+        let mut depth: Vec<usize> = Vec::with_capacity(nodes.len());
+
+        for node in nodes {
+            let inputs = Self::signals(expr, node);
+            if !Self::is_logic(node) || inputs.is_empty() {
+                cuts.push(Vec::new());
+                depth.push(0);
+                continue;
+            }
+
+            // Merge the cuts of the inputs one at a time
+            let mut merged = vec![Cut {
+                leaves: Vec::new(),
+                arrival: 0,
+            }];
+            for c in inputs {
+                let i = usize::from(c);
+                let trivial = Cut::trivial(c, depth[i]);
+                let mut next: Vec<Cut> = Vec::new();
+                for m in &merged {
+                    for cut in cuts[i].iter().chain(std::iter::once(&trivial)) {
+                        if let Some(cut) = m.merge(cut, k)
+                            && !next.contains(&cut)
+                        {
+                            next.push(cut);
+                        }
+                    }
+                }
+                next.sort_by_key(|c| c.priority());
+                next.truncate(n * n);
+                merged = next;
+            }
+
+            // Drop cuts that are dominated by a smaller one
+            let mut best: Vec<Cut> = Vec::new();
+            for cut in merged {
+                let leaves: HashSet<&Id> = cut.leaves.iter().collect();
+                if !best
+                    .iter()
+                    .any(|b| b.leaves.iter().all(|l| leaves.contains(l)))
+                {
+                    best.push(cut);
+                }
+            }
+
+            best.truncate(n);
+            depth.push(best.first().map(|c| c.get_depth()).unwrap_or(0));
+            cuts.push(best);
+        }
+
+        Self { expr, cuts, depth }
+    }
+
+    /// Returns true if `node` is combinational logic that can be covered by a LUT
+    fn is_logic(node: &L) -> bool {
+        node.get_int().is_none() && node.simulate(&vec![0; node.len()]).is_some()
+    }
+
+    /// Returns the children of `node` that are signals and not parameters
+    fn signals(expr: &RecExpr<L>, node: &L) -> Vec<Id> {
+        node.children()
+            .iter()
+            .filter(|c| expr[**c].get_int().is_none())
+            .cloned()
+            .collect()
+    }
+
+    /// Returns the best non-trivial cuts of `id`
+    pub fn get_cuts(&self, id: Id) -> &[Cut] {
+        &self.cuts[usize::from(id)]
+    }
+
+    /// Returns the depth of `id` when every node is implemented with its best cut
+    pub fn get_depth(&self, id: Id) -> usize {
+        self.depth[usize::from(id)]
+    }
+
+    /// Returns the truth table of `id` as a function of the leaves of `cut`, the first leaf being the lsb
+    pub fn truth_table(&self, id: Id, cut: &Cut) -> u64 {
+        let mut cone: HashSet<Id> = HashSet::new();
+        let mut stack = vec![id];
+        while let Some(c) = stack.pop() {
+            if !cut.leaves.contains(&c) && cone.insert(c) {
+                stack.extend(self.expr[c].children());
+            }
+        }
+        let mut cone: Vec<Id> = cone.into_iter().collect();
+        cone.sort();
+
+        let mut sim: Vec<u64> = vec![0; usize::from(id) + 1];
+        for (i, l) in cut.leaves.iter().enumerate() {
+            sim[usize::from(*l)] = VAR_PATTERNS[i];
+        }
+        for c in cone {
+            let node = &self.expr[c];
+            sim[usize::from(c)] = match node.get_int() {
+                Some(p) => p,
+                None => {
+                    let children: Vec<u64> = node
+                        .children()
+                        .iter()
+                        .map(|c| sim[usize::from(*c)])
+                        .collect();
+                    node.simulate(&children).unwrap()
+                }
+            };
+        }
+
+        let tt = sim[usize::from(id)];
+        match cut.leaves.len() {
+            6 => tt,
+            n => tt & ((1 << (1 << n)) - 1),
+        }
+    }
+
+    /// Add the cuts of every node to `egraph` as LUTs, merged with the e-class of their node.
+    /// Returns the number of LUTs inserted.
+    pub fn seed<A: Analysis<L>>(&self, egraph: &mut EGraph<L, A>) -> usize {
+        let mut ids: Vec<Id> = Vec::with_capacity(self.cuts.len());
+        for node in self.expr.as_ref() {
+            let node = node.clone().map_children(|c| ids[usize::from(c)]);
+            ids.push(egraph.add_uncanonical(node));
+        }
+
+        let mut seeded = 0;
+        for (i, cuts) in self.cuts.iter().enumerate() {
+            for cut in cuts {
+                let Some(p) = L::int(self.truth_table(Id::from(i), cut)) else {
+                    return seeded;
+                };
+                let p = egraph.add_uncanonical(p);
+                // LUT operands are msb first
+                let operands = cut.leaves.iter().rev().map(|l| ids[usize::from(*l)]);
+                let Some(lut) = L::lut(std::iter::once(p).chain(operands)) else {
+                    return seeded;
+                };
+                let lut = egraph.add_uncanonical(lut);
+                if egraph.union_trusted(lut, ids[i], "priority-cut") {
+                    seeded += 1;
+                }
+            }
+        }
+        egraph.rebuild();
+        seeded
+    }
+}
@@ -0,0 +1,363 @@
+/*!
+
+  Don't care based LUT simplification. Every LUT is simulated within a bounded window of
+  its fanin and fanout cones. Input combinations that can't occur (satisfiability don't cares)
+  or whose output is never observed at the window outputs (observability don't cares) are
+  free to change, which often lets inputs be removed from the LUT.
+
+*/
+use super::check::Check;
+use super::driver::CircuitLang;
+use super::lut::VAR_PATTERNS;
+use egg::{Id, RecExpr};
+use std::collections::{BTreeSet, HashMap};
+
+/// The max number of leaves of a window, so that it can be simulated exhaustively
+const MAX_WINDOW_LEAVES: usize = 12;
+
+/// A window around a LUT: the logic to simulate and the nodes it is observed through
+struct Window {
+    /// The logic of the window in topological order
+    logic: Vec<usize>,
+    /// The inputs of the window
+    leaves: Vec<usize>,
+    /// The nodes of the window that are observed outside of it
+    outputs: Vec<usize>,
+}
+
+/// The state of the expression while it is being simplified
+struct DontCares<L: CircuitLang> {
+    /// The nodes of the expression. New programs are appended at the end.
+    nodes: Vec<L>,
+    /// The fanouts of each node
+    fanouts: Vec<Vec<usize>>,
+    /// The root of the expression
+    root: usize,
+}
+
+impl<L: CircuitLang> DontCares<L> {
+    fn new(expr: &RecExpr<L>) -> Self {
+        // Hash-cons the nodes, so that repeated variables are the same leaf
+        let mut nodes: Vec<L> = Vec::new();
+        let mut ids: Vec<usize> = Vec::with_capacity(expr.as_ref().len());
+        let mut memo: HashMap<L, usize> = HashMap::new();
+        for n in expr.as_ref() {
+            let n = n.clone().map_children(|c| Id::from(ids[usize::from(c)]));
+            let id = *memo.entry(n.clone()).or_insert_with(|| {
+                nodes.push(n);
+                nodes.len() - 1
+            });
+            ids.push(id);
+        }
+
+        let mut fanouts = vec![Vec::new(); nodes.len()];
+        for (i, n) in nodes.iter().enumerate() {
+            for c in n.children() {
+                fanouts[usize::from(*c)].push(i);
+            }
+        }
+        let root = *ids.last().unwrap();
+        Self {
+            nodes,
+            fanouts,
+            root,
+        }
+    }
+
+    /// Returns true if node `i` is combinational logic
+    fn is_logic(&self, i: usize) -> bool {
+        let n = &self.nodes[i];
+        n.get_int().is_none() && n.simulate(&vec![0; n.len()]).is_some()
+    }
+
+    /// Returns the children of node `i` that are signals and not parameters
+    fn signals(&self, i: usize) -> Vec<usize> {
+        self.nodes[i]
+            .children()
+            .iter()
+            .map(|c| usize::from(*c))
+            .filter(|c| self.nodes[*c].get_int().is_none())
+            .collect()
+    }
+
+    /// The window of LUT `n`, reaching `depth` levels into its fanout and at most `depth` levels into the fanin of those
+    fn window(&self, n: usize, depth: usize) -> Option<Window> {
+        let mut fanout: BTreeSet<usize> = BTreeSet::from([n]);
+        let mut frontier = vec![n];
+        for _ in 0..depth {
+            frontier = frontier
+                .iter()
+                .flat_map(|f| self.fanouts[*f].iter().cloned())
+                .filter(|f| self.is_logic(*f) && fanout.insert(*f))
+                .collect();
+        }
+
+        let outputs: Vec<usize> = fanout
+            .iter()
+            .filter(|f| *f == &self.root || self.fanouts[**f].iter().any(|o| !fanout.contains(o)))
+            .cloned()
+            .collect();
+
+        // Shrink the fanin cone until the window can be simulated exhaustively
+        for d in (0..=depth).rev() {
+            let mut logic = fanout.clone();
+            let mut frontier: Vec<usize> = fanout.iter().cloned().collect();
+            for _ in 0..d {
+                frontier = frontier
+                    .iter()
+                    .flat_map(|f| self.signals(*f))
+                    .filter(|f| self.is_logic(*f) && logic.insert(*f))
+                    .collect();
+            }
+
+            let leaves: BTreeSet<usize> = logic
+                .iter()
+                .flat_map(|l| self.signals(*l))
+                .filter(|l| !logic.contains(l))
+                .collect();
+            if leaves.len() <= MAX_WINDOW_LEAVES {
+                return Some(Window {
+                    logic: logic.into_iter().collect(),
+                    leaves: leaves.into_iter().collect(),
+                    outputs,
+                });
+            }
+        }
+        None
+    }
+
+    /// Simulate node `i` given the words of its children in `sim`
+    fn simulate(&self, i: usize, sim: &HashMap<usize, Vec<u64>>, words: usize) -> Vec<u64> {
+        let n = &self.nodes[i];
+        (0..words)
+            .map(|w| {
+                let c: Vec<u64> = n
+                    .children()
+                    .iter()
+                    .map(|c| match self.nodes[usize::from(*c)].get_int() {
+                        Some(p) => p,
+                        None => sim[&usize::from(*c)][w],
+                    })
+                    .collect();
+                n.simulate(&c).unwrap()
+            })
+            .collect()
+    }
+
+    /// Returns the input combinations of LUT `n` that are cared about, given its window.
+    /// Combinations are indexed like the LUT program, with the first operand as the msb.
+    fn care_set(&self, n: usize, window: &Window) -> u64 {
+        let words = 1 << window.leaves.len().saturating_sub(6);
+        let mut sim: HashMap<usize, Vec<u64>> = HashMap::new();
+        for (i, l) in window.leaves.iter().enumerate() {
+            let pattern = (0..words)
+                .map(|w| match i {
+                    i if i < 6 => VAR_PATTERNS[i],
+                    i if (w >> (i - 6)) & 1 == 1 => u64::MAX,
+                    _ => 0,
+                })
+                .collect();
+            sim.insert(*l, pattern);
+        }
+        for l in &window.logic {
+            let s = self.simulate(*l, &sim, words);
+            sim.insert(*l, s);
+        }
+
+        // Flip the LUT and see which patterns reach an output
+        let mut flipped = sim.clone();
+        flipped.insert(n, sim[&n].iter().map(|w| !w).collect());
+        for f in window.logic.iter().filter(|l| **l > n) {
+            let s = self.simulate(*f, &flipped, words);
+            flipped.insert(*f, s);
+        }
+
+        let signals = self.signals(n);
+        let k = signals.len();
+        let mut care: u64 = 0;
+        for w in 0..words {
+            let observed = window
+                .outputs
+                .iter()
+                .fold(0, |o, x| o | (sim[x][w] ^ flipped[x][w]));
+            for m in 0..1 << k {
+                if (care >> m) & 1 == 1 {
+                    continue;
+                }
+                let cube = signals.iter().enumerate().fold(observed, |t, (i, x)| {
+                    if (m >> (k - 1 - i)) & 1 == 1 {
+                        t & sim[x][w]
+                    } else {
+                        t & !sim[x][w]
+                    }
+                });
+                if cube != 0 {
+                    care |= 1 << m;
+                }
+            }
+        }
+        care
+    }
+
+    /// Build the window as a standalone expression with a bus of its outputs.
+    /// If given, `lut` replaces the program and signals of LUT `n`.
+    fn window_expr(&self, n: usize, window: &Window, lut: Option<(u64, &[usize])>) -> RecExpr<L> {
+        let mut expr: RecExpr<L> = RecExpr::default();
+        let mut map: HashMap<usize, Id> = HashMap::new();
+        for (i, l) in window.leaves.iter().enumerate() {
+            map.insert(*l, expr.add(L::var(format!("dc{i}").into())));
+        }
+        for l in &window.logic {
+            let id = match lut {
+                Some((p, signals)) if *l == n => {
+                    let p = expr.add(L::int(p).unwrap());
+                    let signals = signals.iter().map(|s| map[s]);
+                    expr.add(L::lut(std::iter::once(p).chain(signals)).unwrap())
+                }
+                _ => {
+                    let node = self.nodes[*l].clone().map_children(|c| {
+                        let c = usize::from(c);
+                        match map.get(&c) {
+                            Some(id) => *id,
+                            None => expr.add(self.nodes[c].clone()),
+                        }
+                    });
+                    expr.add(node)
+                }
+            };
+            map.insert(*l, id);
+        }
+        expr.add(L::bus(window.outputs.iter().map(|o| map[o])));
+        expr
+    }
+
+    /// Try to remove inputs of LUT `n` using the don't cares of a window `depth` levels deep.
+    /// Returns the number of inputs removed.
+    fn simplify(&mut self, n: usize, depth: usize) -> Result<usize, String> {
+        let Some(window) = self.window(n, depth) else {
+            return Ok(0);
+        };
+        let mut signals = self.signals(n);
+        let full = match signals.len() {
+            6 => u64::MAX,
+            k => (1 << (1 << k)) - 1,
+        };
+        let mut care = self.care_set(n, &window);
+        if care == full {
+            return Ok(0);
+        }
+
+        let mut p = self.nodes[usize::from(self.nodes[n].children()[0])]
+            .get_int()
+            .unwrap();
+        let mut removed = 0;
+        while let Some((i, (np, nc))) = (0..signals.len())
+            .filter(|_| signals.len() > 1)
+            .find_map(|i| drop_input(p, care, signals.len(), i).map(|r| (i, r)))
+        {
+            signals.remove(i);
+            p = np;
+            care = nc;
+            removed += 1;
+        }
+        if removed == 0 {
+            return Ok(0);
+        }
+
+        let before = self.window_expr(n, &window, None);
+        let after = self.window_expr(n, &window, Some((p, &signals)));
+        match L::check_expr(&before, &after) {
+            Check::Equiv => (),
+            Check::NotEquiv => {
+                return Err(format!(
+                    "Don't care simplification of node {n} changed its window: {before} vs. {after}"
+                ));
+            }
+            Check::Inconclusive => return Ok(0),
+        }
+
+        for c in self.signals(n) {
+            if !signals.contains(&c) {
+                self.fanouts[c].retain(|f| *f != n);
+            }
+        }
+        self.nodes.push(L::int(p).unwrap());
+        self.fanouts.push(vec![n]);
+        let p = Id::from(self.nodes.len() - 1);
+        self.nodes[n] =
+            L::lut(std::iter::once(p).chain(signals.into_iter().map(Id::from))).unwrap();
+        Ok(removed)
+    }
+
+    /// Returns the expression reachable from the root
+    fn into_expr(self) -> RecExpr<L> {
+        let mut reachable = vec![false; self.nodes.len()];
+        let mut stack = vec![self.root];
+        while let Some(i) = stack.pop() {
+            if !reachable[i] {
+                reachable[i] = true;
+                stack.extend(self.nodes[i].children().iter().map(|c| usize::from(*c)));
+            }
+        }
+
+        // New programs come after their LUTs, so parameters are added as they are used
+        let mut expr: RecExpr<L> = RecExpr::default();
+        let mut map: HashMap<usize, Id> = HashMap::new();
+        for (i, n) in self.nodes.iter().enumerate() {
+            if !reachable[i] || n.get_int().is_some() {
+                continue;
+            }
+            let node = n.clone().map_children(|c| {
+                let c = usize::from(c);
+                *map.entry(c)
+                    .or_insert_with(|| expr.add(self.nodes[c].clone()))
+            });
+            map.insert(i, expr.add(node));
+        }
+        expr
+    }
+}
+
+/// Make program `p` over `k` inputs independent of input `i` (msb first), only changing combinations outside of `care`.
+/// Returns the new program and care set over the remaining `k - 1` inputs.
+fn drop_input(p: u64, care: u64, k: usize, i: usize) -> Option<(u64, u64)> {
+    let b = k - 1 - i;
+    let mut np = 0;
+    let mut nc = 0;
+    for m in 0..1 << (k - 1) {
+        let m0 = ((m >> b) << (b + 1)) | (m & ((1 << b) - 1));
+        let m1 = m0 | (1 << b);
+        let (c0, c1) = ((care >> m0) & 1, (care >> m1) & 1);
+        let (v0, v1) = ((p >> m0) & 1, (p >> m1) & 1);
+        let v = match (c0, c1) {
+            (1, 1) if v0 != v1 => return None,
+            (0, 1) => v1,
+            _ => v0,
+        };
+        np |= v << m;
+        nc |= (c0 | c1) << m;
+    }
+    Some((np, nc))
+}
+
+/// Remove LUT inputs that are irrelevant under the don't cares of a window reaching `depth` levels around each LUT.
+/// Every rewrite is verified on its window before it is applied.
+/// Returns the simplified expression and the number of LUT inputs removed.
+pub fn simplify_dont_cares<L: CircuitLang>(
+    expr: &RecExpr<L>,
+    depth: usize,
+) -> Result<(RecExpr<L>, usize), String> {
+    if expr.as_ref().is_empty() {
+        return Ok((expr.clone(), 0));
+    }
+
+    let mut dc = DontCares::new(expr);
+    let mut removed = 0;
+    for n in 0..dc.nodes.len() {
+        // Dead LUTs are not observed at all
+        if dc.nodes[n].is_lut() && (n == dc.root || !dc.fanouts[n].is_empty()) {
+            removed += dc.simplify(n, depth)?;
+        }
+    }
+    Ok((dc.into_expr(), removed))
+}
@@ -3,26 +3,37 @@
   Common infrastructure to configure logic synthesis runs using egg.
 
 */
+use super::analysis::SoundnessCheck;
 use super::check::Check;
-use super::cost::NegativeCostFn;
-use super::lut::{CircuitStats, LutExprInfo, LutLang};
+use super::cost::{
+    DagCost, DagExtract, DepthBoundExtract, LocalSearchExtract, NegativeCostFn, ParetoPoint,
+};
+use super::cut::CutEnumeration;
+use super::dontcare::simplify_dont_cares;
+use super::fanout::FanoutLimit;
+#[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
+use super::ilp::{BestBound, IlpExtract, IlpObjective};
+use super::lut::{CircuitStats, LutExprInfo, LutLang, VAR_PATTERNS};
+use super::power::{ActivityModel, SwitchingActivity};
 #[cfg(feature = "graph_dumps")]
 use super::serialize::serialize_egraph;
+use super::timing::TimingModel;
 use super::verilog::PrimitiveType;
 use crate::cost::RandomExtract;
 use egg::{
-    Analysis, BackoffScheduler, CostFunction, Explanation, Extractor, FromOpError, Language,
-    RecExpr, RecExprParseError, Rewrite, Runner, StopReason, Symbol, TreeTerm,
+    Analysis, CostFunction, EGraph, Explanation, Extractor, FromOpError, Language, RecExpr,
+    RecExprParseError, Rewrite, RewriteScheduler, Runner, SearchMatches, StopReason, Symbol,
+    TreeTerm,
 };
 use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
 use simplelog::{ColorChoice, ConfigBuilder, TermLogger, TerminalMode};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::str::FromStr;
-use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 
-use log::{info, warn};
+use log::{debug, info, warn};
 use serde::Serialize;
 use std::{
     io::{IsTerminal, Read, Write},
@@ -63,6 +74,51 @@ where
 
     /// Rewrite the module name of the [Report].
     fn with_name(self, name: &str) -> Self;
+
+    /// Attach per-rule statistics from the e-graph build to the [Report].
+    /// By default, the statistics are discarded.
+    fn with_rule_stats(self, _stats: BTreeMap<String, RuleStats>) -> Self {
+        self
+    }
+
+    /// Attach the relative optimality gap of an exact extraction to the [Report].
+    /// By default, the gap is discarded.
+    fn with_optimality_gap(self, _gap: f64) -> Self {
+        self
+    }
+
+    /// Attach the slack of every output before and after optimization to the [Report].
+    /// By default, the slack is discarded.
+    fn with_slack(self, _slack: Comparison<BTreeMap<String, f32>>) -> Self {
+        self
+    }
+
+    /// Attach the seed of a random extraction to the [Report], so that it can be reproduced.
+    /// By default, the seed is discarded.
+    fn with_seed(self, _seed: u64) -> Self {
+        self
+    }
+
+    /// Attach the max fanout of any logic before and after a fanout-limited extraction to the [Report].
+    /// By default, the fanout is discarded.
+    fn with_max_fanout(self, _fanout: Comparison<usize>) -> Self {
+        self
+    }
+}
+
+/// Per-rule statistics gathered while building the e-graph.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct RuleStats {
+    /// The number of matches found by the rule
+    pub matches: u64,
+    /// The number of applications that changed the e-graph
+    pub applications: u64,
+    /// The time spent searching for matches in seconds
+    pub search_time: f64,
+    /// The time spent applying matches in seconds
+    pub apply_time: f64,
+    /// The number of times the rule was banned by the scheduler
+    pub times_banned: u64,
 }
 
 /// A struct to compare two results before and after some optimization.
@@ -95,6 +151,16 @@ pub struct SynthReport {
     num_iterations: u64,
     saturated: bool,
     circuit_stats: Comparison<CircuitStats>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    rule_stats: BTreeMap<String, RuleStats>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    optimality_gap: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slack: Option<Comparison<BTreeMap<String, f32>>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    seed: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_fanout: Option<Comparison<usize>>,
 }
 
 impl SynthReport {
@@ -151,9 +217,19 @@ impl SynthReport {
             num_nodes,
             num_iterations,
             circuit_stats,
+            rule_stats: BTreeMap::new(),
+            optimality_gap: None,
+            slack: None,
+            seed: None,
+            max_fanout: None,
         }
     }
 
+    /// Get the per-rule statistics of the e-graph build.
+    pub fn get_rule_stats(&self) -> &BTreeMap<String, RuleStats> {
+        &self.rule_stats
+    }
+
     /// Mark whether the input originally contained gates or not.
     pub fn contains_gates(self, v: bool) -> Self {
         Self {
@@ -182,6 +258,38 @@ impl Report<LutLang> for SynthReport {
             ..self
         }
     }
+
+    fn with_rule_stats(self, rule_stats: BTreeMap<String, RuleStats>) -> Self {
+        Self { rule_stats, ..self }
+    }
+
+    fn with_optimality_gap(self, gap: f64) -> Self {
+        Self {
+            optimality_gap: Some(gap),
+            ..self
+        }
+    }
+
+    fn with_slack(self, slack: Comparison<BTreeMap<String, f32>>) -> Self {
+        Self {
+            slack: Some(slack),
+            ..self
+        }
+    }
+
+    fn with_seed(self, seed: u64) -> Self {
+        Self {
+            seed: Some(seed),
+            ..self
+        }
+    }
+
+    fn with_max_fanout(self, fanout: Comparison<usize>) -> Self {
+        Self {
+            max_fanout: Some(fanout),
+            ..self
+        }
+    }
 }
 
 /// The output of a [SynthRequest] run.
@@ -265,6 +373,11 @@ where
         )
     }
 
+    /// Get the report of the output, if one was requested
+    pub fn get_report(&self) -> Option<&R> {
+        self.rpt.as_ref()
+    }
+
     /// Check if the output has an explanation.
     pub fn has_explanation(&self) -> bool {
         self.expl.is_some()
@@ -367,6 +480,113 @@ enum BuildStrat {
     Custom(u64, usize, usize),
 }
 
+impl BuildStrat {
+    /// Apply the build limits to a runner.
+    fn apply<L, A>(&self, runner: Runner<L, A>) -> Runner<L, A>
+    where
+        L: Language,
+        A: Analysis<L> + std::default::Default,
+    {
+        match self {
+            BuildStrat::TimeLimited(t) => runner
+                .with_time_limit(Duration::from_secs(*t))
+                .with_node_limit(usize::MAX)
+                .with_iter_limit(usize::MAX),
+            BuildStrat::SizeLimited(n) => runner
+                .with_node_limit(*n)
+                .with_time_limit(Duration::from_secs(31536000))
+                .with_iter_limit(usize::MAX),
+            BuildStrat::IterLimited(n) => runner
+                .with_iter_limit(*n)
+                .with_time_limit(Duration::from_secs(31536000))
+                .with_node_limit(usize::MAX),
+            BuildStrat::Custom(t, n, i) => runner
+                .with_time_limit(Duration::from_secs(*t))
+                .with_node_limit(*n)
+                .with_iter_limit(*i),
+        }
+    }
+}
+
+/// A phase of a staged rewrite schedule.
+/// Each phase runs its own subset of rules on the e-graph left behind by the previous phase.
+pub struct Phase<L, A>
+where
+    L: Language,
+    A: Analysis<L>,
+{
+    name: String,
+    rules: Vec<Rewrite<L, A>>,
+    build_strat: Option<BuildStrat>,
+}
+
+impl<L, A> Clone for Phase<L, A>
+where
+    L: Language,
+    A: Analysis<L> + Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            rules: self.rules.clone(),
+            build_strat: self.build_strat.clone(),
+        }
+    }
+}
+
+impl<L, A> Phase<L, A>
+where
+    L: Language,
+    A: Analysis<L>,
+{
+    /// Create a new phase named `name` that runs `rules`.
+    /// Unless limits are given, the phase uses the build limits of its [SynthRequest].
+    pub fn new(name: &str, rules: Vec<Rewrite<L, A>>) -> Self {
+        Self {
+            name: name.to_string(),
+            rules,
+            build_strat: None,
+        }
+    }
+
+    /// Limit the phase to `timeout` seconds.
+    pub fn time_limited(self, timeout: u64) -> Self {
+        Self {
+            build_strat: Some(BuildStrat::TimeLimited(timeout)),
+            ..self
+        }
+    }
+
+    /// Limit the phase to `node_limit` nodes.
+    pub fn node_limited(self, node_limit: usize) -> Self {
+        Self {
+            build_strat: Some(BuildStrat::SizeLimited(node_limit)),
+            ..self
+        }
+    }
+
+    /// Limit the phase to `iter_limit` rewrite iterations.
+    pub fn iter_limited(self, iter_limit: usize) -> Self {
+        Self {
+            build_strat: Some(BuildStrat::IterLimited(iter_limit)),
+            ..self
+        }
+    }
+
+    /// Limit the phase jointly. If any of the three limits are hit, the phase is stopped.
+    pub fn with_joint_limits(self, timeout: u64, node_limit: usize, iter_limit: usize) -> Self {
+        Self {
+            build_strat: Some(BuildStrat::Custom(timeout, node_limit, iter_limit)),
+            ..self
+        }
+    }
+
+    /// Get the name of the phase.
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+}
+
 /// An enum for the optimization strategies used to synthesize LUT/cell networks.
 #[derive(Debug, Clone)]
 enum OptStrat {
@@ -384,6 +604,12 @@ enum OptStrat {
     CellCountRegWeighted(usize, u64),
     /// Disassemble into set of logic gates.
     Disassemble(HashSet<String>),
+    /// Extract the best worst slack under a timing model with Cells/LUTs with at most `k` inputs.
+    Timing(usize, TimingModel),
+    /// Extract the fewest Cells/LUTs with at most `k` inputs such that the depth is at most `d`.
+    DepthBound(usize, usize),
+    /// Extract the least dynamic power with Cells/LUTs with at most `k` inputs, simulating the inputs of a model.
+    Power(usize, ActivityModel),
 }
 
 /// An enum for the extraction strategies used to synthesize LUT/cell networks.
@@ -391,8 +617,12 @@ enum OptStrat {
 enum ExtractStrat {
     /// Use greedy extraction algorithm.
     Greedy,
-    /// Use a random extraction (regardless of opt metric)
-    Random,
+    /// Use the best of `n` random extractions by the opt metric, seeded with `seed`.
+    Random(u64, usize),
+    /// Use greedy extraction that counts nodes shared in the DAG once.
+    Dag,
+    /// Refine a greedy extraction by local search for a budget in seconds, counting shared nodes once, seeded with `seed`.
+    LocalSearch(u64, u64),
     /// Use exact HiGHS ILP extraction with timeout in seconds.
     #[cfg(feature = "exact_highs")]
     Highs(u64),
@@ -411,24 +641,142 @@ enum RewriteStrat {
 }
 
 impl RewriteStrat {
-    /// Apply the rewrite strategy to a runner.
-    fn apply<L, A>(&self, runner: Runner<L, A>) -> Runner<L, A>
+    /// Apply the rewrite strategy to a runner, recording rule statistics into `profile`.
+    fn apply<L, A>(&self, runner: Runner<L, A>, profile: RuleProfile) -> Runner<L, A>
     where
         L: Language,
-        A: Analysis<L> + std::default::Default,
+        A: Analysis<L> + SoundnessCheck + std::default::Default,
     {
-        let bos = BackoffScheduler::default();
-
         // Use back-off scheduling on runner to avoid some rules starving others
         let bos = match self {
-            RewriteStrat::Boolean => bos.with_ban_length(1).with_initial_match_limit(960),
-            RewriteStrat::Algebraic => bos.with_ban_length(1).with_initial_match_limit(120),
+            RewriteStrat::Boolean => ProfilingScheduler::new(960, 1, profile),
+            RewriteStrat::Algebraic => ProfilingScheduler::new(120, 1, profile),
         };
 
         runner.with_scheduler(bos)
     }
 }
 
+/// Rule statistics shared between a [ProfilingScheduler] and its [SynthRequest]
+type RuleProfile = Arc<Mutex<BTreeMap<String, RuleStats>>>;
+
+/// The ban state of a single rule in a [ProfilingScheduler]
+#[derive(Debug, Default)]
+struct BanState {
+    banned_until: usize,
+    times_banned: usize,
+}
+
+/// A back-off scheduler that behaves like [egg::BackoffScheduler] but also profiles each rule.
+/// Rules whose matches exceed the limit are banned, and each ban doubles the limit and length.
+struct ProfilingScheduler {
+    match_limit: usize,
+    ban_length: usize,
+    bans: HashMap<Symbol, BanState>,
+    profile: RuleProfile,
+}
+
+impl ProfilingScheduler {
+    fn new(match_limit: usize, ban_length: usize, profile: RuleProfile) -> Self {
+        Self {
+            match_limit,
+            ban_length,
+            bans: HashMap::new(),
+            profile,
+        }
+    }
+
+    fn with_stats<F: FnOnce(&mut RuleStats)>(&self, name: Symbol, f: F) {
+        let mut profile = self.profile.lock().unwrap();
+        f(profile.entry(name.to_string()).or_default());
+    }
+}
+
+impl<L, A> RewriteScheduler<L, A> for ProfilingScheduler
+where
+    L: Language,
+    A: Analysis<L> + SoundnessCheck,
+{
+    fn can_stop(&mut self, iteration: usize) -> bool {
+        // Fast-forward the bans to unban at least one rule
+        let min_ban = self
+            .bans
+            .values()
+            .filter(|b| b.banned_until > iteration)
+            .map(|b| b.banned_until)
+            .min();
+
+        match min_ban {
+            None => true,
+            Some(min_ban) => {
+                let delta = min_ban - iteration;
+                for b in self.bans.values_mut() {
+                    if b.banned_until > iteration {
+                        b.banned_until -= delta;
+                    }
+                }
+                false
+            }
+        }
+    }
+
+    fn search_rewrite<'a>(
+        &mut self,
+        iteration: usize,
+        egraph: &EGraph<L, A>,
+        rewrite: &'a Rewrite<L, A>,
+    ) -> Vec<SearchMatches<'a, L>> {
+        let ban = self.bans.entry(rewrite.name).or_default();
+        if iteration < ban.banned_until {
+            return vec![];
+        }
+
+        let threshold = self
+            .match_limit
+            .checked_shl(ban.times_banned as u32)
+            .unwrap();
+        let start = Instant::now();
+        let matches = rewrite.search_with_limit(egraph, threshold.saturating_add(1));
+        let search_time = start.elapsed().as_secs_f64();
+        let total: usize = matches.iter().map(|m| m.substs.len()).sum();
+
+        let banned = total > threshold;
+        if banned {
+            ban.banned_until = iteration + (self.ban_length << ban.times_banned);
+            ban.times_banned += 1;
+        }
+
+        self.with_stats(rewrite.name, |s| {
+            s.matches += total as u64;
+            s.search_time += search_time;
+            s.times_banned += banned as u64;
+        });
+
+        if banned { vec![] } else { matches }
+    }
+
+    fn apply_rewrite(
+        &mut self,
+        _iteration: usize,
+        egraph: &mut EGraph<L, A>,
+        rewrite: &Rewrite<L, A>,
+        matches: Vec<SearchMatches<L>>,
+    ) -> usize {
+        let start = Instant::now();
+        egraph.analysis.blame(Some(rewrite.name));
+        let applied = rewrite.apply(egraph, &matches).len();
+        egraph.analysis.blame(None);
+        let apply_time = start.elapsed().as_secs_f64();
+
+        self.with_stats(rewrite.name, |s| {
+            s.applications += applied as u64;
+            s.apply_time += apply_time;
+        });
+
+        applied
+    }
+}
+
 /// The list of gates that must be reachable by the disassembling rewrite rule system.
 pub const GATE_WHITELIST_STR: &str = "MUX,AND,OR,XOR,NOT,INV,FDRE,FDSE,FDPE,FDCE,NAND,NOR";
 
@@ -487,19 +835,37 @@ where
 
     /// Returns the area cost function for the language, only selecting cells with fewer than `cut_size` inputs.
     /// Additionally, registers have a parameterized weight `w`.
-    fn cell_cost_with_reg_weight_fn(cut_size: usize, w: u64) -> impl CostFunction<Self>;
+    fn cell_cost_with_reg_weight_fn(
+        cut_size: usize,
+        w: u64,
+    ) -> impl CostFunction<Self, Cost: DagCost>;
 
     /// Returns the area cost function for the language, only selecting cells with fewer than `cut_size` inputs.
     /// In this case, registers have weight 1.
-    fn cell_cost_fn(cut_size: usize) -> impl CostFunction<Self> {
+    fn cell_cost_fn(cut_size: usize) -> impl CostFunction<Self, Cost: DagCost> {
         Self::cell_cost_with_reg_weight_fn(cut_size, 1)
     }
 
     /// Returns the cost function using *real* cell areas.
-    fn exact_area_cost_fn() -> impl CostFunction<Self>;
+    fn exact_area_cost_fn() -> impl CostFunction<Self, Cost: DagCost>;
 
     /// Returns a cost function used for extracting only certain types nodes.
-    fn filter_cost_fn(set: HashSet<String>) -> impl CostFunction<Self>;
+    fn filter_cost_fn(set: HashSet<String>) -> impl CostFunction<Self, Cost: DagCost>;
+
+    /// Returns the cost function for the arrival times of `model`, only selecting cells with fewer than `cut_size` inputs.
+    fn timing_cost_fn(cut_size: usize, model: TimingModel) -> impl CostFunction<Self, Cost = f32>;
+
+    /// Returns the cost function for dynamic power under `activity`, only selecting cells with fewer than `cut_size` inputs.
+    fn power_cost_fn(
+        cut_size: usize,
+        activity: SwitchingActivity<Self>,
+    ) -> impl CostFunction<Self, Cost = f32>;
+
+    /// Returns true if the node is a cell with more than `cut_size` inputs.
+    fn exceeds_cut_size(&self, cut_size: usize) -> bool;
+
+    /// Returns true if the node is combinational logic, which can be duplicated to reduce fanout.
+    fn is_logic(&self) -> bool;
 }
 
 /// A trait to represent that an expression is not best explained by relating its roots.
@@ -537,6 +903,9 @@ pub trait CircuitLang:
     /// Returns a node that stores a constant integer parameter if the Lang supports it
     fn int(x: u64) -> Option<Self>;
 
+    /// Returns a lookup table over `ids` (program first) if the Lang supports it
+    fn lut(ids: impl Iterator<Item = egg::Id>) -> Option<Self>;
+
     /// Returns true is the node is a bus
     fn is_bus(&self) -> bool;
 
@@ -548,10 +917,20 @@ pub trait CircuitLang:
 
     /// Returns the symbol of the node, if is a variable
     fn get_var(&self) -> Option<Symbol>;
+
+    /// Evaluates the node on 64 input patterns at once, given the patterns of its children.
+    /// Integer parameters are given by value. Returns `None` if the node is not combinational logic.
+    fn simulate(&self, children: &[u64]) -> Option<u64>;
 }
 
 type PurgeFn<L> = Arc<dyn Fn(&L) -> bool + 'static>;
 
+/// Decides if a node should be pruned, given the e-graph and all the nodes of its e-class
+type PruneFn<L, A> = Arc<dyn Fn(&EGraph<L, A>, &L, &[L]) -> bool + 'static>;
+
+/// Modifies the e-graph before every rewrite iteration
+type GraphHook<L, A> = Arc<dyn Fn(&mut EGraph<L, A>) -> Result<(), String> + 'static>;
+
 /// A request to explore and extract an expression.
 /// The request can be configured with various options
 /// before dedicating to a particular input and compilation strategy.
@@ -607,8 +986,35 @@ where
     /// A function to purge the e-graph with before extraction.
     purge_fn: Option<PurgeFn<L>>,
 
+    /// A function to prune the e-graph with before every rewrite iteration.
+    prune_fn: Option<PruneFn<L, A>>,
+
+    /// Prunes the nodes of the e-graph that cost too much before every rewrite iteration.
+    cost_prune: Option<GraphHook<L, A>>,
+
+    /// The max support of equivalences proven by functionally sweeping the e-graph before every rewrite iteration.
+    sweep: Option<usize>,
+
+    /// The cut size and number of priority cuts per node to seed the e-graph with.
+    cut_seeds: Option<(usize, usize)>,
+
+    /// The depth of the windows used to simplify LUTs with don't cares before exploration.
+    dont_cares: Option<usize>,
+
+    /// The analysis to build the e-graph with (otherwise the default)
+    analysis: Option<A>,
+
     /// The running result
     result: Option<Runner<L, A>>,
+
+    /// Per-rule statistics of the running result
+    profile: RuleProfile,
+
+    /// The phases of a staged rewrite schedule, run before `rules`
+    phases: Vec<Phase<L, A>>,
+
+    /// The fanout limit to duplicate the extracted logic with
+    fanout_limit: Option<FanoutLimit>,
 }
 
 impl<L: Language, A: Analysis<L>> std::default::Default for SynthRequest<L, A> {
@@ -628,7 +1034,16 @@ impl<L: Language, A: Analysis<L>> std::default::Default for SynthRequest<L, A> {
             max_canon_size: MAX_CANON_SIZE,
             canonicalized: false,
             purge_fn: None,
+            prune_fn: None,
+            cost_prune: None,
+            sweep: None,
+            cut_seeds: None,
+            dont_cares: None,
+            analysis: None,
             result: None,
+            profile: RuleProfile::default(),
+            phases: Vec::new(),
+            fanout_limit: None,
             #[cfg(feature = "graph_dumps")]
             dump_egraph: None,
         }
@@ -652,13 +1067,133 @@ impl<L: Language, A: Analysis<L> + std::clone::Clone> std::clone::Clone for Synt
             max_canon_size: self.max_canon_size,
             canonicalized: self.canonicalized,
             purge_fn: self.purge_fn.clone(),
+            prune_fn: self.prune_fn.clone(),
+            cost_prune: self.cost_prune.clone(),
+            sweep: self.sweep,
+            cut_seeds: self.cut_seeds,
+            dont_cares: self.dont_cares,
+            analysis: self.analysis.clone(),
             result: None,
+            profile: RuleProfile::default(),
+            phases: self.phases.clone(),
+            fanout_limit: self.fanout_limit.clone(),
             #[cfg(feature = "graph_dumps")]
             dump_egraph: self.dump_egraph.clone(),
         }
     }
 }
 
+/// Returns an error listing the unsound merges recorded by the analysis of `egraph`, if any.
+fn check_soundness<L, A>(egraph: &EGraph<L, A>) -> Result<(), String>
+where
+    L: Language,
+    A: Analysis<L> + SoundnessCheck,
+{
+    let unsound = egraph.analysis.unsound_merges();
+    if unsound.is_empty() {
+        return Ok(());
+    }
+
+    let mut msg = format!("Found {} unsound merge(s):", unsound.len());
+    for u in unsound {
+        msg.push_str(&format!("\n\t{u}"));
+    }
+    Err(msg)
+}
+
+/// Prune the e-graph of nodes that `f` finds dominated by the rest of their e-class.
+/// At least one node is always kept in each e-class.
+fn prune_graph<L, A>(egraph: &mut EGraph<L, A>, f: &PruneFn<L, A>) -> Result<(), String>
+where
+    L: Language,
+    A: Analysis<L>,
+{
+    let pruned: Vec<(egg::Id, Vec<L>)> = egraph
+        .classes()
+        .filter_map(|class| {
+            let kept: Vec<L> = class
+                .nodes
+                .iter()
+                .filter(|n| !f(egraph, n, &class.nodes))
+                .cloned()
+                .collect();
+            if kept.is_empty() || kept.len() == class.nodes.len() {
+                None
+            } else {
+                Some((class.id, kept))
+            }
+        })
+        .collect();
+
+    for (id, nodes) in pruned {
+        egraph[id].nodes = nodes;
+    }
+    Ok(())
+}
+
+/// Prune the e-graph of nodes whose cost under `cost_fn` is more than `slack` times the cheapest node of their e-class.
+/// The cost of a node includes the cheapest costs of its children, so the cheapest node of each e-class is kept.
+/// Nodes that can't be extracted yet are kept, since they may become extractable.
+fn cost_prune_graph<L, A, C>(
+    egraph: &mut EGraph<L, A>,
+    cost_fn: &mut C,
+    slack: f64,
+) -> Result<(), String>
+where
+    L: Language,
+    A: Analysis<L>,
+    C: CostFunction<L, Cost: DagCost>,
+{
+    let mut best: HashMap<egg::Id, C::Cost> = HashMap::new();
+    let mut node_cost = |best: &HashMap<egg::Id, C::Cost>, n: &L| {
+        n.all(|c| best.contains_key(&egraph.find(c)))
+            .then(|| cost_fn.cost(n, |c| best[&egraph.find(c)]))
+    };
+
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for class in egraph.classes() {
+            for n in &class.nodes {
+                if let Some(c) = node_cost(&best, n)
+                    && best.get(&class.id).is_none_or(|b| c < *b)
+                {
+                    best.insert(class.id, c);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let pruned: Vec<(egg::Id, Vec<L>)> = egraph
+        .classes()
+        .filter_map(|class| {
+            let bound = best.get(&class.id)?.finite()? * slack;
+            let kept: Vec<L> = class
+                .nodes
+                .iter()
+                .filter(|n| {
+                    node_cost(&best, n).is_none_or(|c| c.finite().is_some_and(|c| c <= bound))
+                })
+                .cloned()
+                .collect();
+            (!kept.is_empty() && kept.len() < class.nodes.len()).then_some((class.id, kept))
+        })
+        .collect();
+
+    debug!(
+        "Cost pruning removed {} nodes",
+        pruned
+            .iter()
+            .map(|(id, kept)| egraph[*id].nodes.len() - kept.len())
+            .sum::<usize>()
+    );
+    for (id, nodes) in pruned {
+        egraph[id].nodes = nodes;
+    }
+    Ok(())
+}
+
 /// Purge the e-graph of nodes that satisfy the predicate `f`.
 /// Also, delete self-loops.
 fn purge_graph<L, A, F: Fn(&L) -> bool>(egraph: &mut egg::EGraph<L, A>, f: F) -> Result<(), String>
@@ -680,45 +1215,268 @@ where
     Ok(())
 }
 
-impl<L, A> SynthRequest<L, A>
+/// The number of 64-bit random patterns used to compute simulation signatures while sweeping
+const SWEEP_WORDS: usize = 4;
+
+/// A deterministic stream of pseudo-random simulation patterns (splitmix64)
+fn sweep_pattern(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+/// How an e-class is evaluated during functional sweeping
+#[derive(Debug, Clone)]
+pub(crate) enum SweepNode<L> {
+    /// An integer parameter, like a LUT program
+    Int(u64),
+    /// A primary input or a class that can't be simulated, like a register
+    Input,
+    /// A combinational node whose children are evaluated first
+    Logic(L),
+}
+
+/// Evaluate the classes in `order` on the words of `inputs`. Classes missing from `inputs` must be [SweepNode::Logic].
+pub(crate) fn sweep_simulate<L: CircuitLang>(
+    order: &[egg::Id],
+    reps: &HashMap<egg::Id, SweepNode<L>>,
+    mut inputs: HashMap<egg::Id, Vec<u64>>,
+    words: usize,
+) -> HashMap<egg::Id, Vec<u64>> {
+    for id in order {
+        let sim = match &reps[id] {
+            SweepNode::Int(p) => vec![*p; words],
+            SweepNode::Input => continue,
+            SweepNode::Logic(n) => (0..words)
+                .map(|w| {
+                    let c: Vec<u64> = n.children().iter().map(|c| inputs[c][w]).collect();
+                    n.simulate(&c).unwrap()
+                })
+                .collect(),
+        };
+        inputs.insert(*id, sim);
+    }
+    inputs
+}
+
+/// Pick a node to evaluate each class of `egraph` with, and return the classes in topological order.
+/// Classes that are cut off from simulation, like register outputs, are treated as free inputs.
+pub(crate) fn sweep_order<L, A>(
+    egraph: &EGraph<L, A>,
+) -> (HashMap<egg::Id, SweepNode<L>>, Vec<egg::Id>)
 where
     L: CircuitLang,
-    A: Analysis<L> + Default,
+    A: Analysis<L>,
 {
-    /// Request greedy extraction of cells/LUTs with at most `k` inputs.
-    pub fn with_k(self, k: usize) -> Self {
-        Self {
-            opt_strat: OptStrat::CellCount(k),
-            extract_strat: ExtractStrat::Greedy,
-            ..self
+    // Pick a representative node for each class in topological order
+    let mut reps: HashMap<egg::Id, SweepNode<L>> = HashMap::new();
+    let mut order: Vec<egg::Id> = Vec::new();
+    let mut classes: Vec<egg::Id> = egraph.classes().map(|c| c.id).collect();
+    classes.sort();
+    for id in &classes {
+        let class = &egraph[*id];
+        if let Some(p) = class.nodes.iter().find_map(|n| n.get_int()) {
+            reps.insert(*id, SweepNode::Int(p));
+            order.push(*id);
+        } else if class.nodes.iter().any(|n| n.get_var().is_some()) {
+            reps.insert(*id, SweepNode::Input);
+            order.push(*id);
         }
     }
 
-    /// Request greedy extraction of cells/LUTs with at most `k` inputs and registers with weight `w`.
-    pub fn with_klut_regw(self, k: usize, w: u64) -> Self {
-        Self {
-            opt_strat: OptStrat::CellCountRegWeighted(k, w),
-            extract_strat: ExtractStrat::Greedy,
-            ..self
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for id in &classes {
+            if reps.contains_key(id) {
+                continue;
+            }
+            let rep = egraph[*id].nodes.iter().find(|n| {
+                n.all(|c| reps.contains_key(&c)) && n.simulate(&vec![0; n.len()]).is_some()
+            });
+            if let Some(n) = rep {
+                reps.insert(*id, SweepNode::Logic(n.clone()));
+                order.push(*id);
+                changed = true;
+            }
         }
     }
 
-    /// Request greedy extraction using *real* cell areas.
-    pub fn with_area(self) -> Self {
-        Self {
-            opt_strat: OptStrat::Area,
-            extract_strat: ExtractStrat::Greedy,
-            ..self
+    // The rest is cut off from simulation, so it is treated as free inputs
+    for id in &classes {
+        if !reps.contains_key(id) {
+            reps.insert(*id, SweepNode::Input);
+            order.push(*id);
         }
     }
 
-    /// Request greedy extraction by syntax complexity.
-    pub fn with_ast_size(self) -> Self {
-        Self {
-            opt_strat: OptStrat::AstSize,
-            extract_strat: ExtractStrat::Greedy,
-            ..self
-        }
+    (reps, order)
+}
+
+/// Functionally sweep the e-graph (like ABC's fraig): classes are simulated on random patterns and
+/// grouped by signature. Candidate pairs are proven equivalent by exhaustively simulating their
+/// cones over at most `max_support` inputs, and proven pairs are merged.
+/// Returns the number of classes merged.
+fn sweep_graph<L, A>(egraph: &mut EGraph<L, A>, max_support: usize) -> Result<usize, String>
+where
+    L: CircuitLang,
+    A: Analysis<L> + SoundnessCheck,
+{
+    let (reps, order) = sweep_order(egraph);
+
+    // Support of each class, if small enough to be proven exhaustively
+    let mut support: HashMap<egg::Id, Option<Vec<egg::Id>>> = HashMap::new();
+    for id in &order {
+        let s = match &reps[id] {
+            SweepNode::Int(_) => Some(vec![]),
+            SweepNode::Input => Some(vec![*id]),
+            SweepNode::Logic(n) => {
+                let mut s: Vec<egg::Id> = Vec::new();
+                let mut bounded = true;
+                for c in n.children() {
+                    match &support[c] {
+                        Some(cs) => s.extend(cs),
+                        None => bounded = false,
+                    }
+                }
+                s.sort();
+                s.dedup();
+                (bounded && s.len() <= max_support).then_some(s)
+            }
+        };
+        support.insert(*id, s);
+    }
+
+    // Random simulation
+    let inputs = order
+        .iter()
+        .filter(|id| matches!(reps[id], SweepNode::Input))
+        .map(|id| {
+            let seed = usize::from(*id) as u64 * SWEEP_WORDS as u64;
+            (
+                *id,
+                (0..SWEEP_WORDS as u64)
+                    .map(|w| sweep_pattern(seed + w))
+                    .collect(),
+            )
+        })
+        .collect();
+    let sims = sweep_simulate(&order, &reps, inputs, SWEEP_WORDS);
+
+    let mut groups: BTreeMap<&[u64], Vec<egg::Id>> = BTreeMap::new();
+    for id in &order {
+        if !matches!(reps[id], SweepNode::Int(_)) {
+            groups.entry(sims[id].as_slice()).or_default().push(*id);
+        }
+    }
+
+    // Prove candidates equivalent by exhaustive simulation of their cones
+    let mut proven: Vec<(egg::Id, egg::Id)> = Vec::new();
+    for group in groups.values().filter(|g| g.len() > 1) {
+        let a = group[0];
+        for b in &group[1..] {
+            let (Some(sa), Some(sb)) = (&support[&a], &support[b]) else {
+                continue;
+            };
+            let mut vars: Vec<egg::Id> = sa.iter().chain(sb).cloned().collect();
+            vars.sort();
+            vars.dedup();
+            if vars.len() > max_support {
+                continue;
+            }
+
+            let words = 1 << vars.len().saturating_sub(6);
+            let inputs = vars
+                .iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let pattern = (0..words)
+                        .map(|w| match i {
+                            0..6 => VAR_PATTERNS[i],
+                            _ if (w >> (i - 6)) & 1 == 1 => u64::MAX,
+                            _ => 0,
+                        })
+                        .collect();
+                    (*v, pattern)
+                })
+                .collect();
+
+            // Collect the cone of the pair in topological order
+            let mut cone: HashSet<egg::Id> = HashSet::new();
+            let mut stack = vec![a, *b];
+            while let Some(id) = stack.pop() {
+                if vars.contains(&id) || !cone.insert(id) {
+                    continue;
+                }
+                if let SweepNode::Logic(n) = &reps[&id] {
+                    stack.extend(n.children());
+                }
+            }
+            let cone: Vec<egg::Id> = order
+                .iter()
+                .filter(|id| cone.contains(id))
+                .cloned()
+                .collect();
+
+            let sims = sweep_simulate(&cone, &reps, inputs, words);
+            if sims[&a] == sims[b] {
+                proven.push((a, *b));
+            }
+        }
+    }
+
+    egraph.analysis.blame(Some("sweep".into()));
+    let mut merged = 0;
+    for (a, b) in proven {
+        if egraph.union_trusted(b, a, "sweep") {
+            merged += 1;
+        }
+    }
+    egraph.rebuild();
+    egraph.analysis.blame(None);
+    Ok(merged)
+}
+
+impl<L, A> SynthRequest<L, A>
+where
+    L: CircuitLang + 'static,
+    A: Analysis<L> + SoundnessCheck + Default + 'static,
+{
+    /// Request greedy extraction of cells/LUTs with at most `k` inputs.
+    pub fn with_k(self, k: usize) -> Self {
+        Self {
+            opt_strat: OptStrat::CellCount(k),
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
+    }
+
+    /// Request greedy extraction of cells/LUTs with at most `k` inputs and registers with weight `w`.
+    pub fn with_klut_regw(self, k: usize, w: u64) -> Self {
+        Self {
+            opt_strat: OptStrat::CellCountRegWeighted(k, w),
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
+    }
+
+    /// Request greedy extraction using *real* cell areas.
+    pub fn with_area(self) -> Self {
+        Self {
+            opt_strat: OptStrat::Area,
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
+    }
+
+    /// Request greedy extraction by syntax complexity.
+    pub fn with_ast_size(self) -> Self {
+        Self {
+            opt_strat: OptStrat::AstSize,
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
     }
 
     /// Request exact extraction using HiGHS to solve ILP with `timeout` in seconds.
@@ -739,6 +1497,25 @@ where
         }
     }
 
+    /// Extract the fewest cells with at most `k` inputs such that the circuit depth is at most `d`.
+    pub fn with_depth_bound(self, k: usize, d: usize) -> Self {
+        Self {
+            opt_strat: OptStrat::DepthBound(k, d),
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
+    }
+
+    /// Extract the least dynamic power with cells with at most `k` inputs.
+    /// The switching activity is estimated by simulating the e-graph on the inputs of `model`.
+    pub fn with_power(self, k: usize, model: ActivityModel) -> Self {
+        Self {
+            opt_strat: OptStrat::Power(k, model),
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
+    }
+
     /// Extract based on minimum circuit depth.
     pub fn with_min_depth(self) -> Self {
         Self {
@@ -748,6 +1525,16 @@ where
         }
     }
 
+    /// Extract based on the worst slack under the arrival, required, and delay times of `model`.
+    /// Only cells/LUTs with at most `k` inputs are extracted.
+    pub fn with_timing(self, k: usize, model: TimingModel) -> Self {
+        Self {
+            opt_strat: OptStrat::Timing(k, model),
+            extract_strat: ExtractStrat::Greedy,
+            ..self
+        }
+    }
+
     /// Extract based on maximum circuit depth. *Does not work with cycles in e-graph.*
     pub fn with_max_depth(self) -> Self {
         Self {
@@ -759,8 +1546,49 @@ where
 
     /// Extract randomly.
     pub fn with_randomness(self) -> Self {
+        self.with_random_samples(None, 1)
+    }
+
+    /// Extract the best of `samples` random extractions by the opt metric.
+    /// The same `seed` makes the same choices on the same e-graph, so the e-graph should be iteration limited.
+    /// Without one, a fresh seed is drawn.
+    pub fn with_random_samples(self, seed: Option<u64>, samples: usize) -> Self {
+        use rand::{RngExt, rng};
+        Self {
+            extract_strat: ExtractStrat::Random(seed.unwrap_or_else(|| rng().random()), samples),
+            ..self
+        }
+    }
+
+    /// Extract greedily, counting the cells/LUTs shared by several fanouts once.
+    /// Depth is not affected by sharing, so depth and timing optimization are rejected.
+    pub fn with_dag_extraction(self) -> Self {
         Self {
-            extract_strat: ExtractStrat::Random,
+            extract_strat: ExtractStrat::Dag,
+            ..self
+        }
+    }
+
+    /// Refine the greedy extraction by simulated annealing for `budget` seconds, counting shared cells/LUTs once.
+    /// Depth is not affected by sharing, so depth and timing optimization are rejected.
+    /// The same `seed` makes the same moves, but the number of moves depends on the budget.
+    /// Without one, a fresh seed is drawn.
+    pub fn with_local_search(self, budget: u64, seed: Option<u64>) -> Self {
+        use rand::{RngExt, rng};
+        Self {
+            extract_strat: ExtractStrat::LocalSearch(
+                budget,
+                seed.unwrap_or_else(|| rng().random()),
+            ),
+            ..self
+        }
+    }
+
+    /// Duplicate the extracted logic that drives more loads than `limit` allows.
+    /// Copies are weighed by the exact area cost of the language.
+    pub fn with_fanout_limit(self, limit: FanoutLimit) -> Self {
+        Self {
+            fanout_limit: Some(limit),
             ..self
         }
     }
@@ -911,6 +1739,15 @@ where
         self
     }
 
+    /// Add a `phase` to the staged rewrite schedule.
+    /// Phases run in the order they are added, each on the e-graph left by the one before.
+    /// Any rules given with [SynthRequest::with_rules] run afterwards as one final phase.
+    pub fn with_phase(mut self, phase: Phase<L, A>) -> Self {
+        self.phases.push(phase);
+        self.result = None;
+        self
+    }
+
     /// Clear the rules currently stored in the request.
     pub fn clear_rules(self) -> Self {
         Self {
@@ -932,45 +1769,215 @@ where
         }
     }
 
+    /// Build the e-graph with `analysis` instead of the default one.
+    pub fn with_analysis(self, analysis: A) -> Self {
+        Self {
+            analysis: Some(analysis),
+            result: None,
+            ..self
+        }
+    }
+
+    /// Prune nodes from the e-graph before every rewrite iteration to keep growth bounded.
+    /// `f` is given the e-graph, a node, and all the nodes of its e-class. It returns true to prune the node.
+    /// Unlike [SynthRequest::with_purge_fn], pruned nodes are never rewritten again.
+    pub fn with_prune_fn<F>(self, f: F) -> Self
+    where
+        F: Fn(&EGraph<L, A>, &L, &[L]) -> bool + 'static,
+    {
+        Self {
+            prune_fn: Some(Arc::new(f)),
+            result: None,
+            ..self
+        }
+    }
+
+    /// Prune LUTs with more than `k` inputs from e-classes that already have a k-feasible node,
+    /// i.e. a leaf or a LUT with at most `k` inputs.
+    pub fn with_k_pruning(self, k: usize) -> Self {
+        let feasible = move |n: &L| n.is_leaf() || (n.is_lut() && n.children().len() <= k + 1);
+        self.with_prune_fn(move |_, n, class| {
+            n.is_lut() && !feasible(n) && class.iter().any(feasible)
+        })
+    }
+
+    /// Prune nodes from the e-graph before every rewrite iteration when their cost under `cost_fn` is more than
+    /// `slack` times the cheapest node of their e-class. The cost of a node includes the cheapest costs of its children,
+    /// so nodes of infinite cost are always pruned from e-classes with a finite alternative.
+    pub fn with_cost_pruning<C>(self, cost_fn: C, slack: f64) -> Self
+    where
+        C: CostFunction<L, Cost: DagCost> + 'static,
+    {
+        let cost_fn = std::cell::RefCell::new(cost_fn);
+        Self {
+            cost_prune: Some(Arc::new(move |egraph| {
+                cost_prune_graph(egraph, &mut *cost_fn.borrow_mut(), slack)
+            })),
+            result: None,
+            ..self
+        }
+    }
+
+    /// Functionally sweep the e-graph before every rewrite iteration, merging e-classes that are proven
+    /// to compute the same function over at most `max_support` inputs.
+    /// This finds sharing that no sequence of rewrites connects.
+    pub fn with_sweeping(self, max_support: usize) -> Self {
+        Self {
+            sweep: Some(max_support),
+            result: None,
+            ..self
+        }
+    }
+
+    /// Seed the e-graph with LUTs covering the `n` best cuts with at most `k` inputs of every node in the input.
+    /// This gives a good starting cover when saturation stops early.
+    pub fn with_cut_seeding(self, k: usize, n: usize) -> Self {
+        Self {
+            cut_seeds: Some((k, n)),
+            result: None,
+            ..self
+        }
+    }
+
+    /// Simplify the LUTs of the input with the don't cares of a window reaching `depth` levels around each LUT.
+    /// Inputs that are irrelevant wherever a LUT is observed are removed before exploration.
+    pub fn with_dont_care_simplification(self, depth: usize) -> Self {
+        Self {
+            dont_cares: Some(depth),
+            result: None,
+            ..self
+        }
+    }
+
     /// Return a reference to the underlying expression
     pub fn get_expr(&self) -> &RecExpr<L> {
         &self.expr
     }
 
+    /// Install the pruning and sweeping hooks on `runner`, if they were requested.
+    fn with_graph_hooks(&self, runner: Runner<L, A>) -> Runner<L, A> {
+        let runner = match &self.prune_fn {
+            Some(f) => {
+                let f = f.clone();
+                runner.with_hook(move |r| prune_graph(&mut r.egraph, &f))
+            }
+            None => runner,
+        };
+        let runner = match &self.cost_prune {
+            Some(f) => {
+                let f = f.clone();
+                runner.with_hook(move |r| f(&mut r.egraph))
+            }
+            None => runner,
+        };
+        match self.sweep {
+            Some(max_support) => runner.with_hook(move |r| {
+                let merged = sweep_graph(&mut r.egraph, max_support)?;
+                debug!("Sweeping merged {merged} e-classes");
+                Ok(())
+            }),
+            None => runner,
+        }
+    }
+
+    /// Add the input expression to `runner`, along with its priority cuts if requested.
+    fn with_seeded_expr(&self, runner: Runner<L, A>) -> Runner<L, A> {
+        let mut runner = runner.with_expr(&self.expr);
+        if let Some((k, n)) = self.cut_seeds {
+            runner.egraph.analysis.blame(Some("priority-cut".into()));
+            let seeded = CutEnumeration::new(&self.expr, k, n).seed(&mut runner.egraph);
+            runner.egraph.analysis.blame(None);
+            info!("Seeded e-graph with {seeded} LUTs from priority cuts");
+        }
+        runner
+    }
+
+    /// Run each phase of the staged schedule, carrying the e-graph from one phase to the next.
+    /// Returns the runner of the last phase, holding the iterations of all phases.
+    fn run_phases(&self, interrupt: &Arc<AtomicBool>, analysis: A) -> Runner<L, A> {
+        let mut iterations = Vec::new();
+        let mut prev: Option<Runner<L, A>> = None;
+        let mut analysis = Some(analysis);
+
+        for phase in &self.phases {
+            // Only the first phase builds a new e-graph
+            let runner = Runner::new(analysis.take().unwrap_or_default());
+            let runner = if self.gen_proof {
+                runner.with_explanations_enabled()
+            } else {
+                runner.with_explanations_disabled()
+            };
+            let runner = self.rewrite_strat.apply(runner, self.profile.clone());
+            let runner = phase
+                .build_strat
+                .as_ref()
+                .unwrap_or(&self.build_strat)
+                .apply(runner);
+            let hook_interrupt = Arc::clone(interrupt);
+            let runner = runner.with_hook(move |r| report_progress(r, &hook_interrupt, None, None));
+            let runner = self.with_graph_hooks(runner);
+            let runner = runner.with_hook(|r| check_soundness(&r.egraph));
+            let runner = match prev.take() {
+                None => self.with_seeded_expr(runner),
+                Some(mut p) => {
+                    iterations.append(&mut p.iterations);
+                    let mut runner = runner.with_egraph(p.egraph);
+                    runner.roots = p.roots;
+                    runner
+                }
+            };
+
+            info!(
+                "Running phase {} with {} rewrite rules...",
+                phase.name,
+                phase.rules.len()
+            );
+            let runner = runner.run(&phase.rules);
+            info!(
+                "Phase {} grown to {} nodes with reason {:?}",
+                phase.name,
+                runner.egraph.total_number_of_nodes(),
+                runner.stop_reason.as_ref().unwrap()
+            );
+            prev = Some(runner);
+
+            if interrupt.load(Ordering::Acquire) {
+                break;
+            }
+        }
+
+        let mut last = prev.expect("Schedule has no phases");
+        iterations.append(&mut last.iterations);
+        last.iterations = iterations;
+        last
+    }
+
     fn explore(&mut self) -> Result<(), String> {
+        // With a staged schedule, the first phase builds the e-graph
+        let analysis = self.analysis.take().unwrap_or_default();
+        let (runner, analysis) = if self.phases.is_empty() {
+            (Runner::new(analysis), None)
+        } else {
+            (Runner::default(), Some(analysis))
+        };
         let runner = if self.gen_proof {
             warn!("Proof generation is on (slow)");
-            Runner::default().with_explanations_enabled()
+            runner.with_explanations_enabled()
         } else {
-            Runner::default().with_explanations_disabled()
+            runner.with_explanations_disabled()
         };
 
         // Print a progress bar to get a sense of growth
         let mp = MultiProgress::new();
 
-        let runner = self.rewrite_strat.apply(runner);
+        self.profile = RuleProfile::default();
+        let runner = self.rewrite_strat.apply(runner, self.profile.clone());
 
-        let runner = match self.build_strat {
-            BuildStrat::TimeLimited(t) => runner
-                .with_time_limit(Duration::from_secs(t))
-                .with_node_limit(usize::MAX)
-                .with_iter_limit(usize::MAX),
-            BuildStrat::SizeLimited(n) => runner
-                .with_node_limit(n)
-                .with_time_limit(Duration::from_secs(31536000))
-                .with_iter_limit(usize::MAX),
-            BuildStrat::IterLimited(n) => runner
-                .with_iter_limit(n)
-                .with_time_limit(Duration::from_secs(31536000))
-                .with_node_limit(usize::MAX),
-            BuildStrat::Custom(t, n, i) => runner
-                .with_time_limit(Duration::from_secs(t))
-                .with_node_limit(n)
-                .with_iter_limit(i),
-        };
+        let runner = self.build_strat.apply(runner);
 
         let interrupt = Arc::new(AtomicBool::new(false));
         let interrupt_clone = Arc::clone(&interrupt);
+        let phase_interrupt = Arc::clone(&interrupt);
         let _ = ctrlc::set_handler(move || {
             if interrupt_clone.load(Ordering::Acquire) {
                 std::process::exit(130);
@@ -1008,6 +2015,9 @@ where
             runner.with_hook(move |r| report_progress(r, &interrupt, None, None))
         };
 
+        let runner = self.with_graph_hooks(runner);
+        let runner = runner.with_hook(|r| check_soundness(&r.egraph));
+
         // Make a time bar
         let time_bar = match (self.prog_bar, self.build_strat.clone()) {
             (true, BuildStrat::TimeLimited(t)) | (true, BuildStrat::Custom(t, _, _)) => {
@@ -1054,7 +2064,28 @@ where
             ));
         }
 
-        self.result = Some(runner.with_expr(&self.expr).run(&self.rules));
+        if let Some(depth) = self.dont_cares {
+            let (expr, removed) = simplify_dont_cares(&self.expr, depth)?;
+            info!("Removed {removed} LUT inputs with don't cares");
+            self.expr = expr;
+        }
+
+        self.result = Some(if self.phases.is_empty() {
+            self.with_seeded_expr(runner).run(&self.rules)
+        } else {
+            let mut last = self.run_phases(&phase_interrupt, analysis.unwrap_or_default());
+            if self.rules.is_empty() {
+                last
+            } else {
+                // The rules of the request run last, as one final phase
+                let mut runner = runner.with_egraph(last.egraph);
+                runner.roots = last.roots;
+                let mut runner = runner.run(&self.rules);
+                last.iterations.append(&mut runner.iterations);
+                runner.iterations = last.iterations;
+                runner
+            }
+        });
 
         // Clear the progress bar
         if let Some(t) = time_bar {
@@ -1063,6 +2094,8 @@ where
         }
         mp.clear().unwrap();
 
+        check_soundness(&self.result.as_ref().unwrap().egraph)?;
+
         if self.gen_proof {
             let runner = self.result.as_ref().unwrap();
             let croot = runner.egraph.find(runner.roots[0]);
@@ -1075,12 +2108,8 @@ where
         Ok(())
     }
 
-    /// Extract requested expression with `extractor`
-    pub fn extract_with<R, F>(&mut self, extractor: F) -> Result<SynthOutput<L, R>, String>
-    where
-        R: Report<L>,
-        F: FnOnce(&egg::EGraph<L, A>, egg::Id) -> RecExpr<L>,
-    {
+    /// Explore the e-graph if needed and purge it before extraction
+    fn prepare_extraction(&mut self) -> Result<(), String> {
         if self.result.is_none() {
             self.explore()?;
         }
@@ -1090,6 +2119,26 @@ where
             purge_graph(&mut self.result.as_mut().unwrap().egraph, f.as_ref())?;
         }
 
+        Ok(())
+    }
+
+    /// Extract requested expression with `extractor`
+    pub fn extract_with<R, F>(&mut self, extractor: F) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        F: FnOnce(&egg::EGraph<L, A>, egg::Id) -> RecExpr<L>,
+    {
+        self.try_extract_with(|egraph, root| Ok((extractor(egraph, root), None)))
+    }
+
+    /// Extract requested expression with `extractor`, which may fail or report an optimality gap
+    fn try_extract_with<R, F>(&mut self, extractor: F) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        F: FnOnce(&egg::EGraph<L, A>, egg::Id) -> Result<(RecExpr<L>, Option<f64>), String>,
+    {
+        self.prepare_extraction()?;
+
         let runner = self.result.as_ref().unwrap();
 
         // We need to canonicalize the root ID
@@ -1102,7 +2151,11 @@ where
         // use an Extractor to pick the best element of the root eclass
         info!("Extracting...");
         let extraction_start = Instant::now();
-        let best = extractor(&runner.egraph, root);
+        let (best, gap) = extractor(&runner.egraph, root)?;
+        let best = match &self.fanout_limit {
+            Some(limit) => limit.apply(&best, &mut L::exact_area_cost_fn()),
+            None => best,
+        };
         let extraction_time = extraction_start.elapsed();
         if self.gen_proof {
             info!("Extraction time: {} seconds", extraction_time.as_secs_f64());
@@ -1126,12 +2179,30 @@ where
 
         let rpt = if self.produce_rpt {
             info!("Generating report...");
-            Some(R::new(
-                &self.expr,
-                &best,
-                extraction_time.as_secs_f64(),
-                runner,
-            )?)
+            let stats = self.profile.lock().unwrap().clone();
+            let rpt = R::new(&self.expr, &best, extraction_time.as_secs_f64(), runner)?
+                .with_rule_stats(stats);
+            let rpt = match gap {
+                Some(gap) => rpt.with_optimality_gap(gap),
+                None => rpt,
+            };
+            let rpt = match &self.extract_strat {
+                ExtractStrat::Random(seed, _) => rpt.with_seed(*seed),
+                _ => rpt,
+            };
+            let rpt = match &self.fanout_limit {
+                Some(_) => rpt.with_max_fanout(Comparison::new(
+                    FanoutLimit::max_fanout(&self.expr),
+                    FanoutLimit::max_fanout(&best),
+                )),
+                None => rpt,
+            };
+            Some(match &self.opt_strat {
+                OptStrat::Timing(_, model) => {
+                    rpt.with_slack(Comparison::new(model.slack(&self.expr), model.slack(&best)))
+                }
+                _ => rpt,
+            })
         } else {
             None
         };
@@ -1161,6 +2232,260 @@ where
         })
     }
 
+    /// Extract greedily requested expression with cost model `c`, counting shared nodes once
+    pub fn dag_extract_with<R, C>(&mut self, c: C) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        self.extract_with(|egraph, root| {
+            let mut e = DagExtract::new(c);
+            e.extract(egraph, root).unwrap_or_else(|| {
+                warn!("DAG extraction chose a cycle. Falling back to tree extraction.");
+                Extractor::new(egraph, e.into_cost_fn()).find_best(root).1
+            })
+        })
+    }
+
+    /// Extract requested expression with cost model `c` by refining a greedy extraction for `budget` seconds,
+    /// making the moves seeded with `seed`. The nodes that satisfy `forbid` are never extracted.
+    pub fn local_search_extract_with<R, C>(
+        &mut self,
+        mut c: C,
+        forbid: impl Fn(&L) -> bool,
+        budget: u64,
+        seed: u64,
+    ) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        self.extract_with(|egraph, root| {
+            LocalSearchExtract::new(egraph, &mut c)
+                .forbid(forbid)
+                .with_seed(seed)
+                .extract(root, Duration::from_secs(budget))
+                .unwrap_or_else(|| {
+                    warn!("Local search found no expression to refine. Falling back to tree extraction.");
+                    Extractor::new(egraph, c).find_best(root).1
+                })
+        })
+    }
+
+    /// Extract the best of `samples` random expressions seeded with `seed` under the cost model `c`
+    pub fn random_extract_with<R, C>(
+        &mut self,
+        mut c: C,
+        seed: u64,
+        samples: usize,
+    ) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        C: CostFunction<L>,
+    {
+        self.extract_with(|egraph, root| {
+            let e = RandomExtract::with_seed(seed);
+            let mut best = e.extract(egraph, root);
+            let mut best_cost = c.cost_rec(&best);
+            for _ in 1..samples {
+                let expr = e.extract(egraph, root);
+                let cost = c.cost_rec(&expr);
+                if cost < best_cost {
+                    best = expr;
+                    best_cost = cost;
+                }
+            }
+            best
+        })
+    }
+
+    /// Extract the best of `samples` random expressions seeded with `seed` under the cost model of `opt`
+    fn random_extract<R>(
+        &mut self,
+        opt: OptStrat,
+        seed: u64,
+        samples: usize,
+    ) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+    {
+        match opt {
+            OptStrat::AstSize => self.random_extract_with(egg::AstSize, seed, samples),
+            OptStrat::Area => self.random_extract_with(L::exact_area_cost_fn(), seed, samples),
+            OptStrat::MaxDepth => {
+                self.random_extract_with(NegativeCostFn::new(L::depth_cost_fn()), seed, samples)
+            }
+            OptStrat::MinDepth => self.random_extract_with(L::depth_cost_fn(), seed, samples),
+            OptStrat::CellCount(k) | OptStrat::DepthBound(k, _) => {
+                self.random_extract_with(L::cell_cost_fn(k), seed, samples)
+            }
+            OptStrat::CellCountRegWeighted(k, w) => {
+                self.random_extract_with(L::cell_cost_with_reg_weight_fn(k, w), seed, samples)
+            }
+            OptStrat::Disassemble(set) => {
+                self.random_extract_with(L::filter_cost_fn(set), seed, samples)
+            }
+            OptStrat::Timing(k, model) => {
+                self.random_extract_with(L::timing_cost_fn(k, model), seed, samples)
+            }
+            OptStrat::Power(..) => {
+                Err("Power optimization is incompatible with random extraction".to_string())
+            }
+        }
+    }
+
+    /// Returns the power cost function for cells with at most `k` inputs,
+    /// with the switching activity of the explored e-graph under `model`.
+    fn power_cost_fn(
+        &mut self,
+        k: usize,
+        model: &ActivityModel,
+    ) -> Result<impl CostFunction<L, Cost = f32> + use<L, A>, String> {
+        self.prepare_extraction()?;
+        info!("Estimating switching activity...");
+        let egraph = &self.result.as_ref().unwrap().egraph;
+        Ok(L::power_cost_fn(k, model.simulate(egraph)))
+    }
+
+    /// Extract the expression with the least area under `area_fn` such that its depth is at most `bound`.
+    /// Cells with more than `k` inputs are never extracted.
+    pub fn depth_bound_extract_with<R, C>(
+        &mut self,
+        area_fn: C,
+        k: usize,
+        bound: usize,
+    ) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        self.try_extract_with(|egraph, root| {
+            let e = DepthBoundExtract::new(egraph, area_fn, L::depth_cost_fn())
+                .forbid(|n| n.exceeds_cut_size(k));
+            match e.extract(root, bound) {
+                Some(expr) => Ok((expr, None)),
+                None => match e.min_depth(root) {
+                    Some(d) => Err(format!(
+                        "Depth bound {bound} is infeasible. The minimum achievable depth is {d}"
+                    )),
+                    None => Err(format!(
+                        "No expression with cells of at most {k} inputs can be extracted"
+                    )),
+                },
+            }
+        })
+    }
+
+    /// Extract every expression on the area/depth Pareto front under `area_fn`, from least depth to least area.
+    /// Cells with more than `k` inputs are never extracted.
+    pub fn pareto_extract_with<R, C>(
+        &mut self,
+        area_fn: C,
+        k: usize,
+    ) -> Result<Vec<SynthOutput<L, R>>, String>
+    where
+        R: Report<L>,
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        self.prepare_extraction()?;
+        let runner = self.result.as_ref().unwrap();
+        let root = runner.egraph.find(runner.roots[0]);
+        let front: Vec<ParetoPoint<L>> =
+            DepthBoundExtract::new(&runner.egraph, area_fn, L::depth_cost_fn())
+                .forbid(|n| n.exceeds_cut_size(k))
+                .pareto(root);
+        if front.is_empty() {
+            return Err(format!(
+                "No expression with cells of at most {k} inputs can be extracted"
+            ));
+        }
+
+        front
+            .into_iter()
+            .map(|p| {
+                let expr = p.into_expr();
+                self.extract_with(|_, _| expr)
+            })
+            .collect()
+    }
+
+    /// Extract requested expression by solving an ILP with `solver` under the cost model of `opt`
+    #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
+    fn ilp_extract_with<R, S>(
+        &mut self,
+        opt: OptStrat,
+        solver: S,
+        timeout: u64,
+    ) -> Result<SynthOutput<L, R>, String>
+    where
+        R: Report<L>,
+        S: good_lp::Solver,
+        S::Model: good_lp::WithTimeLimit + good_lp::WithInitialSolution,
+        <S::Model as good_lp::SolverModel>::Solution: BestBound,
+    {
+        let t = timeout as f64;
+        self.try_extract_with(|egraph, root| {
+            let best = match opt {
+                OptStrat::AstSize => {
+                    IlpExtract::new(egraph, egg::AstSize, IlpObjective::Area).solve(root, solver, t)
+                }
+                OptStrat::Area => {
+                    IlpExtract::new(egraph, L::exact_area_cost_fn(), IlpObjective::Area)
+                        .solve(root, solver, t)
+                }
+                OptStrat::CellCount(k) => {
+                    IlpExtract::new(egraph, L::cell_cost_fn(k), IlpObjective::Area)
+                        .forbid(|n| n.exceeds_cut_size(k))
+                        .solve(root, solver, t)
+                }
+                OptStrat::CellCountRegWeighted(k, w) => IlpExtract::new(
+                    egraph,
+                    L::cell_cost_with_reg_weight_fn(k, w),
+                    IlpObjective::Area,
+                )
+                .forbid(|n| n.exceeds_cut_size(k))
+                .solve(root, solver, t),
+                OptStrat::Disassemble(set) => {
+                    IlpExtract::new(egraph, L::filter_cost_fn(set), IlpObjective::Area)
+                        .solve(root, solver, t)
+                }
+                OptStrat::MinDepth => {
+                    IlpExtract::new(egraph, L::depth_cost_fn(), IlpObjective::Depth)
+                        .solve(root, solver, t)
+                }
+                OptStrat::MaxDepth => {
+                    return Err(
+                        "MaxDepth optimization is incompatible with ILP extraction".to_string()
+                    );
+                }
+                OptStrat::Timing(..) => {
+                    return Err(
+                        "Timing optimization is incompatible with ILP extraction".to_string()
+                    );
+                }
+                OptStrat::DepthBound(..) => {
+                    return Err(
+                        "Depth-bounded optimization is incompatible with ILP extraction"
+                            .to_string(),
+                    );
+                }
+                OptStrat::Power(k, model) => IlpExtract::new(
+                    egraph,
+                    L::power_cost_fn(k, model.simulate(egraph)),
+                    IlpObjective::Area,
+                )
+                .forbid(|n| n.exceeds_cut_size(k))
+                .solve(root, solver, t),
+            }?;
+            let gap = best.get_gap();
+            Ok((L::canonicalize_expr(best.into_expr()), gap))
+        })
+    }
+
     /// Serialize the e-graph with an associated cost provided by `c`.
     #[cfg(feature = "graph_dumps")]
     pub fn serialize_with_greedy_cost<C>(&mut self, c: C, w: &mut impl Write) -> std::io::Result<()>
@@ -1192,6 +2517,15 @@ where
         L::get_explanations(root_expr, best, runner)
     }
 
+    /// Synthesize every circuit on the area/depth Pareto front of cells with at most `k` inputs,
+    /// where registers cost `w` cells. The outputs are sorted from least depth to least area.
+    pub fn synth_pareto<R>(&mut self, k: usize, w: u64) -> Result<Vec<SynthOutput<L, R>>, String>
+    where
+        R: Report<L>,
+    {
+        self.pareto_extract_with(L::cell_cost_with_reg_weight_fn(k, w), k)
+    }
+
     /// Synthesize with the extraction strategy set in `self`.
     pub fn synth<R>(&mut self) -> Result<SynthOutput<L, R>, String>
     where
@@ -1205,10 +2539,35 @@ where
             (OptStrat::MinDepth, ExtractStrat::Greedy) => {
                 self.greedy_extract_with(L::depth_cost_fn())
             }
+            (OptStrat::Timing(k, model), ExtractStrat::Greedy) => {
+                self.greedy_extract_with(L::timing_cost_fn(k, model))
+            }
+            (OptStrat::DepthBound(k, d), ExtractStrat::Greedy) => {
+                self.depth_bound_extract_with(L::cell_cost_fn(k), k, d)
+            }
             (OptStrat::MaxDepth, ExtractStrat::Greedy) => {
                 warn!("Maximizing cost on e-graphs with cycles will crash.");
                 self.greedy_extract_with(NegativeCostFn::new(L::depth_cost_fn()))
             }
+            (
+                OptStrat::MinDepth
+                | OptStrat::Timing(..)
+                | OptStrat::DepthBound(..)
+                | OptStrat::MaxDepth,
+                ExtractStrat::Dag,
+            ) => {
+                Err("Depth and timing optimization is incompatible with DAG extraction".to_string())
+            }
+            (
+                OptStrat::MinDepth
+                | OptStrat::Timing(..)
+                | OptStrat::DepthBound(..)
+                | OptStrat::MaxDepth,
+                ExtractStrat::LocalSearch(..),
+            ) => Err(
+                "Depth and timing optimization is incompatible with local search extraction"
+                    .to_string(),
+            ),
             (OptStrat::CellCount(k), ExtractStrat::Greedy) => {
                 self.greedy_extract_with(L::cell_cost_fn(k))
             }
@@ -1218,32 +2577,52 @@ where
             (OptStrat::Disassemble(set), ExtractStrat::Greedy) => {
                 self.greedy_extract_with(L::filter_cost_fn(set))
             }
-            (_, ExtractStrat::Random) => {
-                self.extract_with(|e, r| RandomExtract::new().extract(e, r))
+            (OptStrat::Power(k, model), ExtractStrat::Greedy) => {
+                let c = self.power_cost_fn(k, &model)?;
+                self.greedy_extract_with(c)
+            }
+            (OptStrat::Power(k, model), ExtractStrat::Dag) => {
+                let c = self.power_cost_fn(k, &model)?;
+                self.dag_extract_with(c)
+            }
+            (OptStrat::Power(k, model), ExtractStrat::LocalSearch(t, seed)) => {
+                let c = self.power_cost_fn(k, &model)?;
+                self.local_search_extract_with(c, |n| n.exceeds_cut_size(k), t, seed)
+            }
+            (OptStrat::AstSize, ExtractStrat::Dag) => self.dag_extract_with(egg::AstSize),
+            (OptStrat::Area, ExtractStrat::Dag) => self.dag_extract_with(L::exact_area_cost_fn()),
+            (OptStrat::CellCount(k), ExtractStrat::Dag) => {
+                self.dag_extract_with(L::cell_cost_fn(k))
             }
+            (OptStrat::CellCountRegWeighted(k, w), ExtractStrat::Dag) => {
+                self.dag_extract_with(L::cell_cost_with_reg_weight_fn(k, w))
+            }
+            (OptStrat::Disassemble(set), ExtractStrat::Dag) => {
+                self.dag_extract_with(L::filter_cost_fn(set))
+            }
+            (OptStrat::AstSize, ExtractStrat::LocalSearch(t, seed)) => {
+                self.local_search_extract_with(egg::AstSize, |_| false, t, seed)
+            }
+            (OptStrat::Area, ExtractStrat::LocalSearch(t, seed)) => {
+                self.local_search_extract_with(L::exact_area_cost_fn(), |_| false, t, seed)
+            }
+            (OptStrat::CellCount(k), ExtractStrat::LocalSearch(t, seed)) => self
+                .local_search_extract_with(L::cell_cost_fn(k), |n| n.exceeds_cut_size(k), t, seed),
+            (OptStrat::CellCountRegWeighted(k, w), ExtractStrat::LocalSearch(t, seed)) => self
+                .local_search_extract_with(
+                    L::cell_cost_with_reg_weight_fn(k, w),
+                    |n| n.exceeds_cut_size(k),
+                    t,
+                    seed,
+                ),
+            (OptStrat::Disassemble(set), ExtractStrat::LocalSearch(t, seed)) => {
+                self.local_search_extract_with(L::filter_cost_fn(set), |_| false, t, seed)
+            }
+            (opt, ExtractStrat::Random(seed, n)) => self.random_extract(opt, seed, n),
             #[cfg(feature = "exact_cbc")]
-            (
-                OptStrat::CellCount(6) | OptStrat::CellCountRegWeighted(6, 1) | OptStrat::AstSize,
-                ExtractStrat::Cbc(t),
-            ) => self.extract_with(|egraph, root| {
-                info!("ILP ON");
-                let mut e = egg::LpExtractor::new(egraph, egg::AstSize);
-                L::canonicalize_expr(e.solve_with_timeout(root, good_lp::coin_cbc, t as f64))
-            }),
+            (opt, ExtractStrat::Cbc(t)) => self.ilp_extract_with(opt, good_lp::coin_cbc, t),
             #[cfg(feature = "exact_highs")]
-            (
-                OptStrat::CellCount(6) | OptStrat::CellCountRegWeighted(6, 1) | OptStrat::AstSize,
-                ExtractStrat::Highs(t),
-            ) => self.extract_with(|egraph, root| {
-                info!("ILP ON");
-                let mut e = egg::LpExtractor::new(egraph, egg::AstSize);
-                L::canonicalize_expr(e.solve_with_timeout(root, good_lp::highs, t as f64))
-            }),
-            #[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
-            _ => Err(format!(
-                "{:?} optimization strategy is incomptabile with {:?} extraction.",
-                self.opt_strat, self.extract_strat
-            )),
+            (opt, ExtractStrat::Highs(t)) => self.ilp_extract_with(opt, good_lp::highs, t),
         }
     }
 }
@@ -1280,8 +2659,8 @@ pub fn process_expression<L, A, R>(
     no_verify: bool,
 ) -> std::io::Result<SynthOutput<L, R>>
 where
-    L: CircuitLang,
-    A: Analysis<L> + Clone + Default,
+    L: CircuitLang + 'static,
+    A: Analysis<L> + SoundnessCheck + Clone + Default + 'static,
     R: Report<L>,
 {
     if !no_verify {
@@ -1319,14 +2698,26 @@ where
         info!("{} ... => ", &expr[0..len]);
     }
 
-    let simplified = result.get_expr();
+    verify_output(&expr, &result, no_verify)?;
+    Ok(result)
+}
 
+/// Check that the expression of `result` is functionally equivalent to `expr`, unless `no_verify` is set
+fn verify_output<L, R>(
+    expr: &RecExpr<L>,
+    result: &SynthOutput<L, R>,
+    no_verify: bool,
+) -> std::io::Result<()>
+where
+    L: CircuitLang,
+    R: Report<L>,
+{
     // Verify functionality
     if no_verify {
         info!("Skipping functionality tests...");
     } else {
         info!("Checking expression...");
-        let check = L::check_expr(&expr, simplified);
+        let check = L::check_expr(expr, result.get_expr());
         if check.is_inconclusive() {
             warn!("Functionality verification inconclusive");
         }
@@ -1345,7 +2736,35 @@ where
             return Err(std::io::Error::other("Functionality verification failed"));
         }
     }
-    Ok(result)
+    Ok(())
+}
+
+/// Compile a [CircuitLang] expression into every circuit on the area/depth Pareto front of cells with at most `k` inputs,
+/// where registers cost `w` cells.
+/// The outputs are sorted from least depth to least area. Everything else goes to stderr.
+pub fn process_pareto_front<L, A, R>(
+    expr: RecExpr<L>,
+    req: SynthRequest<L, A>,
+    k: usize,
+    w: u64,
+    no_verify: bool,
+) -> std::io::Result<Vec<SynthOutput<L, R>>>
+where
+    L: CircuitLang + 'static,
+    A: Analysis<L> + SoundnessCheck + Clone + Default + 'static,
+    R: Report<L>,
+{
+    if !no_verify {
+        L::verify_expr(&expr).map_err(std::io::Error::other)?;
+    }
+
+    let mut req = req.with_expr(expr.clone());
+    let front = req.synth_pareto::<R>(k, w).map_err(std::io::Error::other)?;
+
+    for result in &front {
+        verify_output(&expr, result, no_verify)?;
+    }
+    Ok(front)
 }
 
 /// Compile a [CircuitLang] expression from a line of text using a baseline request `req`.
@@ -1356,9 +2775,9 @@ pub fn process_string_expression<L, A, R>(
     no_verify: bool,
 ) -> std::io::Result<SynthOutput<L, R>>
 where
-    L: CircuitLang,
+    L: CircuitLang + 'static,
     <L as egg::FromOp>::Error: serde::ser::StdError + Sync + Send + 'static,
-    A: Analysis<L> + Clone + Default,
+    A: Analysis<L> + SoundnessCheck + Clone + Default + 'static,
     R: Report<L>,
 {
     let line = line.trim();
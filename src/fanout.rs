@@ -0,0 +1,120 @@
+/*!
+
+  Fanout limits for extracted circuits. The loads of a node are counted in the extracted DAG,
+  so logic shared by several parents drives all of them. Logic that drives too many loads is
+  duplicated, and the loads are split round-robin between the copies.
+
+*/
+use super::cost::DagCost;
+use super::driver::Extractable;
+use egg::{CostFunction, Id, Language, RecExpr};
+
+/// Limits the fanout of the logic in an extracted circuit by duplicating it.
+/// By default, the limit is enforced. With a penalty, logic is only duplicated when the copies are cheaper than the violation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FanoutLimit {
+    max_fanout: usize,
+    penalty: Option<f64>,
+}
+
+impl FanoutLimit {
+    /// Returns a limit that enforces that logic drives at most `max_fanout` loads.
+    pub fn new(max_fanout: usize) -> Self {
+        if max_fanout < 1 {
+            panic!("max_fanout must be at least 1");
+        }
+        Self {
+            max_fanout,
+            penalty: None,
+        }
+    }
+
+    /// Instead of enforcing the limit, charge `penalty` for every load over the limit.
+    pub fn with_penalty(self, penalty: f64) -> Self {
+        Self {
+            penalty: Some(penalty),
+            ..self
+        }
+    }
+
+    /// Returns the max number of loads per node.
+    pub fn get_max_fanout(&self) -> usize {
+        self.max_fanout
+    }
+
+    /// Returns the number of loads driven by every node in `expr`.
+    pub fn fanout<L: Language>(expr: &RecExpr<L>) -> Vec<usize> {
+        let mut loads = vec![0; expr.len()];
+        for c in expr.as_ref().iter().flat_map(|n| n.children()) {
+            loads[usize::from(*c)] += 1;
+        }
+        loads
+    }
+
+    /// Returns the most loads driven by any logic in `expr`.
+    pub fn max_fanout<L: Extractable>(expr: &RecExpr<L>) -> usize {
+        Self::fanout(expr)
+            .into_iter()
+            .zip(expr.as_ref())
+            .filter(|(_, n)| n.is_logic())
+            .map(|(f, _)| f)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Returns the number of copies of a node of cost `cost` with `loads` loads.
+    fn copies(&self, loads: usize, cost: Option<f64>) -> usize {
+        let needed = loads.div_ceil(self.max_fanout).max(1);
+        let (penalty, cost) = match (self.penalty, cost) {
+            (None, _) => return needed,
+            (Some(p), Some(c)) => (p, c),
+            (Some(_), None) => return 1,
+        };
+        let total = |n: usize| {
+            (n - 1) as f64 * cost + penalty * loads.saturating_sub(n * self.max_fanout) as f64
+        };
+        (1..=needed)
+            .min_by(|a, b| total(*a).total_cmp(&total(*b)))
+            .unwrap()
+    }
+
+    /// Duplicates the logic in `expr` that drives too many loads.
+    /// The cost of a copy is the cost of the node under `cost_fn`.
+    pub fn apply<L, C>(&self, expr: &RecExpr<L>, cost_fn: &mut C) -> RecExpr<L>
+    where
+        L: Extractable,
+        C: CostFunction<L, Cost: DagCost>,
+    {
+        let nodes = expr.as_ref();
+
+        // Parents come after their children, so the copies of every parent are known top-down
+        let mut loads = vec![0; nodes.len()];
+        let mut copies = vec![1; nodes.len()];
+        for (i, node) in nodes.iter().enumerate().rev() {
+            if node.is_logic() && loads[i] > self.max_fanout {
+                let cost = cost_fn.cost(node, |_| C::Cost::default()).finite();
+                copies[i] = self.copies(loads[i], cost);
+            }
+            for c in node.children() {
+                loads[usize::from(*c)] += copies[i];
+            }
+        }
+
+        let mut ids: Vec<Vec<Id>> = Vec::with_capacity(nodes.len());
+        let mut next = vec![0; nodes.len()];
+        let mut out = RecExpr::default();
+        for (i, node) in nodes.iter().enumerate() {
+            let mut copy_ids = Vec::with_capacity(copies[i]);
+            for _ in 0..copies[i] {
+                let copy = node.clone().map_children(|c| {
+                    let c = usize::from(c);
+                    next[c] += 1;
+                    ids[c][(next[c] - 1) % ids[c].len()]
+                });
+                copy_ids.push(out.add(copy));
+            }
+            ids.push(copy_ids);
+        }
+        out
+    }
+}
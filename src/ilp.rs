@@ -0,0 +1,436 @@
+/*!
+
+  Exact extraction with integer linear programming. Every e-node is a binary variable,
+  and the objective sums the costs of the chosen e-nodes, so shared logic is counted once.
+  Nodes with a saturated cost are never chosen, and a level per e-class keeps the choices acyclic.
+  The solver is warm started from a greedy extraction, which is returned if the solver can't beat it.
+
+*/
+use super::cost::{DagCost, NodeCosts};
+use egg::{CostFunction, EGraph, Id, Language, RecExpr};
+use good_lp::{
+    Expression, Solution, SolutionStatus, Solver, SolverModel, Variable, WithInitialSolution,
+    WithTimeLimit, variable, variables,
+};
+use log::{info, warn};
+use std::collections::HashMap;
+
+/// What an [IlpExtract] minimizes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IlpObjective {
+    /// The sum of the costs of the chosen e-nodes
+    Area,
+    /// The largest sum of costs along a path from the root, breaking ties by area
+    Depth,
+}
+
+/// A solution that may know a bound on the optimal objective
+pub trait BestBound {
+    /// Returns the best lower bound proven by the solver, if it exposes one
+    fn best_bound(&self) -> Option<f64>;
+}
+
+#[cfg(feature = "exact_cbc")]
+impl BestBound for good_lp::solvers::coin_cbc::CoinCbcSolution {
+    fn best_bound(&self) -> Option<f64> {
+        Some(self.model().best_possible_value())
+    }
+}
+
+/// good_lp drops the solved HiGHS model, so its `mip_dual_bound` can't be read.
+/// HiGHS only reports a gap when its optimal solution is extracted.
+#[cfg(feature = "exact_highs")]
+impl BestBound for good_lp::solvers::highs::HighsSolution {
+    fn best_bound(&self) -> Option<f64> {
+        None
+    }
+}
+
+/// The result of an [IlpExtract]
+pub struct IlpSolution<L: Language> {
+    expr: RecExpr<L>,
+    gap: Option<f64>,
+}
+
+impl<L: Language> IlpSolution<L> {
+    /// Get the extracted expression
+    pub fn get_expr(&self) -> &RecExpr<L> {
+        &self.expr
+    }
+
+    /// Get the relative optimality gap of the expression, if it is known
+    pub fn get_gap(&self) -> Option<f64> {
+        self.gap
+    }
+
+    /// Consume the solution, returning the extracted expression
+    pub fn into_expr(self) -> RecExpr<L> {
+        self.expr
+    }
+}
+
+/// The variables of an e-class
+struct ClassVars {
+    /// True if the e-class is extracted
+    active: Variable,
+    /// True if the e-node is chosen, or [None] if it can't be
+    nodes: Vec<Option<Variable>>,
+    /// The topological level of the e-class
+    level: Variable,
+    /// The depth of the e-class, if minimizing depth
+    depth: Option<Variable>,
+}
+
+/// An ILP extractor over the node costs of a [CostFunction]
+pub struct IlpExtract<'a, L: Language, A: egg::Analysis<L>> {
+    egraph: &'a EGraph<L, A>,
+    costs: NodeCosts<f64>,
+    objective: IlpObjective,
+}
+
+impl<'a, L, A> IlpExtract<'a, L, A>
+where
+    L: Language,
+    A: egg::Analysis<L>,
+{
+    /// Create an ILP extractor using the node costs of `cost_fn`. The costs of children are ignored.
+    pub fn new<C>(egraph: &'a EGraph<L, A>, mut cost_fn: C, objective: IlpObjective) -> Self
+    where
+        C: CostFunction<L>,
+        C::Cost: DagCost,
+    {
+        Self {
+            egraph,
+            costs: NodeCosts::from_cost_fn(egraph, &mut cost_fn),
+            objective,
+        }
+    }
+
+    /// Never extract the e-nodes that satisfy `f`, like LUTs that are too wide
+    pub fn forbid(mut self, f: impl Fn(&L) -> bool) -> Self {
+        self.costs.forbid(self.egraph, f);
+        self
+    }
+
+    /// Build the variables and the objective of the problem
+    fn variables(
+        &self,
+    ) -> (
+        good_lp::ProblemVariables,
+        HashMap<Id, ClassVars>,
+        Expression,
+    ) {
+        let mut builder = variables!();
+        let n = self.egraph.number_of_classes() as f64;
+        let vars: HashMap<Id, ClassVars> = self
+            .egraph
+            .classes()
+            .map(|class| {
+                let nodes = self.costs[class.id]
+                    .iter()
+                    .map(|c| c.map(|_| builder.add(variable().binary())))
+                    .collect();
+                let vars = ClassVars {
+                    active: builder.add(variable().binary()),
+                    nodes,
+                    level: builder.add(variable().min(0).max(n)),
+                    depth: (self.objective == IlpObjective::Depth)
+                        .then(|| builder.add(variable().min(0))),
+                };
+                (class.id, vars)
+            })
+            .collect();
+
+        let mut area: Expression = 0.into();
+        for (id, class) in &vars {
+            for (v, c) in class.nodes.iter().zip(&self.costs[*id]) {
+                if let (Some(v), Some(c)) = (v, c) {
+                    area += *c * *v;
+                }
+            }
+        }
+        (builder, vars, area)
+    }
+
+    /// The largest depth a path through the e-graph can have
+    fn max_depth(&self) -> f64 {
+        self.egraph
+            .classes()
+            .map(|class| {
+                self.costs[class.id]
+                    .iter()
+                    .flatten()
+                    .fold(0.0, |m: f64, c| m.max(*c))
+            })
+            .sum::<f64>()
+            + 1.0
+    }
+
+    /// Add the constraints that the choices form a DAG that implements `root`
+    fn add_constraints<M: SolverModel>(
+        &self,
+        model: &mut M,
+        vars: &HashMap<Id, ClassVars>,
+        root: Id,
+    ) {
+        let n = self.egraph.number_of_classes() as f64;
+        let max_depth = self.max_depth();
+        for (id, class) in vars {
+            // An active e-class chooses exactly one e-node
+            let chosen = class
+                .nodes
+                .iter()
+                .flatten()
+                .fold(Expression::from(0), |acc, v| acc + *v);
+            model.add_constraint((chosen - class.active).eq(0));
+
+            for (i, node) in self.egraph[*id].nodes.iter().enumerate() {
+                let Some(x) = class.nodes[i] else {
+                    continue;
+                };
+                let cost = self.costs[*id][i].unwrap();
+                if let Some(d) = class.depth {
+                    model.add_constraint((d - cost * x).geq(0));
+                }
+                for c in node.children() {
+                    let child = &vars[&self.egraph.find(*c)];
+                    model.add_constraint((x - child.active).leq(0));
+
+                    // A chosen e-node sits above its children, which rules out cycles
+                    model.add_constraint((class.level - child.level - (n + 1.0) * x).geq(-n));
+                    if let (Some(d), Some(cd)) = (class.depth, child.depth) {
+                        model.add_constraint((d - cd - (max_depth + cost) * x).geq(-max_depth));
+                    }
+                }
+            }
+        }
+        model.add_constraint(Expression::from(vars[&root].active).eq(1));
+    }
+
+    /// Choose the cheapest e-node of every e-class as a tree, like a greedy extractor
+    fn greedy(&self) -> HashMap<Id, usize> {
+        let best = match self.objective {
+            IlpObjective::Area => self.costs.greedy(self.egraph, |c| *c, |x, y| x + y),
+            IlpObjective::Depth => self.costs.greedy(self.egraph, |c| *c, f64::max),
+        };
+        best.into_iter().map(|(id, (_, i))| (id, i)).collect()
+    }
+
+    /// Visit the e-classes below `id` under `choices` in post-order, failing on a cycle or a missing choice
+    fn visit(
+        &self,
+        choices: &HashMap<Id, usize>,
+        id: Id,
+        order: &mut Vec<Id>,
+        done: &mut HashMap<Id, bool>,
+    ) -> Option<()> {
+        match done.get(&id) {
+            Some(true) => return Some(()),
+            Some(false) => return None,
+            None => (),
+        }
+        done.insert(id, false);
+        let node = &self.egraph[id].nodes[*choices.get(&id)?];
+        for c in node.children() {
+            self.visit(choices, self.egraph.find(*c), order, done)?;
+        }
+        done.insert(id, true);
+        order.push(id);
+        Some(())
+    }
+
+    /// Returns the e-classes reachable from `root` under `choices` with children first, or [None] if they are not a DAG
+    fn topo_order(&self, choices: &HashMap<Id, usize>, root: Id) -> Option<Vec<Id>> {
+        let mut order = Vec::new();
+        self.visit(choices, root, &mut order, &mut HashMap::new())?;
+        Some(order)
+    }
+
+    /// The weight of area in the objective when minimizing depth
+    fn tie_weight(&self) -> f64 {
+        // Area breaks ties without outweighing a single level of depth
+        1.0 / (self.max_depth() * self.max_depth())
+    }
+
+    /// Returns the level and depth of every e-class in `order` under `choices`
+    fn levels(&self, choices: &HashMap<Id, usize>, order: &[Id]) -> HashMap<Id, (f64, f64)> {
+        let mut levels: HashMap<Id, (f64, f64)> = HashMap::new();
+        for id in order {
+            let i = choices[id];
+            let (level, depth) = self.egraph[*id].nodes[i]
+                .children()
+                .iter()
+                .map(|c| levels[&self.egraph.find(*c)])
+                .fold((-1.0, 0.0), |(l, d), (cl, cd)| (cl.max(l), cd.max(d)));
+            levels.insert(*id, (level + 1.0, depth + self.costs[*id][i].unwrap()));
+        }
+        levels
+    }
+
+    /// The objective value of the extraction given by `choices` and `order`
+    fn objective_value(&self, choices: &HashMap<Id, usize>, order: &[Id]) -> f64 {
+        let area: f64 = order
+            .iter()
+            .map(|id| self.costs[*id][choices[id]].unwrap())
+            .sum();
+        match self.objective {
+            IlpObjective::Area => area,
+            IlpObjective::Depth => {
+                let root = order.last().unwrap();
+                self.levels(choices, order)[root].1 + area * self.tie_weight()
+            }
+        }
+    }
+
+    /// Assign every variable of the problem from the extraction given by `choices` and `order`
+    fn initial_solution(
+        &self,
+        vars: &HashMap<Id, ClassVars>,
+        choices: &HashMap<Id, usize>,
+        order: &[Id],
+    ) -> Vec<(Variable, f64)> {
+        let levels = self.levels(choices, order);
+        let mut values = Vec::new();
+        for (id, class) in vars {
+            let chosen = levels.get(id).map(|_| choices[id]);
+            let (level, depth) = levels.get(id).copied().unwrap_or_default();
+            values.push((class.active, chosen.is_some() as u8 as f64));
+            for (i, v) in class.nodes.iter().enumerate() {
+                if let Some(v) = v {
+                    values.push((*v, (chosen == Some(i)) as u8 as f64));
+                }
+            }
+            values.push((class.level, level));
+            if let Some(d) = class.depth {
+                values.push((d, depth));
+            }
+        }
+        values
+    }
+
+    /// Build the expression given by `choices` and `order`
+    fn build(&self, choices: &HashMap<Id, usize>, order: &[Id]) -> RecExpr<L> {
+        let mut expr = RecExpr::default();
+        let mut ids: HashMap<Id, Id> = HashMap::new();
+        for id in order {
+            let node = self.egraph[*id].nodes[choices[id]]
+                .clone()
+                .map_children(|c| ids[&self.egraph.find(c)]);
+            ids.insert(*id, expr.add(node));
+        }
+        expr
+    }
+
+    /// Solve for the best expression rooted at `root` using `solver`, giving up after `timeout` seconds.
+    /// The solver starts from the greedy solution, and the better of the two is returned.
+    pub fn solve<S>(&self, root: Id, solver: S, timeout: f64) -> Result<IlpSolution<L>, String>
+    where
+        S: Solver,
+        S::Model: WithTimeLimit + WithInitialSolution,
+        <S::Model as SolverModel>::Solution: BestBound,
+    {
+        let root = self.egraph.find(root);
+        let greedy = self.greedy();
+        let incumbent = match self.topo_order(&greedy, root) {
+            Some(order) => {
+                let value = self.objective_value(&greedy, &order);
+                Some((greedy, order, value))
+            }
+            None => None,
+        };
+
+        let (builder, vars, area) = self.variables();
+        let objective = match vars[&root].depth {
+            Some(d) => Expression::from(d) + area * self.tie_weight(),
+            None => area,
+        };
+        let mut model = builder
+            .minimise(objective)
+            .using(solver)
+            .with_time_limit(timeout);
+        if let Some((choices, order, value)) = &incumbent {
+            info!("Warm starting ILP with greedy solution of cost {value}");
+            model = model.with_initial_solution(self.initial_solution(&vars, choices, order));
+        }
+        self.add_constraints(&mut model, &vars, root);
+
+        info!(
+            "Solving ILP with {} e-classes using {}",
+            vars.len(),
+            S::name()
+        );
+        let (found, optimal, bound) = match model.solve() {
+            Ok(solution) => {
+                match solution.status() {
+                    SolutionStatus::Optimal => info!("ILP solution is optimal"),
+                    SolutionStatus::TimeLimit => {
+                        warn!("ILP solver timed out, solution may not be optimal")
+                    }
+                    SolutionStatus::GapLimit => info!("ILP solver reached its gap limit"),
+                }
+                let choices: HashMap<Id, usize> = vars
+                    .iter()
+                    .filter_map(|(id, class)| {
+                        let i = class
+                            .nodes
+                            .iter()
+                            .position(|v| v.is_some_and(|v| solution.value(v) > 0.5))?;
+                        Some((*id, i))
+                    })
+                    .collect();
+                let found = match self.topo_order(&choices, root) {
+                    Some(order) => {
+                        let value = self.objective_value(&choices, &order);
+                        Some((choices, order, value))
+                    }
+                    None => {
+                        warn!("ILP solution is not a valid extraction");
+                        None
+                    }
+                };
+                (
+                    found,
+                    matches!(solution.status(), SolutionStatus::Optimal),
+                    solution.best_bound(),
+                )
+            }
+            Err(e) => {
+                warn!("ILP solver failed: {e}");
+                (None, false, None)
+            }
+        };
+
+        // A greedy solution only beats a valid ILP solution when it is at least as good, so it inherits its optimality
+        let from_ilp = found.is_some();
+        let (choices, order, value) = match (found, incumbent) {
+            (Some(f), Some(g)) if g.2 < f.2 => {
+                info!("Greedy solution ({}) beats ILP solution ({})", g.2, f.2);
+                g
+            }
+            (Some(f), _) => f,
+            (None, Some(g)) => {
+                warn!("Falling back to greedy solution");
+                g
+            }
+            (None, None) => return Err("No extraction satisfies the ILP constraints".to_string()),
+        };
+
+        let gap = if optimal && from_ilp {
+            Some(0.0)
+        } else {
+            bound.map(|b| ((value - b) / value.abs().max(f64::EPSILON)).max(0.0))
+        };
+        match gap {
+            Some(gap) => info!("ILP optimality gap is {:.2}%", gap * 100.0),
+            None => warn!(
+                "The optimality gap is unknown because {} did not report a best bound",
+                S::name()
+            ),
+        }
+
+        Ok(IlpSolution {
+            expr: self.build(&choices, &order),
+            gap,
+        })
+    }
+}
@@ -21,24 +21,46 @@ Arguments:
   [OUTPUT]  Verilog file to output to (or use stdout)
 
 Options:
-      --report <REPORT>            If provided, output a JSON file with result data
-  -a, --assert-sat                 Return an error if the graph does not reach saturation
-  -f, --no-verify                  Do not verify the functionality of the output
-  -c, --no-canonicalize            Do not canonicalize the input into LUTs
-  -d, --decomp                     Find new decompositions at runtime
-      --disassemble <DISASSEMBLE>  Comma separated list of cell types to decompose into
-      --partition <PARTITION>      Netlist partitioning method for re-synthesis
+      --report <REPORT>                        If provided, output a JSON file with result data
+      --rules <RULES>                          If provided, use rules compiled from file instead of built-in rules
+  -a, --assert-sat                             Return an error if the graph does not reach saturation
+  -f, --no-verify                              Do not verify the functionality of the output
+  -c, --no-canonicalize                        Do not canonicalize the input into LUTs
+  -d, --decomp                                 Find new decompositions at runtime
+      --disassemble <DISASSEMBLE>              Comma separated list of cell types to decompose into
+      --collapse-cuts                          Collapse every e-class with at most k inputs into a single LUT
+      --partition <PARTITION>                  Netlist partitioning method for re-synthesis
                            [default: arc-set] [possible values: r2r, arc-set, delay-paths]
-  -v, --verbose                    Print explanations (generates a proof and runs slower)
-      --min-depth                  Extract for minimum circuit depth
-      --random                     Extract randomly
-  -k, --k <K>                      Max fan in size allowed for extracted LUTs [default: 6]
-  -w, --reg-weight <REG_WEIGHT>    Ratio of register cost to LUT cost [default: 1]
-  -t, --timeout <TIMEOUT>          Build/extraction timeout in seconds
-  -s, --node-limit <NODE_LIMIT>    Maximum number of nodes in graph
-  -n, --iter-limit <ITER_LIMIT>    Maximum number of rewrite iterations
-  -h, --help                       Print help
-  -V, --version                    Print version
+      --enable <ENABLE>                        Comma separated list of rule categories (or rules) to enable
+      --disable <DISABLE>                      Comma separated list of rule categories (or rules) to disable
+  -v, --verbose                                Print explanations (generates a proof and runs slower)
+      --min-depth                              Extract for minimum circuit depth
+      --random                                 Extract randomly
+      --seed <SEED>                            Seed the random extraction or local search to make it reproducible
+      --samples <SAMPLES>                      Keep the best of this many random extractions by the cost model [default: 1]
+      --dag                                    Extract a DAG, counting LUTs shared by several fanouts once
+      --local-search <LOCAL_SEARCH>            Refine the extraction by simulated annealing for this many seconds, counting shared LUTs once
+      --timing <TIMING>                        Extract for the worst slack under the arrival, required, and delay times in this file
+      --depth-bound <DEPTH_BOUND>              Extract the fewest LUTs such that the circuit depth is at most this bound
+      --power                                  Extract for the least dynamic power, weighing every LUT by the toggle rate of its output
+      --input-probability <INPUT_PROBABILITY>  Probability that each input is one when estimating the switching activity [default: 0.5]
+      --max-fanout <MAX_FANOUT>                Duplicate logic so that every LUT or gate drives at most this many loads
+      --fanout-penalty <FANOUT_PENALTY>        Only duplicate logic when it is cheaper than this penalty for every load over the max fanout
+      --pareto                                 Extract every circuit on the LUT count/depth Pareto front and report all of them
+      --pareto-point <PARETO_POINT>            Emit this point of the Pareto front, counting from the least depth (defaults to the fewest LUTs)
+      --pareto-dir <PARETO_DIR>                Write every point of the Pareto front to a separate Verilog file in this directory
+      --prune                                  Prune LUTs wider than k from the e-graph during exploration
+      --prune-cost <PRUNE_COST>                Prune nodes costing more than this many times the cheapest node of their e-class during exploration
+      --sweep[=<SWEEP>]                        Merge functionally equivalent e-classes with at most this many inputs during exploration
+      --cut-seeds <CUT_SEEDS>                  Seed the e-graph with LUTs covering this many of the best k-feasible cuts of every node
+      --dont-cares <DONT_CARES>                Simplify LUTs with the don't cares of a window this many levels deep before exploration
+  -k, --k <K>                                  Max fan in size allowed for extracted LUTs [default: 6]
+  -w, --reg-weight <REG_WEIGHT>                Ratio of register cost to LUT cost [default: 1]
+  -t, --timeout <TIMEOUT>                      Build/extraction timeout in seconds
+  -s, --node-limit <NODE_LIMIT>                Maximum number of nodes in graph
+  -n, --iter-limit <ITER_LIMIT>                Maximum number of rewrite iterations
+  -h, --help                                   Print help
+  -V, --version                                Print version
 ```
 
 */
@@ -47,14 +69,22 @@ pub mod analysis;
 pub mod asic;
 pub mod check;
 pub mod cost;
+pub mod cut;
+pub mod dontcare;
 pub mod driver;
+pub mod fanout;
+#[cfg(any(feature = "exact_cbc", feature = "exact_highs"))]
+pub mod ilp;
 pub mod logic;
 pub mod lut;
 pub mod netlist;
 pub mod pass;
+pub mod power;
 pub mod rewrite;
+pub mod rulegen;
 #[cfg(feature = "graph_dumps")]
 pub mod serialize;
+pub mod timing;
 pub mod verilog;
 
 #[cfg(test)]
@@ -63,7 +93,7 @@ mod tests {
 
     use analysis::LutAnalysis;
     use asic::{CellAnalysis, CellLang, CellRpt, asic_rewrites};
-    use driver::{Canonical, SynthRequest};
+    use driver::{Canonical, Extractable, Phase, SynthRequest};
     use egg::{Analysis, Language, RecExpr};
     use lut::{LutExprInfo, LutLang};
     use verilog::{PrimitiveType, SVModule, sv_parse_wrapper};
@@ -1137,5 +1167,643 @@ endmodule\n"
 
         assert!(result.contains("area"));
         assert!(result.contains("\"area\": 1.064"));
+        assert!(result.contains("\"rule_stats\""));
+    }
+
+    #[test]
+    fn test_rule_synthesis() {
+        use rulegen::{CellSpec, RuleSynthesizer};
+
+        let library = "# AOI21: !(A | (B1 & B2))\nAOI21_X1 3 0x07\nXOR2_X1 2 6\nNAND2_X1 2 0x7\n";
+        let cells = CellSpec::parse_library(library.as_bytes()).unwrap();
+        assert_eq!(cells.len(), 3);
+        assert!(CellSpec::parse_library("AND2_X1 2 0x18".as_bytes()).is_err());
+        assert!(CellSpec::parse_library("AND 2 0x8".as_bytes()).is_err());
+
+        let (file, unmatched) = RuleSynthesizer::new(cells).synthesize().unwrap();
+        assert!(unmatched.is_empty());
+        assert!(file.contains("aoi21_x1: (INV (OR ?a (AND ?b ?c))) <=> (AOI21_X1 ?a ?b ?c)"));
+        assert!(file.contains("(AND ?a ?b) <=> (AND ?b ?a)"));
+
+        // XOR can't be built from two operators
+        let cells = CellSpec::parse_library("XOR2_X1 2 6".as_bytes()).unwrap();
+        let (_, unmatched) = RuleSynthesizer::new(cells)
+            .with_cell_size(2)
+            .synthesize()
+            .unwrap();
+        assert_eq!(unmatched, ["XOR2_X1"]);
+
+        // Cells whose names differ only in case get distinct rules
+        let cells = CellSpec::parse_library("NAND2_X1 2 0x7\nnand2_x1 2 0x7".as_bytes()).unwrap();
+        let (cased, _) = RuleSynthesizer::new(cells).synthesize().unwrap();
+        assert!(cased.contains("nand2_x1: (INV (AND ?a ?b)) <=> (NAND2_X1 ?a ?b)"));
+        assert!(cased.contains("nand2_x1-2: (INV (AND ?a ?b)) <=> (nand2_x1 ?a ?b)"));
+
+        let mut manager = rewrite::RewriteManager::<CellLang, CellAnalysis>::new();
+        manager.parse_rules(file.as_bytes()).unwrap();
+        manager.enable_category("cells").unwrap();
+        let expr: RecExpr<CellLang> = "(INV (OR a (AND b c)))".parse().unwrap();
+        let mut req: SynthRequest<CellLang, CellAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_rules(manager.active_rules())
+            .with_k(4)
+            .without_progress_bar();
+        let result = req.synth::<CellRpt>().unwrap();
+        assert_eq!(result.get_expr().to_string(), "(AOI21_X1 a b c)");
+    }
+
+    #[test]
+    fn test_dag_extract() {
+        let mut egraph: egg::EGraph<egg::SymbolLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(p (s a b) (t c d))".parse().unwrap());
+        let shared = egraph.add_expr(&"(q (u a b c) (u a b c))".parse().unwrap());
+        egraph.union(root, shared);
+        egraph.rebuild();
+
+        // A tree counts (u a b c) twice
+        let tree = egg::Extractor::new(&egraph, egg::AstSize).find_best(root).1;
+        assert_eq!(tree.to_string(), "(p (s a b) (t c d))");
+        let dag = cost::DagExtract::new(egg::AstSize)
+            .extract(&egraph, root)
+            .unwrap();
+        assert_eq!(dag.to_string(), "(q (u a b c) (u a b c))");
+        assert_eq!(dag.len(), 5);
+
+        // DAG extraction only counts cells, so it can't optimize depth
+        let expr: RecExpr<LutLang> = "(LUT 8 a b)".parse().unwrap();
+        let req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_min_depth()
+            .without_progress_bar();
+        assert!(req.clone().synth::<driver::SynthReport>().is_ok());
+        assert!(
+            req.clone()
+                .with_dag_extraction()
+                .synth::<driver::SynthReport>()
+                .is_err()
+        );
+        assert!(
+            req.with_local_search(1, None)
+                .synth::<driver::SynthReport>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_timing() {
+        let constraints = "# Input a arrives late\narrival a 5\nrequired * 8\nrequired y 6\n\
+                           delay AND 2\ndelay LUT2 I1 2\ndelay LUT2 I0 1\n";
+        let model = timing::TimingModel::parse(constraints.as_bytes()).unwrap();
+        assert!(timing::TimingModel::parse("delay LUT2 I2 1".as_bytes()).is_err());
+        assert!(timing::TimingModel::parse("arrival a".as_bytes()).is_err());
+
+        let expr: RecExpr<LutLang> = "(BUS (LUT 8 a b) (LUT 6 b c))".parse().unwrap();
+        let slack = model
+            .clone()
+            .with_output_names(vec!["y".to_string()])
+            .slack(&expr);
+        assert_eq!(slack["y"], -1.0);
+        assert_eq!(slack["1"], 6.0);
+
+        let mut req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr("(AND (AND a b) c)".parse().unwrap())
+            .with_rules(rewrite::all_static_rules(false))
+            .with_timing(2, model)
+            .with_report()
+            .without_progress_bar()
+            .iter_limited(8);
+        let result = req.synth::<driver::SynthReport>().unwrap();
+        assert_eq!(result.get_expr().to_string(), "(LUT 8 c (LUT 8 b a))");
+
+        // The late input moves to the fast pins
+        let report = result.write_report_to_string().unwrap();
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["slack"]["before"]["0"].as_f64(), Some(-1.0));
+        assert_eq!(report["slack"]["after"]["0"].as_f64(), Some(1.0));
+    }
+
+    #[test]
+    fn test_depth_bound_extract() {
+        let mut egraph: egg::EGraph<LutLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(LUT 8 (LUT 8 a b) c)".parse().unwrap());
+        let wide = egraph.add_expr(&"(LUT 128 a b c d e)".parse().unwrap());
+        egraph.union(root, wide);
+        egraph.rebuild();
+
+        let e = cost::DepthBoundExtract::new(&egraph, cost::KLUTCostFn::new(6), cost::DepthCostFn);
+        assert_eq!(e.min_depth(root), Some(1));
+        assert_eq!(
+            e.extract(root, 2).unwrap().to_string(),
+            "(LUT 8 (LUT 8 a b) c)"
+        );
+        assert_eq!(
+            e.extract(root, 1).unwrap().to_string(),
+            "(LUT 128 a b c d e)"
+        );
+        assert!(e.extract(root, 0).is_none());
+
+        let e = cost::DepthBoundExtract::new(&egraph, cost::KLUTCostFn::new(6), cost::DepthCostFn)
+            .forbid(|n| n.exceeds_cut_size(4));
+        assert_eq!(e.min_depth(root), Some(2));
+        assert!(e.extract(root, 1).is_none());
+    }
+
+    #[test]
+    fn test_pareto_extract() {
+        let mut egraph: egg::EGraph<LutLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(LUT 8 (LUT 8 a b) c)".parse().unwrap());
+        let wide = egraph.add_expr(&"(LUT 128 a b c d e)".parse().unwrap());
+        let deep = egraph.add_expr(&"(LUT 8 (LUT 8 (LUT 8 a b) c) d)".parse().unwrap());
+        egraph.union(root, wide);
+        egraph.union(root, deep);
+        egraph.rebuild();
+
+        let e = cost::DepthBoundExtract::new(&egraph, cost::KLUTCostFn::new(6), cost::DepthCostFn);
+        let front = e.pareto(root);
+        assert_eq!(front.len(), 2);
+        assert_eq!(front[0].get_expr().to_string(), "(LUT 128 a b c d e)");
+        assert_eq!((front[0].get_area(), front[0].get_depth()), (6.0, 1));
+        assert_eq!(front[1].get_expr().to_string(), "(LUT 8 (LUT 8 a b) c)");
+        assert_eq!((front[1].get_area(), front[1].get_depth()), (5.0, 2));
+
+        // Shared inputs count once, so the deeper tree no longer beats the wide LUT
+        let mut egraph: egg::EGraph<LutLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(LUT 8 (LUT 8 a b) c)".parse().unwrap());
+        let wide = egraph.add_expr(&"(LUT 128 a a a b c)".parse().unwrap());
+        egraph.union(root, wide);
+        egraph.rebuild();
+
+        let e = cost::DepthBoundExtract::new(&egraph, cost::KLUTCostFn::new(6), cost::DepthCostFn);
+        let front = e.pareto(root);
+        assert_eq!(front.len(), 1);
+        assert_eq!(front[0].get_expr().to_string(), "(LUT 128 a a a b c)");
+        assert_eq!((front[0].get_area(), front[0].get_depth()), (4.0, 1));
+    }
+
+    #[test]
+    fn test_local_search_extract() {
+        let mut egraph: egg::EGraph<egg::SymbolLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(p (s a b) (t c d))".parse().unwrap());
+        let shared = egraph.add_expr(&"(q (u a b c) (u a b c))".parse().unwrap());
+        egraph.union(root, shared);
+        egraph.rebuild();
+
+        // Greedy extraction counts the shared node twice, so only the local search finds it
+        let (_, greedy) = egg::Extractor::new(&egraph, egg::AstSize).find_best(root);
+        assert_eq!(greedy.to_string(), "(p (s a b) (t c d))");
+        let budget = std::time::Duration::from_millis(100);
+        let e = cost::LocalSearchExtract::new(&egraph, &mut egg::AstSize).with_seed(7);
+        let best = e.extract(root, budget).unwrap();
+        assert_eq!(best.to_string(), "(q (u a b c) (u a b c))");
+        assert_eq!(best.len(), 5);
+
+        let e = cost::LocalSearchExtract::new(&egraph, &mut egg::AstSize)
+            .forbid(|n| n.op.as_str() == "u");
+        let best = e.extract(root, budget).unwrap();
+        assert_eq!(best.to_string(), "(p (s a b) (t c d))");
+    }
+
+    #[test]
+    fn test_random_seed() {
+        let mut egraph: egg::EGraph<egg::SymbolLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(p (s a b) (t c d))".parse().unwrap());
+        for alt in ["(q a b)", "(r c d)", "(u a b c)"] {
+            let alt = egraph.add_expr(&alt.parse().unwrap());
+            egraph.union(root, alt);
+        }
+        egraph.rebuild();
+
+        let draw = |seed| {
+            let e = cost::RandomExtract::with_seed(seed);
+            (0..8)
+                .map(|_| e.extract(&egraph, root).to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(draw(7), draw(7));
+        assert!(
+            draw(7)
+                .iter()
+                .collect::<std::collections::HashSet<_>>()
+                .len()
+                > 1
+        );
+
+        let expr: RecExpr<LutLang> = "(MUX s1 (MUX s0 a b) (MUX s0 c d))".parse().unwrap();
+        let synth = || {
+            let mut req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+                .with_expr(expr.clone())
+                .with_rules(rewrite::all_static_rules(false))
+                .with_k(4)
+                .with_random_samples(Some(7), 16)
+                .with_report()
+                .iter_limited(4);
+            req.synth::<driver::SynthReport>().unwrap()
+        };
+        let result = synth();
+        assert_eq!(result.get_expr(), synth().get_expr());
+        let report = result.write_report_to_string().unwrap();
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["seed"].as_u64(), Some(7));
+    }
+
+    #[test]
+    fn test_fanout_limit() {
+        let mut egraph: egg::EGraph<LutLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(
+            &"(BUS (LUT 8 (LUT 6 a b) c) (LUT 8 (LUT 6 a b) d) (LUT 8 (LUT 6 a b) e))"
+                .parse()
+                .unwrap(),
+        );
+        let expr = egg::Extractor::new(&egraph, egg::AstSize).find_best(root).1;
+        assert_eq!(fanout::FanoutLimit::max_fanout(&expr), 3);
+
+        let limit = fanout::FanoutLimit::new(2);
+        let split = limit.apply(&expr, &mut LutLang::exact_area_cost_fn());
+        assert_eq!(fanout::FanoutLimit::max_fanout(&split), 2);
+        assert_eq!(
+            split
+                .as_ref()
+                .iter()
+                .filter(|n| matches!(n, LutLang::Lut(_)))
+                .count(),
+            5
+        );
+        assert_eq!(split.to_string(), expr.to_string());
+
+        // A duplicate LUT costs more than a single load over the limit
+        let cheap = limit.with_penalty(0.5);
+        let kept = cheap.apply(&expr, &mut LutLang::exact_area_cost_fn());
+        assert_eq!(kept.as_ref(), expr.as_ref());
+    }
+
+    #[test]
+    fn test_power_extract() {
+        let mut egraph: egg::EGraph<LutLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(LUT 8 (LUT 8 a b) c)".parse().unwrap());
+        let alt = egraph.add_expr(&"(LUT 8 a (LUT 8 b c))".parse().unwrap());
+        egraph.union(root, alt);
+        egraph.rebuild();
+
+        let ab = egraph.lookup_expr(&"(LUT 8 a b)".parse().unwrap()).unwrap();
+        let activity = power::ActivityModel::default()
+            .with_words(256)
+            .simulate(&egraph);
+        let p = activity.get_probability(ab).unwrap();
+        let t = activity.get_toggle_rate(ab).unwrap();
+        assert!((p - 0.25).abs() < 0.02);
+        assert!((t - 0.375).abs() < 0.02);
+
+        // The quiet inputs should be joined first
+        let extract = |pa, pc| {
+            let model = power::ActivityModel::new(0.1)
+                .unwrap()
+                .with_input("a", pa)
+                .unwrap()
+                .with_input("c", pc)
+                .unwrap();
+            let c = LutLang::power_cost_fn(2, model.simulate(&egraph));
+            egg::Extractor::new(&egraph, c)
+                .find_best(root)
+                .1
+                .to_string()
+        };
+        assert_eq!(extract(0.1, 0.9), "(LUT 8 (LUT 8 a b) c)");
+        assert_eq!(extract(0.9, 0.1), "(LUT 8 a (LUT 8 b c))");
+    }
+
+    #[cfg(feature = "exact_cbc")]
+    #[test]
+    fn test_ilp_extract() {
+        let mut egraph: egg::EGraph<egg::SymbolLang, ()> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(p (s a b) (t c d))".parse().unwrap());
+        let shared = egraph.add_expr(&"(q (u a b c) (u a b c))".parse().unwrap());
+        egraph.union(root, shared);
+        egraph.rebuild();
+
+        let ilp = ilp::IlpExtract::new(&egraph, egg::AstSize, ilp::IlpObjective::Area);
+        let best = ilp.solve(root, good_lp::coin_cbc, 10.0).unwrap();
+        assert_eq!(best.get_expr().to_string(), "(q (u a b c) (u a b c))");
+        assert_eq!(best.get_gap(), Some(0.0));
+
+        let ilp = ilp::IlpExtract::new(&egraph, egg::AstSize, ilp::IlpObjective::Area)
+            .forbid(|n| n.op.as_str() == "u");
+        let best = ilp.solve(root, good_lp::coin_cbc, 10.0).unwrap();
+        assert_eq!(best.get_expr().to_string(), "(p (s a b) (t c d))");
+    }
+
+    #[cfg(feature = "exact_cbc")]
+    #[test]
+    fn test_ilp_k_lut() {
+        let expr: RecExpr<LutLang> = "(MUX s1 (MUX s0 a b) (MUX s0 c d))".parse().unwrap();
+        let mut req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr.clone())
+            .with_rules(rewrite::all_static_rules(false))
+            .with_k(4)
+            .with_cbc(10)
+            .with_report()
+            .without_progress_bar()
+            .iter_limited(6);
+        let result = req.synth::<driver::SynthReport>().unwrap();
+        let info = LutExprInfo::new(result.get_expr());
+        assert_eq!(info.get_lut_count_k(5) + info.get_lut_count_k(6), 0);
+        assert!(info.get_lut_count() > 0);
+        assert!(LutLang::func_equiv(&expr, result.get_expr()).is_equiv());
+
+        // The solver proves its solution optimal
+        let report = result.write_report_to_string().unwrap();
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert_eq!(report["optimality_gap"].as_f64(), Some(0.0));
+    }
+
+    #[test]
+    fn test_staged_schedule() {
+        let expr: RecExpr<LutLang> = "(MUX s1 (MUX s0 a b) (MUX s0 c d))".parse().unwrap();
+        let mut req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_phase(Phase::new("structural", rewrite::struct_lut_map(false)).iter_limited(4))
+            .with_phase(Phase::new("fusion", rewrite::all_static_rules(false)).iter_limited(6))
+            .with_k(6)
+            .with_report()
+            .without_canonicalization()
+            .without_progress_bar();
+        let result = req.synth::<driver::SynthReport>().unwrap();
+        assert_eq!(LutExprInfo::new(result.get_expr()).get_lut_count(), 1);
+
+        // Iterations of both phases are reported
+        let report = result.write_report_to_string().unwrap();
+        let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+        assert!(report["num_iterations"].as_u64().unwrap() > 2);
+        assert!(report["rule_stats"]["mux2-1-conversion"]["matches"].as_u64() > Some(0));
+    }
+
+    #[test]
+    fn test_pruning() {
+        let expr: RecExpr<LutLang> = "(MUX s1 (MUX s0 a b) (MUX s0 c d))".parse().unwrap();
+        let req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_rules(rewrite::all_static_rules(false))
+            .with_k(4)
+            .with_report()
+            .without_canonicalization()
+            .without_progress_bar()
+            .iter_limited(6);
+
+        let synth = |mut req: SynthRequest<LutLang, LutAnalysis>| {
+            let result = req.synth::<driver::SynthReport>().unwrap();
+            let report = result.write_report_to_string().unwrap();
+            let report: serde_json::Value = serde_json::from_str(&report).unwrap();
+            (
+                result.get_expr().clone(),
+                report["num_nodes"].as_u64().unwrap(),
+            )
+        };
+
+        let (best, nodes) = synth(req.clone());
+        let (pruned, pruned_nodes) = synth(req.clone().with_k_pruning(4));
+        assert!(pruned_nodes < nodes);
+        assert!(LutLang::func_equiv(req.get_expr(), &pruned).is_equiv());
+
+        // The 4-LUT cover decomposes a 6-LUT, so the pruning cost must not penalize it
+        let (pruned, pruned_nodes) =
+            synth(req.clone().with_cost_pruning(cost::KLUTCostFn::new(6), 1.0));
+        assert!(pruned_nodes < nodes);
+        assert!(LutLang::func_equiv(req.get_expr(), &pruned).is_equiv());
+        assert!(pruned.iter().all(|n| n.get_lut_size().unwrap_or(0) <= 4));
+        assert!(
+            LutExprInfo::new(&pruned).get_lut_count() <= LutExprInfo::new(&best).get_lut_count()
+        );
+
+        // More slack keeps more nodes, but never those of infinite cost
+        let (_, kept_nodes) = synth(req.with_cost_pruning(cost::KLUTCostFn::new(6), 1e9));
+        assert!(pruned_nodes < kept_nodes && kept_nodes < nodes);
+    }
+
+    #[test]
+    fn test_constant_propagation() {
+        let mut egraph: egg::EGraph<LutLang, LutAnalysis> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(LUT 202 s a (LUT 8 b false))".parse().unwrap());
+        egraph.rebuild();
+        let folded: RecExpr<LutLang> = "(LUT 8 s a)".parse().unwrap();
+        assert_eq!(egraph.lookup_expr(&folded), Some(egraph.find(root)));
+
+        // Constants discovered later should propagate to the parents
+        let and = egraph.add_expr(&"(LUT 8 c d)".parse().unwrap());
+        let d = egraph.add_expr(&"d".parse().unwrap());
+        let f = egraph.add(LutLang::Const(false));
+        egraph.union(d, f);
+        egraph.rebuild();
+        assert_eq!(egraph[and].data.get_as_const(), Ok(false));
+
+        for (p, pos) in [(202, 0), (202, 1), (202, 2), (6, 1)] {
+            let k = if p == 6 { 2 } else { 3 };
+            for v in [false, true] {
+                let q = lut::eval_lut_const_at(p, k, pos, v);
+                for i in 0..(1 << (k - 1)) {
+                    let lo = i & ((1 << (k - 1 - pos)) - 1);
+                    let hi = (i >> (k - 1 - pos)) << (k - pos);
+                    let index = hi | ((v as u64) << (k - 1 - pos)) | lo;
+                    assert_eq!((q >> i) & 1, (p >> index) & 1);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unsound_merge() {
+        use analysis::SoundnessCheck;
+        use egg::rewrite;
+
+        let expr: RecExpr<LutLang> = "(LUT 8 a b)".parse().unwrap();
+        let mut req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_rules(vec![
+                rewrite!("and-true"; "(LUT 8 ?a ?b)" => "true"),
+                rewrite!("and-false"; "(LUT 8 ?a ?b)" => "false"),
+            ])
+            .with_k(4)
+            .without_progress_bar();
+        let err = req.synth::<driver::SynthReport>().err().unwrap();
+        assert!(err.contains("unsound"));
+        assert!(err.contains("rule and-false"));
+
+        // Inputs proven equal are aliased instead
+        let mut egraph: egg::EGraph<LutLang, LutAnalysis> = egg::EGraph::default();
+        let a = egraph.add_expr(&"a".parse().unwrap());
+        let b = egraph.add_expr(&"b".parse().unwrap());
+        let f = egraph.add(LutLang::Const(false));
+        egraph.union(a, b);
+        egraph.union(b, f);
+        egraph.rebuild();
+        assert!(egraph.analysis.unsound_merges().is_empty());
+        assert_eq!(egraph[a].data.get_inputs(), ["a", "b"]);
+        assert_eq!(egraph[a].data.get_as_const(), Ok(false));
+    }
+
+    #[test]
+    fn test_sweeping() {
+        // Too wide for cut analysis, and no rules connect the two outputs
+        let expr: RecExpr<LutLang> =
+            "(BUS (AND (AND (AND a b) (AND c d)) (AND (AND e f) g)) (AND a (AND b (AND c (AND d (AND e (AND f g)))))))"
+                .parse()
+                .unwrap();
+        let req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_k(4)
+            .without_canonicalization()
+            .without_progress_bar();
+
+        let synth = |mut req: SynthRequest<LutLang, LutAnalysis>| {
+            let result = req.synth::<driver::SynthReport>().unwrap();
+            match result.get_expr().as_ref().last().unwrap() {
+                LutLang::Bus(b) => b[0] == b[1],
+                _ => panic!("Expected a bus"),
+            }
+        };
+
+        assert!(!synth(req.clone()));
+        assert!(synth(req.clone().with_sweeping(8)));
+        assert!(!synth(req.with_sweeping(6)));
+    }
+
+    #[test]
+    fn test_cut_seeding() {
+        use cut::CutEnumeration;
+
+        let expr: RecExpr<LutLang> = "(AND a (XOR b c))".parse().unwrap();
+        let cuts = CutEnumeration::new(&expr, 4, 8);
+        let root = egg::Id::from(expr.as_ref().len() - 1);
+        let best = &cuts.get_cuts(root)[0];
+        assert_eq!(best.leaves().len(), 3);
+        assert_eq!(cuts.get_depth(root), 1);
+        assert_eq!(cuts.truth_table(root, best), 40);
+
+        let expr: RecExpr<LutLang> = "(AND a (AND b (AND c (AND d (AND e (AND f (AND g h)))))))"
+            .parse()
+            .unwrap();
+        let req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_k(4)
+            .without_progress_bar();
+        let synth = |mut req: SynthRequest<LutLang, LutAnalysis>| {
+            let result = req.synth::<driver::SynthReport>().unwrap();
+            LutExprInfo::new(result.get_expr()).get_lut_count()
+        };
+
+        // Without any rules, the chain is only covered by 4-LUTs through the seeded cuts
+        assert_eq!(synth(req.clone()), 7);
+        assert_eq!(synth(req.with_cut_seeding(4, 8)), 3);
+    }
+
+    #[test]
+    fn test_dont_cares() {
+        use dontcare::simplify_dont_cares;
+
+        // The inner XOR is only observed when a is true
+        let expr: RecExpr<LutLang> = "(LUT 8 a (LUT 6 a b))".parse().unwrap();
+        let (simplified, removed) = simplify_dont_cares(&expr, 1).unwrap();
+        assert_eq!(removed, 1);
+        assert_eq!(simplified.to_string(), "(LUT 8 a (LUT 1 b))");
+        assert!(LutLang::func_equiv(&expr, &simplified).unwrap());
+
+        // The AND of a and b is never false while their OR is true
+        let expr: RecExpr<LutLang> = "(LUT 8 (LUT 8 a b) (LUT 14 a b))".parse().unwrap();
+        let (simplified, removed) = simplify_dont_cares(&expr, 1).unwrap();
+        assert_eq!(removed, 2);
+        assert_eq!(simplified.to_string(), "(LUT 2 (LUT 8 a b))");
+        assert!(LutLang::func_equiv(&expr, &simplified).unwrap());
+
+        // Nothing is removed when every input combination is observed
+        let expr: RecExpr<LutLang> = "(LUT 6 a (LUT 8 b c))".parse().unwrap();
+        let (simplified, removed) = simplify_dont_cares(&expr, 2).unwrap();
+        assert_eq!(removed, 0);
+        assert_eq!(simplified.to_string(), expr.to_string());
+    }
+
+    #[test]
+    #[cfg(feature = "cut_analysis")]
+    fn test_input_set() {
+        use analysis::InputSet;
+
+        let mut cut = InputSet::singleton(3);
+        cut.union_with(&InputSet::singleton(130));
+        cut.union_with(&InputSet::singleton(3));
+        assert_eq!(cut.len(), 2);
+        assert_eq!(cut.iter().collect::<Vec<_>>(), [3, 130]);
+        assert!(cut.intersects(&InputSet::singleton(130)));
+        assert!(!cut.intersects(&InputSet::singleton(64)));
+        assert!(InputSet::default().is_empty());
+
+        // Inputs are named in the order they were interned
+        let mut egraph: egg::EGraph<LutLang, LutAnalysis> = egg::EGraph::default();
+        let root = egraph.add_expr(&"(LUT 6 (LUT 8 z b) a)".parse().unwrap());
+        egraph.rebuild();
+        let cut = egraph.analysis.cut_inputs(egraph[root].data.get_cut());
+        assert_eq!(cut, ["z".into(), "b".into(), "a".into()]);
+        let (tt, _) = egraph[root].data.get_truth_table(&egraph.analysis).unwrap();
+        assert_eq!(tt, 106);
+    }
+
+    #[test]
+    #[cfg(feature = "cut_analysis")]
+    fn test_cut_collapse() {
+        let expr: RecExpr<LutLang> = "(AND (XOR a b) (NOT (NOR a c)))".parse().unwrap();
+        let collapsed: RecExpr<LutLang> = "(LUT 56 a b c)".parse().unwrap();
+
+        // Collapsing is opt-in
+        let mut egraph: egg::EGraph<LutLang, LutAnalysis> = egg::EGraph::default();
+        let root = egraph.add_expr(&expr);
+        egraph.rebuild();
+        assert!(
+            egraph[root]
+                .data
+                .get_truth_table(&egraph.analysis)
+                .is_some()
+        );
+        assert_eq!(egraph.lookup_expr(&collapsed), None);
+
+        // The cut does not fit in a 2-LUT
+        let mut egraph = egg::EGraph::new(LutAnalysis::default().with_cut_collapse(2));
+        let root = egraph.add_expr(&expr);
+        egraph.rebuild();
+        assert!(
+            egraph[root]
+                .data
+                .get_truth_table(&egraph.analysis)
+                .is_none()
+        );
+        assert_eq!(egraph.lookup_expr(&collapsed), None);
+
+        let mut egraph = egg::EGraph::new(LutAnalysis::default().with_cut_collapse(6));
+        let root = egraph.add_expr(&expr);
+        egraph.rebuild();
+        let (tt, cut) = egraph[root].data.get_truth_table(&egraph.analysis).unwrap();
+        assert_eq!(tt, 56);
+        assert_eq!(cut, ["a".into(), "b".into(), "c".into()]);
+        assert_eq!(egraph.lookup_expr(&collapsed), Some(egraph.find(root)));
+
+        // Absorption collapses to the input itself
+        let root = egraph.add_expr(&"(AND a (NOT (NOR a b)))".parse().unwrap());
+        egraph.rebuild();
+        let a = egraph.lookup_expr(&"a".parse().unwrap()).unwrap();
+        assert_eq!(egraph.find(root), egraph.find(a));
+    }
+
+    #[test]
+    fn test_rule_stats() {
+        let expr: RecExpr<LutLang> = "(LUT 6 (LUT 6 c d) b)".parse().unwrap();
+        let mut req: SynthRequest<LutLang, LutAnalysis> = SynthRequest::default()
+            .with_expr(expr)
+            .with_report()
+            .with_rules(rewrite::all_static_rules(false))
+            .with_k(4)
+            .without_progress_bar();
+        let result = req.synth::<driver::SynthReport>().unwrap();
+        let report = result.write_report_to_string().unwrap();
+        assert!(report.contains("\"rule_stats\""));
+        assert!(report.contains("\"lut3-2-fuse\""));
+
+        let mut rpt = Vec::new();
+        assert!(result.print_report(&mut rpt).is_ok());
+        assert!(String::from_utf8(rpt).unwrap().contains("search_time"));
     }
 }
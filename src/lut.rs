@@ -6,8 +6,10 @@
 use super::analysis::LutAnalysis;
 use super::check::{Check, equivalent, inconclusive, not_equivalent};
 use super::cost::DepthCostFn;
-use super::cost::{GateCostFn, KLUTCostFn};
+use super::cost::{DagCost, GateCostFn, KLUTCostFn};
 use super::driver::{Canonical, CircuitLang, EquivCheck, Explanable, Extractable};
+use super::power::{PowerCostFn, SwitchingActivity};
+use super::timing::{TimingCostFn, TimingModel};
 use bitvec::prelude::*;
 use egg::CostFunction;
 use egg::Id;
@@ -538,6 +540,17 @@ fn verify_expr(expr: &RecExpr<LutLang>) -> Result<(), String> {
     Ok(())
 }
 
+/// The truth tables of the first six variables of a function, lsb first.
+/// Simulating a function on these patterns gives its truth table.
+pub const VAR_PATTERNS: [u64; 6] = [
+    0xaaaaaaaaaaaaaaaa,
+    0xcccccccccccccccc,
+    0xf0f0f0f0f0f0f0f0,
+    0xff00ff00ff00ff00,
+    0xffff0000ffff0000,
+    0xffffffff00000000,
+];
+
 /// Evaluates the boolean value of a Lut program given a slice of [bool] inputs (msb first).
 pub fn eval_lut(p: u64, inputs: &[bool]) -> bool {
     let mut index = 0;
@@ -607,7 +620,7 @@ pub fn remove_lsb_var(p: u64, k: usize) -> Option<u64> {
 /// Return a partially-evaluated LUT program with the `msb` input tied to the constant `v`
 pub fn eval_lut_const_input(p: &u64, msb: usize, v: bool) -> u64 {
     assert!(msb <= 5);
-    assert_eq!(msb >> (1 << (msb + 1)), 0);
+    assert!(msb == 5 || p >> (1 << (msb + 1)) == 0);
     if v {
         p >> (1 << msb)
     } else {
@@ -615,6 +628,24 @@ pub fn eval_lut_const_input(p: &u64, msb: usize, v: bool) -> u64 {
     }
 }
 
+/// Return a partially-evaluated `k`-LUT program with the input at `pos` (offset from the msb) tied to the constant `v`
+pub fn eval_lut_const_at(p: u64, k: usize, pos: usize, v: bool) -> u64 {
+    assert!(pos < k);
+    if pos == 0 {
+        return eval_lut_const_input(&p, k - 1, v);
+    }
+
+    let b = k - pos - 1;
+    let mut r = 0;
+    for i in 0..(1 << (k - 1)) {
+        let lo = i & ((1 << b) - 1);
+        let hi = (i >> b) << (b + 1);
+        let index = hi | ((v as u64) << b) | lo;
+        r |= ((p >> index) & 1) << i;
+    }
+    r
+}
+
 /// Returns a pair of programs (r, q) s.t. msb * r + not(msb) * q = p
 pub fn cofactors_in_msb(p: &u64, k: usize) -> (u64, u64) {
     assert!(k >= 2);
@@ -1111,17 +1142,49 @@ impl Extractable for LutLang {
         DepthCostFn
     }
 
-    fn cell_cost_with_reg_weight_fn(cut_size: usize, w: u64) -> impl CostFunction<Self> {
+    fn cell_cost_with_reg_weight_fn(
+        cut_size: usize,
+        w: u64,
+    ) -> impl CostFunction<Self, Cost: DagCost> {
         KLUTCostFn::new(cut_size).with_reg_weight(w)
     }
 
-    fn exact_area_cost_fn() -> impl CostFunction<Self> {
+    fn exact_area_cost_fn() -> impl CostFunction<Self, Cost: DagCost> {
         KLUTCostFn::new(6).with_reg_weight(1)
     }
 
-    fn filter_cost_fn(set: std::collections::HashSet<String>) -> impl CostFunction<Self> {
+    fn filter_cost_fn(
+        set: std::collections::HashSet<String>,
+    ) -> impl CostFunction<Self, Cost: DagCost> {
         GateCostFn::new(set)
     }
+
+    fn timing_cost_fn(cut_size: usize, model: TimingModel) -> impl CostFunction<Self, Cost = f32> {
+        TimingCostFn::new(model).with_cut_size(cut_size)
+    }
+
+    fn power_cost_fn(
+        cut_size: usize,
+        activity: SwitchingActivity<Self>,
+    ) -> impl CostFunction<Self, Cost = f32> {
+        PowerCostFn::new(KLUTCostFn::new(cut_size), cut_size, activity)
+    }
+
+    fn exceeds_cut_size(&self, cut_size: usize) -> bool {
+        matches!(self, LutLang::Lut(l) if l.len() > cut_size.saturating_add(1))
+    }
+
+    fn is_logic(&self) -> bool {
+        matches!(
+            self,
+            LutLang::Lut(_)
+                | LutLang::Nor(_)
+                | LutLang::Mux(_)
+                | LutLang::And(_)
+                | LutLang::Xor(_)
+                | LutLang::Not(_)
+        )
+    }
 }
 
 impl Canonical for LutLang {
@@ -1159,6 +1222,10 @@ impl CircuitLang for LutLang {
         Some(Self::Program(x))
     }
 
+    fn lut(ids: impl Iterator<Item = egg::Id>) -> Option<Self> {
+        Some(Self::Lut(ids.collect()))
+    }
+
     fn is_bus(&self) -> bool {
         matches!(self, Self::Bus(_))
     }
@@ -1180,6 +1247,34 @@ impl CircuitLang for LutLang {
             _ => None,
         }
     }
+
+    fn simulate(&self, c: &[u64]) -> Option<u64> {
+        match self {
+            Self::Const(b) => Some(if *b { u64::MAX } else { 0 }),
+            Self::Nor(_) => Some(!(c[0] | c[1])),
+            Self::And(_) => Some(c[0] & c[1]),
+            Self::Xor(_) => Some(c[0] ^ c[1]),
+            Self::Not(_) => Some(!c[0]),
+            Self::Mux(_) => Some((c[0] & c[1]) | (!c[0] & c[2])),
+            Self::Lut(_) => {
+                let (p, x) = c.split_first().unwrap();
+                let k = x.len();
+                let mut r = 0;
+                for m in (0..1 << k).filter(|m| (p >> m) & 1 == 1) {
+                    // Operands are msb first
+                    r |= x.iter().enumerate().fold(u64::MAX, |t, (i, xi)| {
+                        if (m >> (k - 1 - i)) & 1 == 1 {
+                            t & xi
+                        } else {
+                            t & !xi
+                        }
+                    });
+                }
+                Some(r)
+            }
+            _ => None,
+        }
+    }
 }
 
 #[cfg(test)]
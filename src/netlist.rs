@@ -74,6 +74,26 @@ impl<L: CircuitLang, I: Instantiable + LogicFunc<L>> LogicMapping<L, I> {
         self.leaves_by_id.get(id).cloned()
     }
 
+    /// Returns the same mapping into a copy of its netlist, like one made by [Netlist::deep_clone].
+    /// Returns [None] if a mapped net is missing from `netlist`.
+    pub fn with_netlist(&self, netlist: &Netlist<I>) -> Option<Self> {
+        let find = |n: &DrivenNet<I>| netlist.find_net(&n.as_net());
+        Some(Self {
+            expr: self.expr.clone(),
+            roots: self.roots.iter().map(find).collect::<Option<_>>()?,
+            leaves: self
+                .leaves
+                .iter()
+                .map(|(s, n)| Some((*s, find(n)?)))
+                .collect::<Option<_>>()?,
+            leaves_by_id: self
+                .leaves_by_id
+                .iter()
+                .map(|(id, n)| Some((*id, find(n)?)))
+                .collect::<Option<_>>()?,
+        })
+    }
+
     /// Replaces the expression with a rewritten one
     ///
     /// # Panics
@@ -0,0 +1,201 @@
+/*!
+
+  Switching activity and power estimation. The e-graph is simulated on random input patterns,
+  where each input is one with a given probability, and every e-class records how often its signal is one
+  and how often it toggles between consecutive patterns. Classes that are cut off from simulation, like register outputs,
+  are treated as inputs. Since every node of an e-class computes the same function, cells without a simulation model
+  take the activity of an equivalent gate.
+
+*/
+use super::cost::DagCost;
+use super::driver::{CircuitLang, Extractable, SweepNode, sweep_order, sweep_simulate};
+use egg::{Analysis, CostFunction, EGraph, Id, Language};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::collections::HashMap;
+
+/// The input statistics used to estimate the switching activity of an e-graph
+#[derive(Debug, Clone)]
+pub struct ActivityModel {
+    default: f64,
+    inputs: HashMap<String, f64>,
+    words: usize,
+    seed: u64,
+}
+
+impl ActivityModel {
+    /// The default number of 64-bit words of patterns simulated
+    pub const DEF_WORDS: usize = 64;
+
+    /// Returns a model where every input is one with probability `default`.
+    pub fn new(default: f64) -> Result<Self, String> {
+        Ok(Self {
+            default: Self::check(default)?,
+            inputs: HashMap::new(),
+            words: Self::DEF_WORDS,
+            seed: 0,
+        })
+    }
+
+    fn check(p: f64) -> Result<f64, String> {
+        if (0.0..=1.0).contains(&p) {
+            Ok(p)
+        } else {
+            Err(format!("Probability {p} is not between 0 and 1"))
+        }
+    }
+
+    /// Set the probability that `input` is one.
+    pub fn with_input(mut self, input: &str, p: f64) -> Result<Self, String> {
+        self.inputs.insert(input.to_string(), Self::check(p)?);
+        Ok(self)
+    }
+
+    /// Simulate `words` words of 64 patterns.
+    pub fn with_words(self, words: usize) -> Self {
+        Self {
+            words: words.max(1),
+            ..self
+        }
+    }
+
+    /// Seed the random patterns.
+    pub fn with_seed(self, seed: u64) -> Self {
+        Self { seed, ..self }
+    }
+
+    /// Estimate the switching activity of every e-class in `egraph`.
+    pub fn simulate<L, A>(&self, egraph: &EGraph<L, A>) -> SwitchingActivity<L>
+    where
+        L: CircuitLang,
+        A: Analysis<L>,
+    {
+        let (reps, order) = sweep_order(egraph);
+        let mut rng = StdRng::seed_from_u64(self.seed);
+        let inputs = order
+            .iter()
+            .filter(|id| matches!(reps[id], SweepNode::Input))
+            .map(|id| {
+                let p = egraph[*id]
+                    .nodes
+                    .iter()
+                    .find_map(|n| n.get_var())
+                    .and_then(|v| self.inputs.get(v.as_str()))
+                    .copied()
+                    .unwrap_or(self.default);
+                let pattern = (0..self.words)
+                    .map(|_| (0..64).fold(0, |w, b| w | (u64::from(rng.random_bool(p)) << b)))
+                    .collect();
+                (*id, pattern)
+            })
+            .collect();
+        let sims = sweep_simulate(&order, &reps, inputs, self.words);
+
+        let bits = (self.words * 64) as f64;
+        let mut probability = HashMap::new();
+        let mut toggle_rate = HashMap::new();
+        for (id, sim) in &sims {
+            if matches!(reps[id], SweepNode::Int(_)) {
+                continue;
+            }
+            let ones: u32 = sim.iter().map(|w| w.count_ones()).sum();
+            // Patterns are consecutive from the lsb of the first word
+            let toggles: u32 = sim
+                .iter()
+                .enumerate()
+                .map(|(i, w)| {
+                    let next = sim.get(i + 1).map_or(*w >> 63, |n| n & 1);
+                    (w ^ ((w >> 1) | (next << 63))).count_ones()
+                })
+                .sum();
+            probability.insert(*id, ones as f64 / bits);
+            toggle_rate.insert(*id, toggles as f64 / (bits - 1.0));
+        }
+
+        let nodes = egraph
+            .classes()
+            .flat_map(|c| c.nodes.iter().map(move |n| (n.clone(), c.id)))
+            .collect();
+
+        SwitchingActivity {
+            probability,
+            toggle_rate,
+            nodes,
+        }
+    }
+}
+
+impl Default for ActivityModel {
+    fn default() -> Self {
+        Self::new(0.5).unwrap()
+    }
+}
+
+/// The signal probability and toggle rate of every e-class in an e-graph
+#[derive(Debug, Clone)]
+pub struct SwitchingActivity<L: Language> {
+    probability: HashMap<Id, f64>,
+    toggle_rate: HashMap<Id, f64>,
+    nodes: HashMap<L, Id>,
+}
+
+impl<L: Language> SwitchingActivity<L> {
+    /// Returns the probability that the signal of e-class `id` is one.
+    pub fn get_probability(&self, id: Id) -> Option<f64> {
+        self.probability.get(&id).copied()
+    }
+
+    /// Returns the fraction of patterns on which the signal of e-class `id` toggles.
+    pub fn get_toggle_rate(&self, id: Id) -> Option<f64> {
+        self.toggle_rate.get(&id).copied()
+    }
+
+    /// Returns the toggle rate of the output of e-node `node`.
+    pub fn get_node_toggle_rate(&self, node: &L) -> Option<f64> {
+        self.nodes
+            .get(node)
+            .and_then(|id| self.get_toggle_rate(*id))
+    }
+}
+
+/// A cost function for dynamic power, weighing every node by the cost of `inner` as its capacitance
+/// times the toggle rate of its output. Cells with more than `cut_size` inputs are never extracted.
+/// Only the e-nodes of the simulated e-graph can be weighed, so other nodes have a toggle rate of one.
+pub struct PowerCostFn<L: Language, C> {
+    inner: C,
+    cut_size: usize,
+    activity: SwitchingActivity<L>,
+}
+
+impl<L: Language, C> PowerCostFn<L, C> {
+    /// Returns a new power cost function with capacitances given by `inner`.
+    pub fn new(inner: C, cut_size: usize, activity: SwitchingActivity<L>) -> Self {
+        Self {
+            inner,
+            cut_size,
+            activity,
+        }
+    }
+}
+
+impl<L, C> CostFunction<L> for PowerCostFn<L, C>
+where
+    L: Extractable,
+    C: CostFunction<L, Cost: DagCost>,
+{
+    type Cost = f32;
+    fn cost<F>(&mut self, enode: &L, mut costs: F) -> Self::Cost
+    where
+        F: FnMut(Id) -> Self::Cost,
+    {
+        if enode.exceeds_cut_size(self.cut_size) {
+            return f32::MAX;
+        }
+        let Some(capacitance) = self.inner.cost(enode, |_| C::Cost::default()).finite() else {
+            return f32::MAX;
+        };
+        let toggle_rate = self.activity.get_node_toggle_rate(enode).unwrap_or(1.0);
+        let op_cost = (capacitance * toggle_rate) as f32;
+        enode.fold(op_cost, |sum, id| sum.saturating_add(costs(id)))
+    }
+}
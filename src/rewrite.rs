@@ -4,6 +4,7 @@
 
 */
 use super::analysis::LutAnalysis;
+use super::driver::CircuitLang;
 use super::lut;
 use super::lut::to_bitvec;
 use bitvec::{bitvec, order::Lsb0, vec::BitVec};
@@ -178,6 +179,26 @@ pub fn general_cut_fusion() -> Vec<Rewrite<lut::LutLang, LutAnalysis>> {
     rules
 }
 
+/// Collapses paths that reconverge within `depth` levels below a LUT into a single LUT with at most `k` inputs.
+/// This catches the sharing in comparators and adders that pairwise cut fusion only finds by chance.
+pub fn reconvergent_fusion(k: usize, depth: usize) -> Vec<Rewrite<lut::LutLang, LutAnalysis>> {
+    let mut rules: Vec<Rewrite<lut::LutLang, LutAnalysis>> = Vec::new();
+    for n in 2..=lut::LutLang::MAX_LUT_SIZE {
+        let vars = (0..n).map(|i| format!("?v{i}")).collect::<Vec<String>>();
+        let pattern: Pattern<lut::LutLang> =
+            format!("(LUT ?p {})", vars.join(" ")).parse().unwrap();
+        let applier = CollapseReconvergence::new(
+            "?p".parse().unwrap(),
+            vars.iter().map(|v| v.parse().unwrap()).collect(),
+            k,
+            depth,
+        );
+        rules.push(rewrite!(format!("lut{n}-reconverge"); pattern => applier));
+    }
+
+    rules
+}
+
 /// Returns a list of rules for evaluating constant LUTs
 pub fn constant_luts<A>() -> Vec<Rewrite<lut::LutLang, A>>
 where
@@ -226,6 +247,17 @@ pub fn dyn_decompositions(any_order: bool) -> Vec<Rewrite<lut::LutLang, LutAnaly
     rules
 }
 
+/// Replace LUTs with at most five inputs with a minimum network of 2-LUTs, found by exhaustive search.
+/// The networks are only added when the search finds one with at most `max_gates` gates.
+#[cfg(feature = "dyn_decomp")]
+pub fn exact_decompositions(max_gates: usize) -> Vec<Rewrite<lut::LutLang, LutAnalysis>> {
+    let mut rules: Vec<Rewrite<lut::LutLang, LutAnalysis>> = Vec::new();
+    rules.push(rewrite!("lut3-exact"; "(LUT ?p ?a ?b ?c)" => {decomp::ExactSynthesis::new("?p".parse().unwrap(), vec!["?a".parse().unwrap(), "?b".parse().unwrap(), "?c".parse().unwrap()], max_gates)}));
+    rules.push(rewrite!("lut4-exact"; "(LUT ?p ?a ?b ?c ?d)" => {decomp::ExactSynthesis::new("?p".parse().unwrap(), vec!["?a".parse().unwrap(), "?b".parse().unwrap(), "?c".parse().unwrap(), "?d".parse().unwrap()], max_gates)}));
+    rules.push(rewrite!("lut5-exact"; "(LUT ?p ?a ?b ?c ?d ?e)" => {decomp::ExactSynthesis::new("?p".parse().unwrap(), vec!["?a".parse().unwrap(), "?b".parse().unwrap(), "?c".parse().unwrap(), "?d".parse().unwrap(), "?e".parse().unwrap()], max_gates)}));
+    rules
+}
+
 /// Canonicalizes LUTs with redundant inputs
 pub fn redundant_inputs() -> Vec<Rewrite<lut::LutLang, LutAnalysis>> {
     let mut rules: Vec<Rewrite<lut::LutLang, LutAnalysis>> = Vec::new();
@@ -239,6 +271,28 @@ pub fn redundant_inputs() -> Vec<Rewrite<lut::LutLang, LutAnalysis>> {
     rules
 }
 
+/// Returns a list of rules for small LUTs with constant or ignored inputs
+fn constant_inputs<A>() -> Vec<Rewrite<lut::LutLang, A>>
+where
+    A: Analysis<lut::LutLang>,
+{
+    vec![
+        rewrite!("lut1-const-false"; "(LUT 0 ?a)" => "false"),
+        rewrite!("lut1-const-true"; "(LUT 3 ?a)" => "true"),
+        rewrite!("lut1-const-id"; "(LUT 2 ?a)" => "?a"),
+        rewrite!("lut2-invariant"; "(LUT 12 ?a ?b)" => "(LUT 2 ?a)"),
+        rewrite!("lut1-const-true-inv"; "(LUT 1 false)" => "true"),
+        rewrite!("lut1-const-false-inv"; "(LUT 1 true)" => "false"),
+    ]
+}
+
+fn double_complement<A>() -> Rewrite<lut::LutLang, A>
+where
+    A: Analysis<lut::LutLang>,
+{
+    rewrite!("double-complement"; "(NOT (NOT ?a))" => "?a")
+}
+
 /// Returns a list of all static LUT rewrite rules
 /// `bidirectional` determines if gates are inserted for 2-LUTs
 pub fn all_static_rules(bidirectional: bool) -> Vec<Rewrite<lut::LutLang, LutAnalysis>> {
@@ -250,14 +304,9 @@ pub fn all_static_rules(bidirectional: bool) -> Vec<Rewrite<lut::LutLang, LutAna
     // Evaluate constant programs
     rules.append(&mut constant_luts());
 
-    // Evaluate constant inputs (impl as modify-analysis for multi-input cases)
-    rules.push(rewrite!("lut1-const-false"; "(LUT 0 ?a)" => "false"));
-    rules.push(rewrite!("lut1-const-true"; "(LUT 3 ?a)" => "true"));
-    rules.push(rewrite!("lut1-const-id"; "(LUT 2 ?a)" => "?a"));
-    rules.push(rewrite!("lut2-invariant"; "(LUT 12 ?a ?b)" => "(LUT 2 ?a)"));
-    rules.push(rewrite!("lut1-const-true-inv"; "(LUT 1 false)" => "true"));
-    rules.push(rewrite!("lut1-const-false-inv"; "(LUT 1 true)" => "false"));
-    rules.push(rewrite!("double-complement"; "(NOT (NOT ?a))" => "?a"));
+    // Evaluate constant inputs (multi-input cases are folded by the analysis)
+    rules.append(&mut constant_inputs());
+    rules.push(double_complement());
 
     // Remove redundant inputs
     rules.append(&mut redundant_inputs());
@@ -692,6 +741,265 @@ impl Applier<lut::LutLang, LutAnalysis> for FuseCut {
     }
 }
 
+/// A cut found while expanding the window of [CollapseReconvergence]
+#[derive(Debug, Clone)]
+struct WindowCut {
+    /// The sorted leaves of the cut
+    leaves: Vec<egg::Id>,
+    /// True if two paths through the window meet at the same e-class
+    reconvergent: bool,
+    /// The number of e-classes expanded to reach the leaves
+    expanded: usize,
+}
+
+impl WindowCut {
+    /// Merge two cuts, if the result has at most `k` leaves
+    fn merge(&self, other: &Self, k: usize) -> Option<Self> {
+        let mut leaves = self.leaves.clone();
+        leaves.extend(&other.leaves);
+        leaves.sort();
+        leaves.dedup();
+        if leaves.len() > k {
+            return None;
+        }
+        Some(Self {
+            reconvergent: self.reconvergent
+                || other.reconvergent
+                || leaves.len() < self.leaves.len() + other.leaves.len(),
+            leaves,
+            expanded: self.expanded + other.expanded,
+        })
+    }
+}
+
+/// A rewrite applier that collapses reconvergent paths below a LUT into a single LUT.
+/// The e-graph is expanded up to `depth` levels below the matched LUT, and the smallest reconvergent
+/// cut with at most `k` leaves is compiled into a new LUT.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CollapseReconvergence {
+    /// The program
+    program: Var,
+    /// The inputs of the LUT
+    vars: Vec<Var>,
+    /// The max size of the collapsed LUT
+    k: usize,
+    /// The number of levels to expand, including the matched LUT
+    depth: usize,
+}
+
+impl CollapseReconvergence {
+    /// The max number of cuts kept per e-class while expanding the window
+    const MAX_CUTS: usize = 16;
+
+    /// Create an applier that collapses reconvergence up to `depth` levels below a LUT over `vars` into a `k`-LUT.
+    pub fn new(program: Var, vars: Vec<Var>, k: usize, depth: usize) -> Self {
+        Self {
+            program,
+            vars,
+            k,
+            depth,
+        }
+    }
+
+    /// The node used to expand e-class `id`: the combinational node with the fewest signals that does not contain itself
+    fn expansion(
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        id: egg::Id,
+    ) -> Option<&lut::LutLang> {
+        egraph[id]
+            .nodes
+            .iter()
+            .filter(|n| {
+                n.simulate(&vec![0; n.len()]).is_some()
+                    && !n.children().iter().any(|c| egraph.find(*c) == id)
+            })
+            .min_by_key(|n| n.len())
+    }
+
+    /// Returns the children of `node` that are signals and not programs
+    fn signals(
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        node: &lut::LutLang,
+    ) -> Vec<egg::Id> {
+        node.children()
+            .iter()
+            .map(|c| egraph.find(*c))
+            .filter(|c| egraph[*c].data.get_program().is_err())
+            .collect()
+    }
+
+    /// Merge the cuts of `signals`, each expanded up to `depth` levels
+    fn merge_cuts(
+        &self,
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        signals: &[egg::Id],
+        depth: usize,
+        memo: &mut HashMap<(egg::Id, usize), Vec<WindowCut>>,
+    ) -> Vec<WindowCut> {
+        let mut merged = vec![WindowCut {
+            leaves: Vec::new(),
+            reconvergent: false,
+            expanded: 0,
+        }];
+        for s in signals {
+            let cuts = self.cuts(egraph, *s, depth, memo);
+            let mut next: Vec<WindowCut> = merged
+                .iter()
+                .flat_map(|m| cuts.iter().filter_map(|c| m.merge(c, self.k)))
+                .collect();
+            next.sort_by_key(|c| (c.leaves.len(), !c.reconvergent));
+            next.truncate(Self::MAX_CUTS);
+            merged = next;
+        }
+        merged
+    }
+
+    /// The cuts of e-class `id` expanded up to `depth` levels
+    fn cuts(
+        &self,
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        id: egg::Id,
+        depth: usize,
+        memo: &mut HashMap<(egg::Id, usize), Vec<WindowCut>>,
+    ) -> Vec<WindowCut> {
+        if let Some(cuts) = memo.get(&(id, depth)) {
+            return cuts.clone();
+        }
+        let mut cuts = vec![WindowCut {
+            leaves: vec![id],
+            reconvergent: false,
+            expanded: 0,
+        }];
+        if depth > 0
+            && let Some(node) = Self::expansion(egraph, id)
+        {
+            let signals = Self::signals(egraph, node);
+            for mut c in self.merge_cuts(egraph, &signals, depth - 1, memo) {
+                c.expanded += 1;
+                cuts.push(c);
+            }
+        }
+        memo.insert((id, depth), cuts.clone());
+        cuts
+    }
+
+    /// Simulate e-class `id` as a function of `leaves`, the first leaf being the lsb
+    fn simulate(
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        id: egg::Id,
+        leaves: &[egg::Id],
+        depth: usize,
+    ) -> Option<u64> {
+        if let Some(i) = leaves.iter().position(|l| *l == id) {
+            return Some(lut::VAR_PATTERNS[i]);
+        }
+        if depth == 0 {
+            return None;
+        }
+        let node = Self::expansion(egraph, id)?;
+        Self::simulate_node(egraph, node, leaves, depth - 1)
+    }
+
+    /// Simulate `node` as a function of `leaves`, with its children expanded up to `depth` levels
+    fn simulate_node(
+        egraph: &egg::EGraph<lut::LutLang, LutAnalysis>,
+        node: &lut::LutLang,
+        leaves: &[egg::Id],
+        depth: usize,
+    ) -> Option<u64> {
+        let children = node
+            .children()
+            .iter()
+            .map(|c| {
+                let c = egraph.find(*c);
+                match egraph[c].data.get_program() {
+                    Ok(p) => Some(p),
+                    Err(_) => Self::simulate(egraph, c, leaves, depth),
+                }
+            })
+            .collect::<Option<Vec<u64>>>()?;
+        node.simulate(&children)
+    }
+}
+
+impl Applier<lut::LutLang, LutAnalysis> for CollapseReconvergence {
+    fn apply_one(
+        &self,
+        egraph: &mut egg::EGraph<lut::LutLang, LutAnalysis>,
+        eclass: egg::Id,
+        subst: &egg::Subst,
+        searcher_ast: Option<&egg::PatternAst<lut::LutLang>>,
+        rule_name: egg::Symbol,
+    ) -> Vec<egg::Id> {
+        let eclass = egraph.find(eclass);
+        let operands = self
+            .vars
+            .iter()
+            .map(|v| egraph.find(subst[*v]))
+            .collect::<Vec<egg::Id>>();
+        if operands.contains(&eclass) || self.depth < 2 {
+            return vec![];
+        }
+        let program_id = subst[self.program];
+
+        let mut memo = HashMap::new();
+        let mut direct = operands.clone();
+        direct.sort();
+        direct.dedup();
+        let best = self
+            .merge_cuts(egraph, &operands, self.depth - 1, &mut memo)
+            .into_iter()
+            .filter(|c| c.reconvergent && c.leaves != direct && !c.leaves.contains(&eclass))
+            .min_by_key(|c| (c.leaves.len(), std::cmp::Reverse(c.expanded)));
+        let Some(cut) = best else {
+            return vec![];
+        };
+
+        let mut children = vec![program_id];
+        children.extend(&operands);
+        let root = lut::LutLang::Lut(children.into());
+        let Some(tt) = Self::simulate_node(egraph, &root, &cut.leaves, self.depth - 1) else {
+            return vec![];
+        };
+        let n = cut.leaves.len();
+        let tt = if n < 6 {
+            tt & ((1 << (1 << n)) - 1)
+        } else {
+            tt
+        };
+
+        // LUT operands are msb first
+        match searcher_ast {
+            Some(ast) => {
+                // The leaves are deeper than the pattern, so bind them to fresh variables
+                let mut subst = subst.clone();
+                let mut leaves: Vec<String> = Vec::with_capacity(n);
+                for (i, l) in cut.leaves.iter().enumerate().rev() {
+                    let v: Var = format!("?reconverge{i}").parse().unwrap();
+                    subst.insert(v, *l);
+                    leaves.push(v.to_string());
+                }
+                let new_ast: PatternAst<lut::LutLang> =
+                    format!("(LUT {} {})", tt, leaves.join(" "))
+                        .parse()
+                        .unwrap();
+                let (id, b) = egraph.union_instantiations(ast, &new_ast, &subst, rule_name);
+                if b { vec![id] } else { vec![] }
+            }
+            None => {
+                let mut c = vec![egraph.add(lut::LutLang::Program(tt))];
+                c.extend(cut.leaves.iter().rev());
+                let new_lut = egraph.add(lut::LutLang::Lut(c.into()));
+                if egraph.union_trusted(eclass, new_lut, rule_name) {
+                    vec![new_lut]
+                } else {
+                    vec![]
+                }
+            }
+        }
+    }
+}
+
 /// A module dedicated to dynamically finding decompositions of LUTs
 #[cfg(feature = "dyn_decomp")]
 pub mod decomp {
@@ -701,7 +1009,9 @@ pub mod decomp {
         lut::{self, LutLang, from_bitvec, to_bitvec},
     };
     use bitvec::prelude::*;
-    use egg::{Analysis, Applier, Id, Var};
+    use egg::{Analysis, Applier, ENodeOrVar, Id, PatternAst, Var};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
 
     #[derive(Debug, Clone, PartialEq, Eq)]
     /// A data type for folding LUTs
@@ -838,7 +1148,7 @@ pub mod decomp {
                         return true;
                     }
                     let bc = egraph[*b].data.get_cut();
-                    if ac.intersection(bc).count() > 0 {
+                    if ac.intersects(bc) {
                         return true;
                     }
                 }
@@ -917,6 +1227,278 @@ pub mod decomp {
         }
     }
 
+    /// The 2-input functions that depend on both of their inputs and output false when both inputs are false.
+    /// Any network can be made of these gates by pushing inverters into the gates they drive, except for the last.
+    const GATE_PROGRAMS: [u64; 5] = [2, 4, 6, 8, 14];
+
+    /// The number of search steps after which exact synthesis gives up on a function
+    const EXACT_BUDGET: usize = 1 << 18;
+
+    /// A network of 2-LUTs. Signals `0..k` are the inputs (msb first) and signal `k + i` is the output of gate `i`.
+    /// The last gate drives the output of the network.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    pub struct Network {
+        k: usize,
+        gates: Vec<(u64, usize, usize)>,
+    }
+
+    impl Network {
+        /// Returns the number of gates in the network
+        pub fn len(&self) -> usize {
+            self.gates.len()
+        }
+
+        /// Returns true if the network has no gates
+        pub fn is_empty(&self) -> bool {
+            self.gates.is_empty()
+        }
+
+        /// Put the network into `egraph` with `inputs` as its inputs and return its output
+        pub fn construct<A>(&self, egraph: &mut egg::EGraph<LutLang, A>, inputs: &[Id]) -> Id
+        where
+            A: Analysis<LutLang>,
+        {
+            assert_eq!(inputs.len(), self.k);
+            let mut signals = inputs.to_vec();
+            for (p, a, b) in &self.gates {
+                let gate = AbstractNode::Lut(*p, vec![signals[*a], signals[*b]]);
+                signals.push(gate.construct(egraph));
+            }
+            *signals.last().unwrap()
+        }
+
+        /// Returns the network as a pattern over `inputs`
+        pub fn to_pattern(&self, inputs: &[Var]) -> PatternAst<LutLang> {
+            assert_eq!(inputs.len(), self.k);
+            let mut ast = PatternAst::default();
+            let mut signals: Vec<Id> = inputs
+                .iter()
+                .map(|v| ast.add(ENodeOrVar::Var(*v)))
+                .collect();
+            for (p, a, b) in &self.gates {
+                let pid = ast.add(ENodeOrVar::ENode(LutLang::Program(*p)));
+                let gate = LutLang::Lut(vec![pid, signals[*a], signals[*b]].into());
+                signals.push(ast.add(ENodeOrVar::ENode(gate)));
+            }
+            ast
+        }
+    }
+
+    /// Evaluate the 2-LUT `p` on the truth tables of its inputs `a` (msb) and `b`
+    fn eval_gate(p: u64, a: u64, b: u64) -> u64 {
+        let mut r = 0;
+        if p & 1 == 1 {
+            r |= !a & !b;
+        }
+        if p & 2 == 2 {
+            r |= !a & b;
+        }
+        if p & 4 == 4 {
+            r |= a & !b;
+        }
+        if p & 8 == 8 {
+            r |= a & b;
+        }
+        r
+    }
+
+    /// A depth-first search for a network with exactly `size` gates
+    struct ExactSearch {
+        target: u64,
+        mask: u64,
+        size: usize,
+        sims: Vec<u64>,
+        uses: Vec<usize>,
+        gates: Vec<(u64, usize, usize)>,
+        budget: usize,
+    }
+
+    impl ExactSearch {
+        fn search(&mut self) -> bool {
+            if self.budget == 0 {
+                return false;
+            }
+            self.budget -= 1;
+
+            let n = self.sims.len();
+            let placed = self.gates.len();
+            let dangling = self.uses.iter().filter(|u| **u == 0).count();
+            if placed == self.size {
+                return dangling == 1 && self.sims[n - 1] == self.target;
+            }
+            // Every signal must be used, and a gate can only leave one less unused signal
+            if dangling > self.size - placed + 1 {
+                return false;
+            }
+
+            let last = placed + 1 == self.size;
+            for b in 1..n {
+                for a in 0..b {
+                    // Independent gates are placed in order
+                    if let Some((_, pa, pb)) = self.gates.last()
+                        && b != n - 1
+                        && (b, a) < (*pb, *pa)
+                    {
+                        continue;
+                    }
+                    for p in GATE_PROGRAMS {
+                        let tt = eval_gate(p, self.sims[a], self.sims[b]) & self.mask;
+                        let inverted = tt ^ self.mask;
+                        if last && tt != self.target && inverted != self.target {
+                            continue;
+                        }
+                        if !last
+                            && (tt == 0
+                                || tt == self.target
+                                || inverted == self.target
+                                || self.sims.contains(&tt))
+                        {
+                            continue;
+                        }
+                        // Only the last gate may be inverted
+                        let (p, tt) = if last && tt != self.target {
+                            (p ^ 15, inverted)
+                        } else {
+                            (p, tt)
+                        };
+                        self.sims.push(tt);
+                        self.uses.push(0);
+                        self.uses[a] += 1;
+                        self.uses[b] += 1;
+                        self.gates.push((p, a, b));
+                        if self.search() {
+                            return true;
+                        }
+                        self.gates.pop();
+                        self.uses[a] -= 1;
+                        self.uses[b] -= 1;
+                        self.uses.pop();
+                        self.sims.pop();
+                    }
+                }
+            }
+            false
+        }
+    }
+
+    /// Find a network with the fewest 2-LUTs that implements the `k`-input program `p`, using at most `max_gates` gates.
+    /// Returns `None` if there is no such network or if the search gave up before proving one minimum.
+    pub fn exact_synthesis(p: u64, k: usize, max_gates: usize) -> Option<Network> {
+        assert!(k >= 2 && k <= lut::LutLang::MAX_LUT_SIZE);
+        let mask = if k == 6 {
+            u64::MAX
+        } else {
+            (1 << (1 << k)) - 1
+        };
+        // Every input must be in the support of the function
+        for v in &lut::VAR_PATTERNS[..k] {
+            let shift = v.trailing_zeros();
+            if ((p & v) >> shift) & mask == p & !v & mask {
+                return None;
+            }
+        }
+        let mut budget = EXACT_BUDGET;
+        for size in (k - 1)..=max_gates {
+            let mut search = ExactSearch {
+                target: p & mask,
+                mask,
+                size,
+                sims: (0..k)
+                    .map(|i| lut::VAR_PATTERNS[k - 1 - i] & mask)
+                    .collect(),
+                uses: vec![0; k],
+                gates: Vec::new(),
+                budget,
+            };
+            if search.search() {
+                return Some(Network {
+                    k,
+                    gates: search.gates,
+                });
+            }
+            if search.budget == 0 {
+                return None;
+            }
+            budget = search.budget;
+        }
+        None
+    }
+
+    /// A rewrite applier that replaces a small LUT with a minimum network of 2-LUTs.
+    /// Networks are cached by program, as the search is expensive.
+    #[derive(Debug, Clone)]
+    pub struct ExactSynthesis {
+        /// The program
+        program: Var,
+        /// The inputs of the LUT
+        vars: Vec<Var>,
+        /// The largest network to search for
+        max_gates: usize,
+        /// The networks found so far
+        cache: Arc<Mutex<HashMap<u64, Option<Network>>>>,
+    }
+
+    impl ExactSynthesis {
+        /// Create an applier that decomposes a LUT over `vars` into at most `max_gates` 2-LUTs.
+        pub fn new(program: Var, vars: Vec<Var>, max_gates: usize) -> Self {
+            Self {
+                program,
+                vars,
+                max_gates,
+                cache: Arc::new(Mutex::new(HashMap::new())),
+            }
+        }
+    }
+
+    impl Applier<LutLang, LutAnalysis> for ExactSynthesis {
+        fn apply_one(
+            &self,
+            egraph: &mut egg::EGraph<LutLang, LutAnalysis>,
+            eclass: egg::Id,
+            subst: &egg::Subst,
+            searcher_ast: Option<&egg::PatternAst<LutLang>>,
+            rule_name: egg::Symbol,
+        ) -> Vec<egg::Id> {
+            let operands = self
+                .vars
+                .iter()
+                .map(|v| subst[*v])
+                .collect::<Vec<egg::Id>>();
+            let k = operands.len();
+            if k <= 2 || k > 5 || operands.contains(&eclass) {
+                return vec![];
+            }
+            let program = egraph[subst[self.program]]
+                .data
+                .get_program()
+                .expect("Expected program");
+
+            let network = self
+                .cache
+                .lock()
+                .unwrap()
+                .entry(program)
+                .or_insert_with(|| exact_synthesis(program, k, self.max_gates))
+                .clone();
+            let Some(network) = network else {
+                return vec![];
+            };
+
+            if let Some(ast) = searcher_ast {
+                let new_ast = network.to_pattern(&self.vars);
+                let (id, b) = egraph.union_instantiations(ast, &new_ast, subst, rule_name);
+                return if b { vec![id] } else { vec![] };
+            }
+
+            let output = network.construct(egraph, &operands);
+            if egraph.union_trusted(eclass, output, rule_name) {
+                vec![output]
+            } else {
+                vec![]
+            }
+        }
+    }
+
     #[test]
     fn test_decomp() {
         let expr: egg::RecExpr<lut::LutLang> = "(LUT 61642 s1 s0 c d)".parse().unwrap();
@@ -935,6 +1517,351 @@ pub mod decomp {
         let ans = req.synth::<SynthReport>().unwrap().get_expr().to_string();
         assert_eq!(ans, "(LUT 202 s1 s0 (LUT 202 s0 c d))");
     }
+
+    #[test]
+    fn test_exact_synthesis() {
+        let sizes = [(202, 3), (232, 3), (0x6996, 4), (0x8000, 4), (0xf0ca, 4)]
+            .map(|(p, k)| exact_synthesis(p, k, 8).map(|n| n.len()));
+        assert_eq!(sizes, [Some(3), Some(4), Some(3), Some(3), Some(5)]);
+
+        // Inputs outside the support of the function can't be synthesized
+        assert!(exact_synthesis(0xf0, 3, 8).is_none());
+
+        for p in 0..256 {
+            if let Some(network) = exact_synthesis(p, 3, 4) {
+                let mut sims: Vec<u64> = (0..3).map(|i| lut::VAR_PATTERNS[2 - i] & 0xff).collect();
+                for (g, a, b) in &network.gates {
+                    sims.push(eval_gate(*g, sims[*a], sims[*b]) & 0xff);
+                }
+                assert_eq!(*sims.last().unwrap(), p);
+            }
+        }
+
+        // The decomposition can be explained
+        let expr: egg::RecExpr<lut::LutLang> = "(LUT 202 s a b)".parse().unwrap();
+        let mut runner = egg::Runner::<lut::LutLang, LutAnalysis>::default()
+            .with_explanations_enabled()
+            .with_expr(&expr)
+            .with_iter_limit(1)
+            .run(&super::exact_decompositions(3));
+        let network = exact_synthesis(202, 3, 3).unwrap();
+        let vars: Vec<Var> = ["?s", "?a", "?b"].map(|v| v.parse().unwrap()).to_vec();
+        let decomp = network.to_pattern(&vars).to_string();
+        let decomp: egg::RecExpr<lut::LutLang> = decomp
+            .replace("?s", "s")
+            .replace("?a", "a")
+            .replace("?b", "b")
+            .parse()
+            .unwrap();
+        let root = runner.egraph.find(runner.roots[0]);
+        assert_eq!(runner.egraph.lookup_expr(&decomp), Some(root));
+        let mut explanation = runner.explain_equivalence(&expr, &decomp);
+        assert!(explanation.get_flat_strings().len() > 1);
+    }
+}
+
+/// An argument passed to a dynamic applier invoked from a rule file.
+/// For example, `@fuse_cut(?pp, [?p0 ?p1], ?qp, [?q0])` has four arguments.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ApplierArg {
+    /// A pattern variable, like `?a`
+    Var(Var),
+    /// A list of pattern variables, like `[?a ?b ?c]`
+    List(Vec<Var>),
+    /// An integer literal
+    Int(u64),
+    /// A boolean literal
+    Bool(bool),
+}
+
+impl ApplierArg {
+    /// Parse a comma-separated list of arguments
+    pub fn parse_list(s: &str) -> Result<Vec<Self>, String> {
+        let s = s.trim();
+        if s.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut args = Vec::new();
+        let mut depth = 0;
+        let mut start = 0;
+        for (i, c) in s.char_indices() {
+            match c {
+                '[' => depth += 1,
+                ']' => depth -= 1,
+                ',' if depth == 0 => {
+                    args.push(s[start..i].parse()?);
+                    start = i + 1;
+                }
+                _ => (),
+            }
+        }
+        args.push(s[start..].parse()?);
+        Ok(args)
+    }
+
+    /// Returns all the pattern variables referenced by the argument
+    pub fn vars(&self) -> Vec<Var> {
+        match self {
+            Self::Var(v) => vec![*v],
+            Self::List(l) => l.clone(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Get the argument as a single pattern variable
+    pub fn as_var(&self) -> Result<Var, String> {
+        match self {
+            Self::Var(v) => Ok(*v),
+            _ => Err(format!("Expected a variable, found {self:?}")),
+        }
+    }
+
+    /// Get the argument as a list of pattern variables
+    pub fn as_list(&self) -> Result<Vec<Var>, String> {
+        match self {
+            Self::List(l) => Ok(l.clone()),
+            _ => Err(format!("Expected a list of variables, found {self:?}")),
+        }
+    }
+
+    /// Get the argument as an integer
+    pub fn as_int(&self) -> Result<u64, String> {
+        match self {
+            Self::Int(i) => Ok(*i),
+            _ => Err(format!("Expected an integer, found {self:?}")),
+        }
+    }
+
+    /// Get the argument as a boolean
+    pub fn as_bool(&self) -> Result<bool, String> {
+        match self {
+            Self::Bool(b) => Ok(*b),
+            _ => Err(format!("Expected a boolean, found {self:?}")),
+        }
+    }
+}
+
+impl std::str::FromStr for ApplierArg {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(list) = s.strip_prefix('[') {
+            let list = list
+                .strip_suffix(']')
+                .ok_or_else(|| format!("Unterminated list: {s}"))?;
+            let vars = list
+                .split(|c: char| c.is_whitespace() || c == ',')
+                .filter(|v| !v.is_empty())
+                .map(|v| v.parse::<Var>().map_err(|e| format!("{e}")))
+                .collect::<Result<Vec<_>, _>>()?;
+            return Ok(Self::List(vars));
+        }
+
+        match s {
+            "true" => Ok(Self::Bool(true)),
+            "false" => Ok(Self::Bool(false)),
+            _ if s.starts_with('?') => s.parse::<Var>().map(Self::Var).map_err(|e| format!("{e}")),
+            _ => s
+                .parse::<u64>()
+                .map(Self::Int)
+                .map_err(|_| format!("Invalid applier argument: {s}")),
+        }
+    }
+}
+
+/// A function that builds a rewrite rule named `name` from a searcher pattern and the arguments to a dynamic applier.
+pub type ApplierCtor<L, A> = fn(Symbol, Pattern<L>, &[ApplierArg]) -> Result<Rewrite<L, A>, String>;
+
+/// Builds a rule for `@fuse_cut(?root_p, [?root ...], ?rhs_p, [?rhs ...])`
+fn fuse_cut_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [root_p, root, rhs_p, rhs] => {
+            let root = root.as_list()?;
+            let rhs = rhs.as_list()?;
+            if root.len() >= lut::LutLang::MAX_LUT_SIZE
+                || rhs.is_empty()
+                || rhs.len() > lut::LutLang::MAX_LUT_SIZE
+            {
+                return Err(format!("@fuse_cut has an invalid cut size in {name}"));
+            }
+            Rewrite::new(
+                name,
+                lhs,
+                FuseCut::new(root_p.as_var()?, root, rhs_p.as_var()?, rhs),
+            )
+        }
+        _ => Err(format!("@fuse_cut expects 4 arguments, got {}", args.len())),
+    }
+}
+
+/// Builds a rule for `@shannon_condense(?sel, ?p, ?q, [?a ...])`
+fn shannon_condense_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [sel, p, q, vars] => {
+            let vars = vars.as_list()?;
+            if vars.len() >= lut::LutLang::MAX_LUT_SIZE {
+                return Err(format!("@shannon_condense has too many inputs in {name}"));
+            }
+            Rewrite::new(
+                name,
+                lhs,
+                ShannonCondense::new(sel.as_var()?, p.as_var()?, q.as_var()?, vars),
+            )
+        }
+        _ => Err(format!(
+            "@shannon_condense expects 4 arguments, got {}",
+            args.len()
+        )),
+    }
+}
+
+/// Builds a rule for `@permute_input(pos, ?p, [?a ...])`
+fn permute_input_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [pos, program, vars] => {
+            let pos = pos.as_int()? as usize;
+            let vars = vars.as_list()?;
+            if pos == 0 || pos >= vars.len() {
+                return Err(format!("@permute_input position is out of range in {name}"));
+            }
+            Rewrite::new(name, lhs, PermuteInput::new(pos, program.as_var()?, vars))
+        }
+        _ => Err(format!(
+            "@permute_input expects 3 arguments, got {}",
+            args.len()
+        )),
+    }
+}
+
+/// Builds a rule for `@combine_alike_inputs(?p, [?a ... ?b ?b])`
+fn combine_alike_inputs_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [program, vars] => {
+            let vars = vars.as_list()?;
+            let n = vars.len();
+            if n < 2 || vars[n - 1] != vars[n - 2] {
+                return Err(format!(
+                    "@combine_alike_inputs needs its last two inputs to be the same in {name}"
+                ));
+            }
+            Rewrite::new(name, lhs, CombineAlikeInputs::new(program.as_var()?, vars))
+        }
+        _ => Err(format!(
+            "@combine_alike_inputs expects 2 arguments, got {}",
+            args.len()
+        )),
+    }
+}
+
+/// Builds a rule for `@collapse_reconvergence(?p, [?a ...], k, depth)`
+fn collapse_reconvergence_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [program, vars, k, depth] => {
+            let k = k.as_int()? as usize;
+            if k > lut::LutLang::MAX_LUT_SIZE {
+                return Err(format!(
+                    "@collapse_reconvergence has an invalid cut size in {name}"
+                ));
+            }
+            Rewrite::new(
+                name,
+                lhs,
+                CollapseReconvergence::new(
+                    program.as_var()?,
+                    vars.as_list()?,
+                    k,
+                    depth.as_int()? as usize,
+                ),
+            )
+        }
+        _ => Err(format!(
+            "@collapse_reconvergence expects 4 arguments, got {}",
+            args.len()
+        )),
+    }
+}
+
+/// Builds a rule for `@shannon_expand(?p, [?a ...], any_order)`
+#[cfg(feature = "dyn_decomp")]
+fn shannon_expand_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [program, vars, any_order] => Rewrite::new(
+            name,
+            lhs,
+            decomp::ShannonExpand::new(program.as_var()?, vars.as_list()?, any_order.as_bool()?),
+        ),
+        _ => Err(format!(
+            "@shannon_expand expects 3 arguments, got {}",
+            args.len()
+        )),
+    }
+}
+
+/// Builds a rule for `@exact_synthesis(?p, [?a ...], max_gates)`
+#[cfg(feature = "dyn_decomp")]
+fn exact_synthesis_rule(
+    name: Symbol,
+    lhs: Pattern<lut::LutLang>,
+    args: &[ApplierArg],
+) -> Result<Rewrite<lut::LutLang, LutAnalysis>, String> {
+    match args {
+        [program, vars, max_gates] => {
+            let vars = vars.as_list()?;
+            if vars.len() < 3 || vars.len() > 5 {
+                return Err(format!("@exact_synthesis needs 3 to 5 inputs in {name}"));
+            }
+            Rewrite::new(
+                name,
+                lhs,
+                decomp::ExactSynthesis::new(program.as_var()?, vars, max_gates.as_int()? as usize),
+            )
+        }
+        _ => Err(format!(
+            "@exact_synthesis expects 3 arguments, got {}",
+            args.len()
+        )),
+    }
+}
+
+/// Returns the dynamic LUT appliers that can be invoked from rule files, keyed by name.
+pub fn lut_appliers() -> Vec<(&'static str, ApplierCtor<lut::LutLang, LutAnalysis>)> {
+    vec![
+        ("fuse_cut", fuse_cut_rule),
+        ("shannon_condense", shannon_condense_rule),
+        ("permute_input", permute_input_rule),
+        ("combine_alike_inputs", combine_alike_inputs_rule),
+        ("collapse_reconvergence", collapse_reconvergence_rule),
+        #[cfg(feature = "dyn_decomp")]
+        ("shannon_expand", shannon_expand_rule),
+        #[cfg(feature = "dyn_decomp")]
+        ("exact_synthesis", exact_synthesis_rule),
+    ]
 }
 
 /// Load and manage groups of rewrite rules
@@ -945,8 +1872,10 @@ where
     A: Analysis<L> + Clone,
 {
     db: HashMap<String, Rewrite<L, A>>,
+    order: Vec<String>,
     active: HashMap<String, Rewrite<L, A>>,
     categories: HashMap<String, Vec<String>>,
+    appliers: HashMap<String, ApplierCtor<L, A>>,
 }
 
 impl<L, A> Default for RewriteManager<L, A>
@@ -965,7 +1894,12 @@ where
     A: Analysis<L> + Clone + 'static,
 {
     fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
-        let strings: Vec<String> = self.active.values().map(|r| format!("{r:?}")).collect();
+        let strings: Vec<String> = self
+            .order
+            .iter()
+            .filter_map(|n| self.active.get(n))
+            .map(|r| format!("{r:?}"))
+            .collect();
         strings.hash(state);
     }
 }
@@ -979,11 +1913,19 @@ where
     pub fn new() -> Self {
         Self {
             db: HashMap::new(),
+            order: Vec::new(),
             active: HashMap::new(),
             categories: HashMap::new(),
+            appliers: HashMap::new(),
         }
     }
 
+    /// Register a dynamic applier that rule files can invoke as `@name(args...)`.
+    /// Returns false if an applier with the same name was already registered.
+    pub fn register_applier(&mut self, name: &str, ctor: ApplierCtor<L, A>) -> bool {
+        self.appliers.insert(name.to_string(), ctor).is_none()
+    }
+
     /// Insert a category of rewrites from an iterator.
     /// Returns an error if any rewrite name already exists in the database (by name)
     pub fn insert_category<I>(&mut self, category: String, rewrites: I) -> Result<(), Rewrite<L, A>>
@@ -997,6 +1939,7 @@ where
                 return Err(rw);
             }
             self.db.insert(name.clone(), rw);
+            self.order.push(name.clone());
             category.push(name);
         }
         Ok(())
@@ -1014,6 +1957,7 @@ where
             return Err(rewrite);
         }
         self.db.insert(name.clone(), rewrite);
+        self.order.push(name.clone());
         let category = self.categories.entry(category).or_default();
         category.push(name);
         Ok(())
@@ -1026,7 +1970,8 @@ where
         if self.db.contains_key(&name) {
             return Err(rewrite);
         }
-        self.db.insert(name, rewrite);
+        self.db.insert(name.clone(), rewrite);
+        self.order.push(name);
         Ok(())
     }
 
@@ -1082,9 +2027,41 @@ where
         self.active.remove(name).is_some()
     }
 
-    /// Returns the active rewrite rules
-    pub fn active_rules(self) -> Vec<Rewrite<L, A>> {
-        self.active.into_values().collect()
+    /// Enables everything in `enable` and disables everything in `disable`.
+    /// Each entry may name either a category or an individual rule.
+    /// Categories are applied first, so individual rules can override their category.
+    pub fn select(&mut self, enable: &[String], disable: &[String]) -> Result<(), String> {
+        for name in enable.iter().chain(disable) {
+            if !self.categories.contains_key(name) && !self.db.contains_key(name) {
+                return Err(format!("No rule or category named {name}"));
+            }
+        }
+
+        for name in enable {
+            self.enable_category(name);
+        }
+        for name in disable {
+            self.disable_category(name);
+        }
+        for name in enable {
+            if !self.categories.contains_key(name) {
+                self.enable_rule(name);
+            }
+        }
+        for name in disable {
+            if !self.categories.contains_key(name) {
+                self.disable_rule(name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the active rewrite rules in the order they were inserted
+    pub fn active_rules(mut self) -> Vec<Rewrite<L, A>> {
+        self.order
+            .iter()
+            .filter_map(|n| self.active.remove(n))
+            .collect()
     }
 
     /// Returns an iterator to all the categories of rewrites
@@ -1127,10 +2104,17 @@ where
         let lhsp: Pattern<L> = lhs
             .parse()
             .map_err(|e: egg::RecExprParseError<_>| format!("lhs: {:?}", e))?;
-        let rhsp: Pattern<L> = rhs
-            .parse()
-            .map_err(|e: egg::RecExprParseError<_>| format!("rhs: {:?}", e))?;
-        let rw: Rewrite<L, A> = Rewrite::new(Symbol::new(name), lhsp, rhsp)?;
+        let rw: Rewrite<L, A> = if let Some(call) = rhs.strip_prefix('@') {
+            if bidirectional {
+                return Err(format!("Dynamic applier cannot be bidirectional: {name}"));
+            }
+            self.construct_dynamic_rule(name, lhsp, call)?
+        } else {
+            let rhsp: Pattern<L> = rhs
+                .parse()
+                .map_err(|e: egg::RecExprParseError<_>| format!("rhs: {:?}", e))?;
+            Rewrite::new(Symbol::new(name), lhsp, rhsp)?
+        };
         if let Some(cat) = category.clone() {
             self.insert_into_category(cat, rw.clone())
                 .map_err(|r| format!("Rule already exists: {}", r.name))?;
@@ -1146,15 +2130,45 @@ where
         Ok(rw)
     }
 
+    /// Constructs a rule whose right-hand side is a call to a registered applier, like `fuse_cut(?p, [?a])`
+    fn construct_dynamic_rule(
+        &self,
+        name: &str,
+        lhs: Pattern<L>,
+        call: &str,
+    ) -> Result<Rewrite<L, A>, String> {
+        let (applier, args) = call
+            .split_once('(')
+            .and_then(|(a, r)| Some((a.trim(), r.trim().strip_suffix(')')?)))
+            .ok_or_else(|| format!("Applier call misformatted: @{call}"))?;
+        let ctor = self
+            .appliers
+            .get(applier)
+            .ok_or_else(|| format!("Unknown applier: @{applier}"))?;
+        let args = ApplierArg::parse_list(args)?;
+
+        let bound = lhs.vars();
+        for v in args.iter().flat_map(|a| a.vars()) {
+            if !bound.contains(&v) {
+                return Err(format!("Rewrite {name} refers to unbound var {v}"));
+            }
+        }
+
+        ctor(Symbol::new(name), lhs, &args)
+    }
+
     /// Parse rewrite rules from a reader, line by line.
     /// Lines starting with `#` are comments and ignored.
     /// Category lines end with a colon `:` and set the category for subsequent rules.
     /// Rules are formatted as `name: lhs => rhs` or `name: lhs <=> rhs` for bidirectional rules.
+    /// The rhs may also call a registered applier as `name: lhs => @applier(args...)`.
     /// Here is an example file:
     /// ```text
     /// # Algebraic rules
     /// algebraic:
     ///     commutative-and: (AND2 ?a ?b) => (AND2 ?b ?a)
+    /// fusion:
+    ///     lut2-1-fuse: (LUT ?pp ?p0 (LUT ?qp ?q0)) => @fuse_cut(?pp, [?p0], ?qp, [?q0])
     /// ```
     pub fn parse_rules(&mut self, file: impl Read) -> Result<(), String> {
         let mut category: Option<String> = None;
@@ -1200,6 +2214,60 @@ where
     }
 }
 
+impl RewriteManager<lut::LutLang, LutAnalysis> {
+    /// Create a new empty [RewriteManager] with all of [lut_appliers] registered
+    pub fn with_lut_appliers() -> Self {
+        let mut manager = Self::new();
+        for (name, ctor) in lut_appliers() {
+            manager.register_applier(name, ctor);
+        }
+        manager
+    }
+
+    /// Create a [RewriteManager] holding the built-in LUT rules, grouped into the categories
+    /// `structural`, `constant`, `permute`, `fusion`, `reconvergence`, `decomp`, `exact` and `retime`. No rules are active.
+    /// `bidirectional` determines if gates are inserted for 2-LUTs.
+    /// `any_order` lets dynamic decompositions choose any variable order.
+    /// Reconvergent paths are only collapsed into LUTs with at most `k` inputs.
+    pub fn with_lut_rules(bidirectional: bool, any_order: bool, k: usize) -> Self {
+        let mut manager = Self::with_lut_appliers();
+
+        let mut constant = constant_luts();
+        constant.append(&mut constant_inputs());
+
+        let mut fusion = condense_cofactors();
+        fusion.append(&mut general_cut_fusion());
+
+        // Keep the same rule order as [all_static_rules]
+        let mut categories = vec![
+            ("structural", struct_lut_map(bidirectional)),
+            ("constant", constant),
+            ("structural", vec![double_complement()]),
+            ("constant", redundant_inputs()),
+            ("permute", permute_groups()),
+            ("fusion", fusion),
+            ("structural", known_decompositions()),
+            ("reconvergence", reconvergent_fusion(k, 3)),
+        ];
+
+        #[cfg(feature = "dyn_decomp")]
+        categories.push(("decomp", dyn_decompositions(any_order)));
+        #[cfg(feature = "dyn_decomp")]
+        categories.push(("exact", exact_decompositions(8)));
+        #[cfg(not(feature = "dyn_decomp"))]
+        let _ = any_order;
+
+        categories.push(("retime", register_retiming()));
+
+        for (name, rules) in categories {
+            manager
+                .insert_category(name.to_string(), rules)
+                .unwrap_or_else(|r| panic!("Duplicate built-in rule {}", r.name));
+        }
+        manager
+    }
+}
+
 #[test]
 fn test_parse_rules() {
     let mut manager = RewriteManager::<lut::LutLang, LutAnalysis>::new();
@@ -1261,3 +2329,144 @@ fn test_parse_rules() {
             .is_err()
     );
 }
+
+#[test]
+fn test_parse_dynamic_rules() {
+    let mut manager = RewriteManager::<lut::LutLang, LutAnalysis>::with_lut_appliers();
+
+    let rules = "fusion:
+        lut2-2-fuse: (LUT ?pp ?p0 (LUT ?qp ?q0 ?q1)) => @fuse_cut(?pp, [?p0], ?qp, [?q0 ?q1])
+        lut2-permute: (LUT ?p ?a ?b) => @permute_input(1, ?p, [?a ?b])";
+    assert!(manager.parse_rules(rules.as_bytes()).is_ok());
+    assert_eq!(manager.enable_category("fusion"), Some(2));
+
+    // Unknown applier
+    assert!(
+        manager
+            .parse_rules("a: (LUT ?p ?a) => @foo(?p)".as_bytes())
+            .is_err()
+    );
+
+    // Unbound var
+    assert!(
+        manager
+            .parse_rules("b: (LUT ?p ?a ?b) => @permute_input(1, ?p, [?a ?c])".as_bytes())
+            .is_err()
+    );
+
+    // Can't be bidirectional
+    assert!(
+        manager
+            .parse_rules("c: (LUT ?p ?a ?b) <=> @permute_input(1, ?p, [?a ?b])".as_bytes())
+            .is_err()
+    );
+
+    // Wrong argument types
+    assert!(
+        manager
+            .parse_rules("d: (LUT ?p ?a ?b) => @permute_input(?p, 1, [?a ?b])".as_bytes())
+            .is_err()
+    );
+
+    let expr: egg::RecExpr<lut::LutLang> = "(LUT 6 b (LUT 6 c d))".parse().unwrap();
+    use crate::driver::{SynthReport, SynthRequest};
+    let mut req = SynthRequest::default()
+        .with_expr(expr)
+        .with_rules(manager.active_rules())
+        .with_k(3)
+        .with_asserts()
+        .without_progress_bar()
+        .with_joint_limits(20, 20_000, 30);
+
+    let ans = req.synth::<SynthReport>().unwrap();
+    assert_eq!(lut::LutExprInfo::new(ans.get_expr()).get_lut_count(), 1);
+}
+
+#[test]
+#[cfg(feature = "dyn_decomp")]
+fn test_fpga_rule_file() {
+    let mut manager = RewriteManager::<lut::LutLang, LutAnalysis>::with_lut_appliers();
+    let file = std::fs::File::open("rules/fpga.lutlang").unwrap();
+    assert!(manager.parse_rules(file).is_ok());
+    let categories = manager.categories().cloned().collect::<Vec<_>>();
+    for cat in categories {
+        manager.enable_category(&cat);
+    }
+
+    let built_in = all_static_rules(false).len()
+        + dyn_decompositions(true).len()
+        + exact_decompositions(8).len()
+        + reconvergent_fusion(6, 3).len()
+        + register_retiming::<LutAnalysis>().len();
+    assert_eq!(manager.num_active(), built_in);
+
+    let built_in = RewriteManager::<lut::LutLang, LutAnalysis>::with_lut_rules(false, true, 6);
+    for (cat, rules) in &built_in.categories {
+        let mut expected = rules.clone();
+        let mut parsed = manager.categories[cat].clone();
+        expected.sort();
+        parsed.sort();
+        assert_eq!(parsed, expected, "category {cat}");
+    }
+}
+
+#[test]
+fn test_select_rules() {
+    let mut manager = RewriteManager::<lut::LutLang, LutAnalysis>::with_lut_rules(false, false, 6);
+    assert_eq!(manager.num_active(), 0);
+
+    let enable = vec!["structural".to_string(), "constant".to_string()];
+    let disable = vec!["double-complement".to_string()];
+    assert!(manager.select(&enable, &disable).is_ok());
+    let active = manager.num_active();
+    assert_eq!(
+        active,
+        struct_lut_map::<LutAnalysis>(false).len()
+            + known_decompositions().len()
+            + constant_luts::<LutAnalysis>().len()
+            + constant_inputs::<LutAnalysis>().len()
+            + redundant_inputs().len()
+    );
+
+    assert!(manager.select(&["no-such-rule".to_string()], &[]).is_err());
+    assert_eq!(manager.num_active(), active);
+
+    // Individual rules override their category
+    let enable = vec!["double-complement".to_string()];
+    let disable = vec!["structural".to_string()];
+    assert!(manager.select(&enable, &disable).is_ok());
+    assert!(manager.active.contains_key("double-complement"));
+    assert!(!manager.active.contains_key("nor2-conversion"));
+    assert!(manager.select(&disable, &[]).is_ok());
+
+    let names = manager
+        .active_rules()
+        .iter()
+        .map(|r| r.name.to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(names.len(), active + 1);
+    assert_eq!(names[0], "nor2-conversion");
+}
+
+#[test]
+fn test_collapse_reconvergence() {
+    let analysis = LutAnalysis::default();
+    // x enters the cone at two different depths
+    let expr: egg::RecExpr<lut::LutLang> = "(LUT 6 x (LUT 8 (LUT 14 x y) z))".parse().unwrap();
+    let run = |k: usize| {
+        egg::Runner::<lut::LutLang, LutAnalysis>::new(analysis.clone())
+            .with_expr(&expr)
+            .with_iter_limit(1)
+            .run(&reconvergent_fusion(k, 3))
+    };
+
+    let runner = run(3);
+    let root = runner.egraph.find(runner.roots[0]);
+    let collapsed: egg::RecExpr<lut::LutLang> = "(LUT 74 z y x)".parse().unwrap();
+    assert_eq!(runner.egraph.lookup_expr(&collapsed), Some(root));
+
+    // The union cut does not fit in a 2-LUT
+    let runner = run(2);
+    let root = runner.egraph.find(runner.roots[0]);
+    assert_eq!(runner.egraph[root].nodes.len(), 1);
+}
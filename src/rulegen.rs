@@ -0,0 +1,484 @@
+/*!
+
+  Synthesizes [CellLang] rewrite rules from the truth tables of a cell library.
+  Small AND/OR/INV terms are enumerated bottom-up and grouped by truth table.
+  Terms equal to a cell become mapping rules, and terms equal to each other become
+  algebraic rules, keeping only the ones the other rules can't already derive.
+
+*/
+use super::asic::{CellAnalysis, CellLang};
+use super::driver::CircuitLang;
+use super::lut::VAR_PATTERNS;
+use super::rewrite::RewriteManager;
+use egg::{Language, Pattern, RecExpr, Rewrite, Runner};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+
+/// The names of the variables of synthesized rules
+const VAR_NAMES: [&str; 6] = ["a", "b", "c", "d", "e", "f"];
+
+/// A standard cell and the logic function it implements
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CellSpec {
+    name: String,
+    inputs: usize,
+    program: u64,
+}
+
+impl CellSpec {
+    /// Create a cell `name` with `inputs` inputs. Its `program` is a truth table with the first input as the msb, like a LUT.
+    pub fn new(name: &str, inputs: usize, program: u64) -> Result<Self, String> {
+        if inputs == 0 || inputs > VAR_NAMES.len() {
+            return Err(format!(
+                "Cell {name} must have between 1 and {} inputs",
+                VAR_NAMES.len()
+            ));
+        }
+        if inputs < 6 && program >> (1 << inputs) != 0 {
+            return Err(format!(
+                "Program {program} of cell {name} is too wide for {inputs} inputs"
+            ));
+        }
+        let cell = Self {
+            name: name.to_string(),
+            inputs,
+            program,
+        };
+        CellLang::Cell(cell.name.as_str().into(), vec![]).verify()?;
+        Ok(cell)
+    }
+
+    /// Get the name of the cell
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the number of inputs of the cell
+    pub fn get_num_inputs(&self) -> usize {
+        self.inputs
+    }
+
+    /// Parse a cell library from a reader, one cell per line formatted as `NAME inputs program`.
+    /// The program may be decimal or hex with a `0x` prefix. Lines starting with `#` or `//` are comments.
+    /// ```text
+    /// # AOI21: !(A | (B1 & B2))
+    /// AOI21_X1 3 0x07
+    /// ```
+    pub fn parse_library(file: impl Read) -> Result<Vec<Self>, String> {
+        let mut cells = Vec::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Library reader: {:?}", e))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let [name, inputs, program] = fields[..] else {
+                return Err(format!("Cell misformatted: {line}"));
+            };
+            let inputs = inputs
+                .parse::<usize>()
+                .map_err(|e| format!("Cell {name} inputs: {e}"))?;
+            let program = match program.strip_prefix("0x") {
+                Some(hex) => u64::from_str_radix(hex, 16),
+                None => program.parse::<u64>(),
+            }
+            .map_err(|e| format!("Cell {name} program: {e}"))?;
+            cells.push(Self::new(name, inputs, program)?);
+        }
+        Ok(cells)
+    }
+
+    /// The truth table of the cell with input `i` on [VAR_PATTERNS]`[i]`
+    fn truth_table(&self) -> u64 {
+        let mut tt = 0;
+        for m in 0..64 {
+            let index = (0..self.inputs).fold(0, |acc, i| (acc << 1) | ((m >> i) & 1));
+            tt |= ((self.program >> index) & 1) << m;
+        }
+        tt
+    }
+}
+
+/// A rewrite rule found by the [RuleSynthesizer]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SynthRule {
+    name: String,
+    lhs: String,
+    rhs: String,
+    bidirectional: bool,
+}
+
+impl SynthRule {
+    /// Get the name of the rule
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// The rewrites of the rule, with the reverse rewrite last
+    fn rewrites(&self) -> Result<Vec<Rewrite<CellLang, CellAnalysis>>, String> {
+        let lhs: Pattern<CellLang> = self.lhs.parse().map_err(|e| format!("{e:?}"))?;
+        let rhs: Pattern<CellLang> = self.rhs.parse().map_err(|e| format!("{e:?}"))?;
+        let mut rws = vec![Rewrite::new(self.name.as_str(), lhs.clone(), rhs.clone())?];
+        if self.bidirectional {
+            rws.push(Rewrite::new(format!("{}-rev", self.name), rhs, lhs)?);
+        }
+        Ok(rws)
+    }
+}
+
+impl fmt::Display for SynthRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let arrow = if self.bidirectional { "<=>" } else { "=>" };
+        write!(f, "{}: {} {} {}", self.name, self.lhs, arrow, self.rhs)
+    }
+}
+
+/// An AND/OR/INV term whose operands are other enumerated terms
+#[derive(Debug, Clone, Copy)]
+enum Term {
+    Var(usize),
+    Const(bool),
+    Inv(usize),
+    And(usize, usize),
+    Or(usize, usize),
+}
+
+/// Terms enumerated bottom-up, keeping the smallest term of every truth table
+struct Enumeration {
+    /// The smallest term of each truth table found, and that truth table
+    terms: Vec<(Term, u64)>,
+}
+
+impl Enumeration {
+    /// Enumerate terms over `vars` variables with up to `max_size` operators.
+    /// `commuted` also enumerates operands in both orders, and `on_equal` is called with
+    /// every term that has the same truth table as an already enumerated one.
+    fn new(
+        vars: usize,
+        max_size: usize,
+        constants: bool,
+        commuted: bool,
+        mut on_equal: impl FnMut(&[(Term, u64)], Term, usize),
+    ) -> Self {
+        let mut terms: Vec<(Term, u64)> = Vec::new();
+        let mut index: HashMap<u64, usize> = HashMap::new();
+        let mut levels: Vec<Vec<usize>> = vec![Vec::new(); max_size + 1];
+
+        let leaves = (0..vars).map(|v| (Term::Var(v), VAR_PATTERNS[v])).chain(
+            [false, true]
+                .into_iter()
+                .filter(|_| constants)
+                .map(|b| (Term::Const(b), if b { u64::MAX } else { 0 })),
+        );
+        for (t, tt) in leaves {
+            index.insert(tt, terms.len());
+            levels[0].push(terms.len());
+            terms.push((t, tt));
+        }
+
+        for s in 1..=max_size {
+            let mut candidates: Vec<(Term, u64)> = levels[s - 1]
+                .iter()
+                .map(|&x| (Term::Inv(x), !terms[x].1))
+                .collect();
+            for i in 0..s {
+                let j = s - 1 - i;
+                if i > j && !commuted {
+                    break;
+                }
+                for (xi, &x) in levels[i].iter().enumerate() {
+                    let ys = if i == j && !commuted {
+                        &levels[j][xi + 1..]
+                    } else {
+                        &levels[j][..]
+                    };
+                    for &y in ys {
+                        let (a, b) = (terms[x].1, terms[y].1);
+                        candidates.push((Term::And(x, y), a & b));
+                        candidates.push((Term::Or(x, y), a | b));
+                    }
+                }
+            }
+
+            for (t, tt) in candidates {
+                if let Some(&r) = index.get(&tt) {
+                    on_equal(&terms, t, r);
+                } else {
+                    index.insert(tt, terms.len());
+                    levels[s].push(terms.len());
+                    terms.push((t, tt));
+                }
+            }
+        }
+
+        Self { terms }
+    }
+
+    /// Returns the smallest term with truth table `tt`
+    fn find(&self, tt: u64) -> Option<usize> {
+        self.terms.iter().position(|(_, t)| *t == tt)
+    }
+}
+
+/// Print `t` as a pattern, naming variables in the order they are first seen in `names`
+fn render(terms: &[(Term, u64)], t: Term, names: &mut Vec<usize>) -> String {
+    match t {
+        Term::Var(v) => {
+            let i = names.iter().position(|n| *n == v).unwrap_or_else(|| {
+                names.push(v);
+                names.len() - 1
+            });
+            format!("?{}", VAR_NAMES[i])
+        }
+        Term::Const(b) => b.to_string(),
+        Term::Inv(x) => format!("(INV {})", render(terms, terms[x].0, names)),
+        Term::And(x, y) => format!(
+            "(AND {} {})",
+            render(terms, terms[x].0, names),
+            render(terms, terms[y].0, names)
+        ),
+        Term::Or(x, y) => format!(
+            "(OR {} {})",
+            render(terms, terms[x].0, names),
+            render(terms, terms[y].0, names)
+        ),
+    }
+}
+
+/// Simulate a pattern of AND/OR/INV, with variable `?a` on [VAR_PATTERNS]`[0]` and so on
+fn simulate_pattern(pattern: &str) -> Result<u64, String> {
+    let expr: RecExpr<CellLang> = pattern
+        .replace('?', "")
+        .parse()
+        .map_err(|e| format!("{e:?}"))?;
+    let mut sims: Vec<u64> = Vec::with_capacity(expr.as_ref().len());
+    for n in expr.as_ref() {
+        let sim = match n.get_var() {
+            Some(v) => VAR_NAMES
+                .iter()
+                .position(|name| *name == v.as_str())
+                .map(|i| VAR_PATTERNS[i]),
+            None => {
+                let c: Vec<u64> = n.children().iter().map(|c| sims[usize::from(*c)]).collect();
+                n.simulate(&c)
+            }
+        };
+        sims.push(sim.ok_or_else(|| format!("Cannot simulate {pattern}"))?);
+    }
+    Ok(*sims.last().unwrap())
+}
+
+/// The variables of a pattern
+fn pattern_vars(pattern: &str) -> HashSet<&str> {
+    pattern
+        .split(|c: char| c.is_whitespace() || c == '(' || c == ')')
+        .filter(|s| s.starts_with('?'))
+        .collect()
+}
+
+/// Enumerates small [CellLang] terms to find rewrite rules for a cell library
+pub struct RuleSynthesizer {
+    cells: Vec<CellSpec>,
+    cell_size: usize,
+    term_vars: usize,
+    term_size: usize,
+    iter_limit: usize,
+    node_limit: usize,
+}
+
+impl RuleSynthesizer {
+    /// Create a synthesizer for the cells in `cells`
+    pub fn new(cells: Vec<CellSpec>) -> Self {
+        Self {
+            cells,
+            cell_size: 6,
+            term_vars: 3,
+            term_size: 3,
+            iter_limit: 3,
+            node_limit: 10_000,
+        }
+    }
+
+    /// Search for cell implementations with up to `size` operators
+    pub fn with_cell_size(self, size: usize) -> Self {
+        Self {
+            cell_size: size,
+            ..self
+        }
+    }
+
+    /// Search for algebraic rules over `vars` variables with up to `size` operators per term
+    pub fn with_term_size(self, vars: usize, size: usize) -> Self {
+        Self {
+            term_vars: vars.min(VAR_NAMES.len()),
+            term_size: size,
+            ..self
+        }
+    }
+
+    /// Limit the e-graph used to discard rules that can already be derived
+    pub fn with_derivation_limits(self, iter_limit: usize, node_limit: usize) -> Self {
+        Self {
+            iter_limit,
+            node_limit,
+            ..self
+        }
+    }
+
+    /// Returns true if `lhs` and `rhs` are proven equal with `rules`
+    fn derivable(
+        &self,
+        lhs: &str,
+        rhs: &str,
+        rules: &[Rewrite<CellLang, CellAnalysis>],
+    ) -> Result<bool, String> {
+        let lhs: RecExpr<CellLang> = lhs.replace('?', "").parse().map_err(|e| format!("{e:?}"))?;
+        let rhs: RecExpr<CellLang> = rhs.replace('?', "").parse().map_err(|e| format!("{e:?}"))?;
+        let runner = Runner::<CellLang, CellAnalysis>::default()
+            .with_expr(&lhs)
+            .with_expr(&rhs)
+            .with_iter_limit(self.iter_limit)
+            .with_node_limit(self.node_limit)
+            .run(rules);
+        Ok(runner.egraph.find(runner.roots[0]) == runner.egraph.find(runner.roots[1]))
+    }
+
+    /// Find the algebraic rules between terms, smallest first
+    pub fn algebraic_rules(&self) -> Result<Vec<SynthRule>, String> {
+        let mut candidates: Vec<(String, String)> = Vec::new();
+        let mut seen: HashSet<(String, String)> = HashSet::new();
+        Enumeration::new(self.term_vars, self.term_size, true, true, |terms, t, r| {
+            let mut names = Vec::new();
+            let lhs = render(terms, t, &mut names);
+            let rhs = render(terms, terms[r].0, &mut names);
+            if lhs != rhs && seen.insert((lhs.clone(), rhs.clone())) {
+                candidates.push((lhs, rhs));
+            }
+        });
+
+        // Try the smallest candidates first, so that larger ones can be derived from them
+        let size = |p: &str| p.matches('(').count();
+        candidates.sort_by_key(|(lhs, rhs)| (size(lhs), size(rhs)));
+
+        let mut rules: Vec<SynthRule> = Vec::new();
+        let mut rewrites: Vec<Rewrite<CellLang, CellAnalysis>> = Vec::new();
+        for (lhs, rhs) in candidates {
+            let (lv, rv) = (pattern_vars(&lhs), pattern_vars(&rhs));
+            if !rv.is_subset(&lv) || self.derivable(&lhs, &rhs, &rewrites)? {
+                continue;
+            }
+            let rule = SynthRule {
+                name: format!("synth-{}", rules.len()),
+                // A bare variable on the rhs would match every e-class in reverse
+                bidirectional: lv == rv && !rhs.starts_with('?'),
+                lhs,
+                rhs,
+            };
+            rewrites.append(&mut rule.rewrites()?);
+            rules.push(rule);
+        }
+        Ok(rules)
+    }
+
+    /// Find the smallest term implementing each cell.
+    /// Returns the rules and the cells that had none.
+    pub fn cell_rules(&self) -> (Vec<SynthRule>, Vec<String>) {
+        let (matched, unmatched) = self.match_cells();
+        (matched.into_iter().map(|(_, r)| r).collect(), unmatched)
+    }
+
+    /// Find the smallest term implementing each cell, paired with the cell it implements.
+    /// Returns the rules and the cells that had none.
+    fn match_cells(&self) -> (Vec<(&CellSpec, SynthRule)>, Vec<String>) {
+        let vars = self.cells.iter().map(|c| c.inputs).max().unwrap_or(0);
+        let enumeration = Enumeration::new(vars, self.cell_size, false, false, |_, _, _| ());
+
+        let mut rules = Vec::new();
+        let mut unmatched = Vec::new();
+        let mut taken: HashSet<String> = HashSet::new();
+        for cell in &self.cells {
+            let Some(t) = enumeration.find(cell.truth_table()) else {
+                unmatched.push(cell.name.clone());
+                continue;
+            };
+
+            // Name the variables by cell input, not by order of appearance
+            let mut names: Vec<usize> = (0..cell.inputs).collect();
+            let lhs = render(&enumeration.terms, enumeration.terms[t].0, &mut names);
+            if names.len() != cell.inputs || pattern_vars(&lhs).len() != cell.inputs {
+                unmatched.push(cell.name.clone());
+                continue;
+            }
+            let operands: Vec<String> = VAR_NAMES[..cell.inputs]
+                .iter()
+                .map(|v| format!("?{v}"))
+                .collect();
+
+            // Cell names may differ only in case, so number the repeats
+            let base = cell.name.to_lowercase();
+            let name = (1..)
+                .map(|i| match i {
+                    1 => base.clone(),
+                    _ => format!("{base}-{i}"),
+                })
+                .find(|n| !taken.contains(n))
+                .unwrap();
+            taken.insert(name.clone());
+            rules.push((
+                cell,
+                SynthRule {
+                    name,
+                    lhs,
+                    rhs: format!("({} {})", cell.name, operands.join(" ")),
+                    bidirectional: true,
+                },
+            ));
+        }
+        (rules, unmatched)
+    }
+
+    /// Check that every rule is sound and that the rules load into a [RewriteManager]
+    fn validate(
+        algebraic: &[SynthRule],
+        cells: &[(&CellSpec, SynthRule)],
+    ) -> Result<String, String> {
+        for rule in algebraic {
+            if simulate_pattern(&rule.lhs)? != simulate_pattern(&rule.rhs)? {
+                return Err(format!("Unsound rule {rule}"));
+            }
+        }
+        for (cell, rule) in cells {
+            if simulate_pattern(&rule.lhs)? != cell.truth_table() {
+                return Err(format!("Unsound rule {rule}"));
+            }
+        }
+        let cells: Vec<SynthRule> = cells.iter().map(|(_, r)| r.clone()).collect();
+
+        let mut file = String::new();
+        for (category, rules) in [("algebraic", algebraic), ("cells", cells.as_slice())] {
+            if rules.is_empty() {
+                continue;
+            }
+            file.push_str(&format!("{category}:\n"));
+            for rule in rules {
+                file.push_str(&format!("    {rule}\n"));
+            }
+        }
+
+        let mut manager = RewriteManager::<CellLang, CellAnalysis>::new();
+        manager.parse_rules(file.as_bytes())?;
+        Ok(file)
+    }
+
+    /// Synthesize a rule file with the categories `algebraic` and `cells`.
+    /// Returns the contents of the file and the cells that could not be implemented.
+    pub fn synthesize(&self) -> Result<(String, Vec<String>), String> {
+        let algebraic = self.algebraic_rules()?;
+        let (cells, unmatched) = self.match_cells();
+        let file = Self::validate(&algebraic, &cells)?;
+        Ok((file, unmatched))
+    }
+}
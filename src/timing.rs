@@ -0,0 +1,276 @@
+/*!
+
+  Static timing for LUT and cell networks. A [TimingModel] reads the arrival times of inputs,
+  the required times of outputs, and the (optionally pin-dependent) delays of primitives
+  from a constraints file. [TimingCostFn] extracts the circuit with the best worst slack.
+
+*/
+use super::driver::CircuitLang;
+use super::verilog::{PrimitiveType, VerilogEmission};
+use egg::{CostFunction, Id, RecExpr};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Read};
+use std::str::FromStr;
+
+/// The delay through a primitive, from any input pin or from specific pins
+#[derive(Debug, Clone)]
+struct PrimitiveDelay {
+    delay: Option<f32>,
+    pins: HashMap<String, f32>,
+}
+
+/// Arrival times, required times, and primitive delays used to time a circuit
+#[derive(Debug, Clone)]
+pub struct TimingModel {
+    arrivals: HashMap<String, f32>,
+    default_arrival: f32,
+    required: HashMap<String, f32>,
+    default_required: f32,
+    delays: HashMap<String, PrimitiveDelay>,
+    default_delay: f32,
+    outputs: Vec<String>,
+}
+
+impl Default for TimingModel {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimingModel {
+    /// Create a model where every primitive has unit delay, so arrival times count levels.
+    /// Inputs arrive at time 0 and outputs are required at time 0.
+    pub fn new() -> Self {
+        Self {
+            arrivals: HashMap::new(),
+            default_arrival: 0.0,
+            required: HashMap::new(),
+            default_required: 0.0,
+            delays: HashMap::new(),
+            default_delay: 1.0,
+            outputs: Vec::new(),
+        }
+    }
+
+    /// Parse a constraints file with one constraint per line:
+    /// `arrival <input> <time>`, `required <output> <time>`, `delay <primitive> [pin] <time>`.
+    /// A `*` in place of a name sets the default. Lines starting with `#` or `//` are comments.
+    pub fn parse(file: impl Read) -> Result<Self, String> {
+        let mut model = Self::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("Constraints reader: {:?}", e))?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') || line.starts_with("//") {
+                continue;
+            }
+
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let time = fields
+                .last()
+                .unwrap()
+                .parse::<f32>()
+                .map_err(|e| format!("Constraint {line}: {e}"))?;
+            model = match fields[..] {
+                ["arrival", "*", _] => Self {
+                    default_arrival: time,
+                    ..model
+                },
+                ["arrival", input, _] => model.with_arrival(input, time),
+                ["required", "*", _] => Self {
+                    default_required: time,
+                    ..model
+                },
+                ["required", output, _] => model.with_required(output, time),
+                ["delay", "*", _] => Self {
+                    default_delay: time,
+                    ..model
+                },
+                ["delay", prim, _] => {
+                    model.with_delay(PrimitiveType::from_str(prim)?, None, time)?
+                }
+                ["delay", prim, pin, _] => {
+                    model.with_delay(PrimitiveType::from_str(prim)?, Some(pin), time)?
+                }
+                _ => return Err(format!("Constraint misformatted: {line}")),
+            };
+        }
+        Ok(model)
+    }
+
+    /// Set the arrival time of `input` to `time`
+    pub fn with_arrival(mut self, input: &str, time: f32) -> Self {
+        self.arrivals.insert(input.to_string(), time);
+        self
+    }
+
+    /// Set the required time of `output` to `time`
+    pub fn with_required(mut self, output: &str, time: f32) -> Self {
+        self.required.insert(output.to_string(), time);
+        self
+    }
+
+    /// Set the delay of `prim` to `time`, only from input `pin` if provided
+    pub fn with_delay(
+        mut self,
+        prim: PrimitiveType,
+        pin: Option<&str>,
+        time: f32,
+    ) -> Result<Self, String> {
+        let delay = self
+            .delays
+            .entry(prim.to_string())
+            .or_insert_with(|| PrimitiveDelay {
+                delay: None,
+                pins: HashMap::new(),
+            });
+        match pin {
+            Some(pin) if prim.get_input_list().iter().any(|p| p == pin) => {
+                delay.pins.insert(pin.to_string(), time);
+            }
+            Some(pin) => return Err(format!("Primitive {prim} has no input pin {pin}")),
+            None => delay.delay = Some(time),
+        }
+        Ok(self)
+    }
+
+    /// Name the outputs of the circuit in order. Unnamed outputs are named by their index.
+    pub fn with_output_names(self, outputs: Vec<String>) -> Self {
+        Self { outputs, ..self }
+    }
+
+    /// Returns the name of output `i`
+    fn output_name(&self, i: usize) -> String {
+        self.outputs
+            .get(i)
+            .cloned()
+            .unwrap_or_else(|| i.to_string())
+    }
+
+    /// Returns the arrival time of `input`
+    pub fn get_arrival(&self, input: &str) -> f32 {
+        self.arrivals
+            .get(input)
+            .copied()
+            .unwrap_or(self.default_arrival)
+    }
+
+    /// Returns the required time of output `i`
+    pub fn get_required(&self, i: usize) -> f32 {
+        self.required
+            .get(&self.output_name(i))
+            .copied()
+            .unwrap_or(self.default_required)
+    }
+
+    /// Returns the delay through `prim`, from input `pin` if provided
+    pub fn get_delay(&self, prim: PrimitiveType, pin: Option<&str>) -> f32 {
+        let delay = self.delays.get(&prim.to_string());
+        let pin_delay = delay.zip(pin).and_then(|(d, p)| d.pins.get(p).copied());
+        pin_delay
+            .or(delay.and_then(|d| d.delay))
+            .unwrap_or(self.default_delay)
+    }
+
+    /// Returns the arrival time at the output of `enode`, or the negated worst slack if it is a bus of outputs
+    fn arrival<L>(&self, enode: &L, mut arrival: impl FnMut(Id) -> f32) -> f32
+    where
+        L: CircuitLang + VerilogEmission,
+    {
+        if let Some(v) = CircuitLang::get_var(enode) {
+            return self.get_arrival(v.as_str());
+        }
+
+        if enode.is_bus() {
+            return enode
+                .children()
+                .iter()
+                .enumerate()
+                .map(|(i, c)| arrival(*c) - self.get_required(i))
+                .fold(f32::MIN, f32::max);
+        }
+
+        match enode.get_gate_type() {
+            // Registers launch new paths
+            Some(prim) if prim.is_reg() => self.get_delay(prim, None),
+            Some(prim) => {
+                let pins = prim.get_input_list();
+                // Parameters like LUT programs come before the operands
+                let operands = &enode.children()[enode.len().saturating_sub(pins.len())..];
+                operands
+                    .iter()
+                    .zip(pins.iter())
+                    .map(|(c, pin)| arrival(*c) + self.get_delay(prim, Some(pin)))
+                    .fold(0.0, f32::max)
+            }
+            None if enode.is_leaf() => 0.0,
+            None => enode.fold(0.0, |t: f32, c| t.max(arrival(c))) + self.default_delay,
+        }
+    }
+
+    /// Returns the slack of every output of `expr`
+    pub fn slack<L: CircuitLang>(&self, expr: &RecExpr<L>) -> BTreeMap<String, f32> {
+        let mut cost_fn = L::timing_cost_fn(usize::MAX, self.clone());
+        let mut arrivals: Vec<f32> = Vec::with_capacity(expr.len());
+        for node in expr.iter() {
+            let t = cost_fn.cost(node, |c| arrivals[usize::from(c)]);
+            arrivals.push(t);
+        }
+
+        let outputs = match expr.last() {
+            Some(n) if n.is_bus() => n.children().to_vec(),
+            Some(_) => vec![Id::from(expr.len() - 1)],
+            None => vec![],
+        };
+        outputs
+            .into_iter()
+            .enumerate()
+            .map(|(i, o)| {
+                (
+                    self.output_name(i),
+                    self.get_required(i) - arrivals[usize::from(o)],
+                )
+            })
+            .collect()
+    }
+}
+
+/// A cost function that extracts the circuit with the best worst slack.
+/// The cost of a node is its arrival time, and a bus of outputs costs its negated worst slack.
+pub struct TimingCostFn {
+    model: TimingModel,
+    cut_size: Option<usize>,
+}
+
+impl TimingCostFn {
+    /// Create a new cost function that times circuits with `model`
+    pub fn new(model: TimingModel) -> Self {
+        Self {
+            model,
+            cut_size: None,
+        }
+    }
+
+    /// Never extract cells with more than `cut_size` inputs
+    pub fn with_cut_size(self, cut_size: usize) -> Self {
+        Self {
+            cut_size: Some(cut_size),
+            ..self
+        }
+    }
+}
+
+impl<L> CostFunction<L> for TimingCostFn
+where
+    L: CircuitLang + VerilogEmission,
+{
+    type Cost = f32;
+    fn cost<C>(&mut self, enode: &L, costs: C) -> Self::Cost
+    where
+        C: FnMut(Id) -> Self::Cost,
+    {
+        match self.cut_size {
+            Some(k) if enode.exceeds_cut_size(k) => f32::INFINITY,
+            _ => self.model.arrival(enode, costs),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// RUN: eqmap_fpga %s --assert-sat -k 4 --max-fanout 2 | FileCheck %s
+
+// The shared 4-LUT drives three loads, so it is duplicated
+module fanout_test (
+    a,
+    b,
+    c,
+    d,
+    e,
+    f,
+    g,
+    y0,
+    y1,
+    y2
+);
+  wire s;
+  input a;
+  wire a;
+  input b;
+  wire b;
+  input c;
+  wire c;
+  input d;
+  wire d;
+  input e;
+  wire e;
+  input f;
+  wire f;
+  input g;
+  wire g;
+  output y0;
+  wire y0;
+  output y1;
+  wire y1;
+  output y2;
+  wire y2;
+  LUT4 #(
+      .INIT(16'h6996)
+  ) _0_ (
+      .I3(a),
+      .I2(b),
+      .I1(c),
+      .I0(d),
+      .O(s)
+  );
+  AND _1_ (
+      .A(s),
+      .B(e),
+      .Y(y0)
+  );
+  AND _2_ (
+      .A(s),
+      .B(f),
+      .Y(y1)
+  );
+  AND _3_ (
+      .A(s),
+      .B(g),
+      .Y(y2)
+  );
+
+endmodule
+
+// CHECK:   LUT4 #(
+// CHECK:     .INIT(16'h6996)
+// CHECK:   LUT4 #(
+// CHECK:     .INIT(16'h6996)
+// CHECK-NOT: LUT4
+// CHECK: endmodule
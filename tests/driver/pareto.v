@@ -0,0 +1,85 @@
+// RUN: eqmap_fpga %s --assert-sat -n 40 -k 4 --pareto --pareto-point 0 | FileCheck %s
+
+// The first point of the front trades an extra LUT for one less level
+module gate_test (
+    a,
+    b,
+    c,
+    d,
+    e,
+    f,
+    g,
+    y
+);
+  wire _00_;
+  wire _01_;
+  wire _02_;
+  wire _03_;
+  wire _04_;
+  input a;
+  wire a;
+  input b;
+  wire b;
+  input c;
+  wire c;
+  input d;
+  wire d;
+  input e;
+  wire e;
+  input f;
+  wire f;
+  input g;
+  wire g;
+  wire tmp0;
+  output y;
+  wire y;
+  AND _05_ (
+      .A(d),
+      .B(e),
+      .Y(_00_)
+  );
+  NOT _06_ (
+      .A(b),
+      .Y(_01_)
+  );
+  NOT _07_ (
+      .A(_02_),
+      .Y(_03_)
+  );
+  NOR _08_ (
+      .A(a),
+      .B(g),
+      .Y(_02_)
+  );
+  MUX _09_ (
+      .A(_00_),
+      .B(_01_),
+      .S(_03_),
+      .Y(tmp0)
+  );
+  XOR _10_ (
+      .A(c),
+      .B(f),
+      .Y(_04_)
+  );
+  XOR _11_ (
+      .A(_04_),
+      .B(tmp0),
+      .Y(y)
+  );
+
+endmodule
+
+// CHECK: module gate_test (
+// CHECK:   LUT2 #(
+// CHECK:   LUT2 #(
+// CHECK:   LUT2 #(
+// CHECK:   LUT4 #(
+// CHECK:     .INIT(16'ha35c)
+// CHECK:     .I3(__0__),
+// CHECK:     .I2(__1__),
+// CHECK:     .I1(__2__),
+// CHECK:     .I0(b),
+// CHECK:     .O(__3__)
+// CHECK:   assign y = __3__;
+// CHECK: endmodule